@@ -0,0 +1,8 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    bgworker_launch_pg_create_stmt,
+    bgworker_insert_value_pg_create_stmt
+);
@@ -0,0 +1,113 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::convert::TryInto;
+use std::time::Duration;
+
+use pg_extend::bgworker::{self, BackgroundWorker, BackgroundWorkerBuilder, WorkerStatus};
+use pg_extend::spi::Spi;
+use pg_extend::{log, pg_magic, pg_sys};
+use pg_extern_attr::{pg_bgworker, pg_extern};
+
+// This tells Postgres this library is a Postgres extension, and to register the worker once it is loaded
+pg_magic!(version: pg_sys::PG_VERSION_NUM, init: register_worker);
+
+/// The number of ticks of a worker launched with `bgworker_launch`
+const LAUNCHED_TICKS: u32 = 5;
+
+/// Registers the ticker to run for the life of the server, if the library is in `shared_preload_libraries`
+fn register_worker() {
+    if bgworker::loading_shared_preload_libraries() {
+        BackgroundWorkerBuilder::new("bgworker ticker", "libbgworker", "pg_bgworker_ticker")
+            .restart_time(Some(Duration::from_secs(10)))
+            .register();
+    }
+}
+
+/// Logs a tick every second until the server shuts down
+#[pg_bgworker]
+fn bgworker_ticker(worker: &BackgroundWorker) {
+    let mut ticks = 0_u64;
+
+    while worker.wait(Duration::from_secs(1)) {
+        ticks += 1;
+        log!("bgworker ticker: tick {}", ticks);
+    }
+}
+
+/// Logs a few ticks and exits
+#[pg_bgworker]
+fn bgworker_launched(worker: &BackgroundWorker) {
+    for tick in 1..=LAUNCHED_TICKS {
+        if !worker.wait(Duration::from_millis(100)) {
+            break;
+        }
+
+        log!("bgworker launched: tick {}", tick);
+    }
+}
+
+/// Connects to the database passed in `bgw_extra`, and inserts the value passed as the `bgw_main_arg` into
+///  `bgworker_results`
+#[pg_bgworker]
+fn bgworker_insert(worker: &BackgroundWorker) {
+    let database = u32::from_ne_bytes(worker.extra()[..4].try_into().expect("extra is too short"));
+    worker.connect_by_oid(database, None);
+
+    worker.transaction(|| {
+        let spi = Spi::connect().expect("failed to connect to SPI");
+        spi.execute(
+            "INSERT INTO bgworker_results (value) VALUES ($1)",
+            &[(worker.main_arg() as i64).into()],
+        )
+        .expect("failed to insert value");
+    });
+}
+
+/// Returns the path of this library, as it was given to `CREATE FUNCTION`
+fn library_path() -> String {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one(
+            "SELECT probin FROM pg_proc WHERE proname = 'bgworker_launch'",
+            &[],
+        )
+        .expect("failed to select library")
+        .expect("bgworker_launch is not a function");
+
+    row.get("probin").expect("probin was not text")
+}
+
+/// Launches a worker which logs a few ticks, returning false if there was no free worker slot
+#[pg_extern]
+fn bgworker_launch() -> bool {
+    BackgroundWorkerBuilder::new("bgworker launched", &library_path(), "pg_bgworker_launched")
+        .register_dynamic()
+        .is_ok()
+}
+
+/// Launches a worker in this database which inserts the value into `bgworker_results`, and waits for it to exit
+#[pg_extern]
+fn bgworker_insert_value(value: i64) -> String {
+    let database = unsafe { pg_sys::MyDatabaseId };
+    let handle =
+        BackgroundWorkerBuilder::new("bgworker insert", &library_path(), "pg_bgworker_insert")
+            .database_connection()
+            .main_arg(value as pg_sys::Datum)
+            .extra(&database.to_ne_bytes())
+            .register_dynamic()
+            .expect("failed to register worker");
+
+    if let WorkerStatus::Started(pid) = handle.wait_for_startup() {
+        log!("bgworker insert started with pid {}", pid);
+    }
+
+    format!("{:?}", handle.wait_for_shutdown())
+}
@@ -0,0 +1,5 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(dsm_sum_squares_pg_create_stmt);
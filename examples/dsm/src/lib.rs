@@ -0,0 +1,79 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::convert::TryInto;
+
+use pg_extend::bgworker::{BackgroundWorker, BackgroundWorkerBuilder};
+use pg_extend::dsm::DsmSegment;
+use pg_extend::shm_mq::{self, ShmMqReceiver, ShmMqSender};
+use pg_extend::spi::Spi;
+use pg_extend::{pg_magic, pg_sys};
+use pg_extern_attr::{pg_bgworker, pg_extern};
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// The size of the segment of the queue
+const QUEUE_SIZE: usize = 16 * 1024;
+
+/// Attaches to the segment passed as the `bgw_main_arg`, and sends the squares up to the number passed in
+///  `bgw_extra` to its queue
+#[pg_bgworker]
+fn dsm_squares_worker(worker: &BackgroundWorker) {
+    let count = i32::from_ne_bytes(worker.extra()[..4].try_into().expect("extra is too short"));
+    let segment = DsmSegment::attach(worker.main_arg() as pg_sys::dsm_handle)
+        .expect("the segment was destroyed");
+    let sender = ShmMqSender::attach(&segment, None);
+
+    for i in 1..=i64::from(count) {
+        // the receiver has detached, so there is no one to send the rest to
+        if sender.send(&(i * i).to_ne_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+/// Returns the path of this library, as it was given to `CREATE FUNCTION`
+fn library_path() -> String {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one(
+            "SELECT probin FROM pg_proc WHERE proname = 'dsm_sum_squares'",
+            &[],
+        )
+        .expect("failed to select library")
+        .expect("dsm_sum_squares is not a function");
+
+    row.get("probin").expect("probin was not text")
+}
+
+/// Launches a worker which sends the squares up to the count through a queue, and returns their sum
+#[pg_extern]
+fn dsm_sum_squares(count: i32) -> i64 {
+    let segment = DsmSegment::create(QUEUE_SIZE);
+    shm_mq::create(&segment);
+
+    let worker =
+        BackgroundWorkerBuilder::new("dsm squares", &library_path(), "pg_dsm_squares_worker")
+            .shared_memory_access()
+            .main_arg(segment.handle() as pg_sys::Datum)
+            .extra(&count.to_ne_bytes())
+            .register_dynamic()
+            .expect("failed to register worker");
+
+    // the worker detaches once it has sent all the squares
+    let receiver = ShmMqReceiver::attach(&segment, Some(&worker));
+    let mut sum = 0;
+    while let Ok(message) = receiver.receive() {
+        sum += i64::from_ne_bytes(message[..].try_into().expect("message is not an i64"));
+    }
+
+    sum
+}
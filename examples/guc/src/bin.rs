@@ -0,0 +1,10 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    guc_batch_size_pg_create_stmt,
+    guc_greeting_pg_create_stmt,
+    guc_format_pg_create_stmt,
+    guc_assigned_pg_create_stmt
+);
@@ -0,0 +1,94 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::ffi::CString;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use pg_extend::guc::{self, Guc, GucEnum, GucSetting};
+use pg_extend::pg_magic;
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension, and to define the GUCs once it is loaded
+pg_magic!(version: pg_sys::PG_VERSION_NUM, init: define_gucs);
+
+/// The format of the greeting
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Plain,
+    Shout,
+}
+
+impl GucEnum for Format {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("plain", Format::Plain),
+        ("shout", Format::Shout),
+        ("loud", Format::Shout),
+    ];
+}
+
+static BATCH_SIZE: GucSetting<i32> = GucSetting::new(100);
+static GREETING: GucSetting<Option<CString>> = GucSetting::new(Some("hello"));
+static FORMAT: GucSetting<Format> = GucSetting::new(Format::Plain);
+
+/// The number of times `guc.format` has been assigned
+static FORMAT_ASSIGNED: AtomicI64 = AtomicI64::new(0);
+
+/// Defines the GUCs of the library, called from `_PG_init`
+fn define_gucs() {
+    Guc::new("guc.batch_size", "The number of rows written at a time")
+        .range(1, 10_000)
+        .define(&BATCH_SIZE);
+
+    Guc::new("guc.greeting", "The greeting returned by guc_greeting()")
+        .check_hook(|greeting: &Option<CString>| match greeting {
+            Some(greeting) if greeting.as_bytes().is_empty() => {
+                Err("the greeting must not be empty".to_string())
+            }
+            _ => Ok(()),
+        })
+        .define(&GREETING);
+
+    Guc::new("guc.format", "The format of the greeting")
+        .assign_hook(|_: &Format| {
+            FORMAT_ASSIGNED.fetch_add(1, Ordering::SeqCst);
+        })
+        .define(&FORMAT);
+
+    guc::emit_warnings_on_placeholders("guc");
+}
+
+/// Returns the value of `guc.batch_size`
+#[pg_extern]
+fn guc_batch_size() -> i32 {
+    BATCH_SIZE.get()
+}
+
+/// Returns the value of `guc.greeting` in `guc.format`
+#[pg_extern]
+fn guc_greeting() -> Option<String> {
+    let greeting = GREETING.get()?.to_string_lossy().into_owned();
+
+    match FORMAT.get() {
+        Format::Plain => Some(greeting),
+        Format::Shout => Some(greeting.to_uppercase()),
+    }
+}
+
+/// Returns the name of the value of `guc.format`
+#[pg_extern]
+fn guc_format() -> String {
+    format!("{:?}", FORMAT.get()).to_lowercase()
+}
+
+/// Returns the number of times `guc.format` has been assigned
+#[pg_extern]
+fn guc_assigned() -> i64 {
+    FORMAT_ASSIGNED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,11 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    hooks_planned_count_pg_create_stmt,
+    hooks_last_query_pg_create_stmt,
+    hooks_protect_tables_pg_create_stmt,
+    hooks_take_fingerprints_pg_create_stmt,
+    hooks_panic_pg_create_stmt
+);
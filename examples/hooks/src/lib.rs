@@ -0,0 +1,200 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::os::raw::c_int;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use pg_extend::hooks::{
+    self, ExecutorHooks, PrevExecutor, PrevProcessUtility, ProcessUtilityHook, QueryDesc,
+    QueryView, UtilityStmt,
+};
+use pg_extend::pg_error::PgError;
+use pg_extend::spi::Spi;
+use pg_extend::{pg_magic, pg_sys};
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension, and to install the hooks once it is loaded
+pg_magic!(version: pg_sys::PG_VERSION_NUM, init: install_hooks);
+
+/// The number of queries planned by this backend since the library was loaded
+static PLANNED: AtomicI64 = AtomicI64::new(0);
+
+/// The most fingerprints which are kept
+const MAX_FINGERPRINTS: usize = 16;
+
+/// The fingerprints of the queries analyzed since they were last taken
+static FINGERPRINTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Fingerprints the query by its type, the relations it reads, its columns and the tags of its nodes, so that
+///  queries which only differ in their constants have the same fingerprint
+fn fingerprint(query: QueryView) -> String {
+    let mut hasher = DefaultHasher::new();
+    query.walk(|node| {
+        node.type_.hash(&mut hasher);
+        false
+    });
+
+    let relations = query
+        .range_table()
+        .filter_map(|rte| rte.relation_oid())
+        .count();
+    let targets = query
+        .target_list()
+        .filter(|tle| !tle.is_junk())
+        .filter_map(|tle| tle.name())
+        .collect::<Vec<_>>();
+
+    format!(
+        "{:?} relations={} targets={} fingerprint={:016x}",
+        query.command_type(),
+        relations,
+        targets.join(","),
+        hasher.finish()
+    )
+}
+
+/// The description of the last query which ended
+static LAST_QUERY: Mutex<String> = Mutex::new(String::new());
+
+/// The executor hooks, which describe each query as it ends
+struct QueryLog;
+
+impl ExecutorHooks for QueryLog {
+    fn executor_start(&self, query_desc: &mut QueryDesc, eflags: c_int, prev: &PrevExecutor) {
+        prev.start(query_desc, eflags);
+        query_desc.track_totals();
+    }
+
+    fn executor_end(&self, query_desc: &mut QueryDesc, prev: &PrevExecutor) {
+        let tuples = query_desc.totals().map_or(0.0, |totals| totals.tuples);
+
+        *LAST_QUERY.lock().expect("last query poisoned") = format!(
+            "{:?} rows={} tuples={}: {}",
+            query_desc.operation(),
+            query_desc.processed(),
+            tuples,
+            query_desc.source_text()
+        );
+
+        prev.end(query_desc)
+    }
+}
+
+/// Whether dropping tables is blocked
+static PROTECT_TABLES: AtomicBool = AtomicBool::new(false);
+
+/// The utility hook, which blocks `DROP TABLE` while tables are protected
+struct ProtectTables;
+
+impl ProcessUtilityHook for ProtectTables {
+    fn process_utility(
+        &self,
+        stmt: &mut UtilityStmt,
+        prev: &PrevProcessUtility,
+    ) -> Result<(), PgError> {
+        if PROTECT_TABLES.load(Ordering::Relaxed) && stmt.node_tag() == pg_sys::NodeTag_T_DropStmt {
+            let drop_stmt = unsafe { &*(stmt.node() as *const pg_sys::DropStmt) };
+
+            if drop_stmt.removeType == pg_sys::ObjectType_OBJECT_TABLE {
+                return Err(PgError::new("dropping tables is not allowed")
+                    .with_hint("unprotect them with hooks_protect_tables(false)")
+                    .with_sqlstate("42501"));
+            }
+        }
+
+        prev.process(stmt)
+    }
+}
+
+/// Installs the hooks, called from `_PG_init`
+fn install_hooks() {
+    hooks::install_planner_hook(|query, cursor_options, bound_params, prev| {
+        PLANNED.fetch_add(1, Ordering::Relaxed);
+        prev.plan(query, cursor_options, bound_params)
+    });
+
+    hooks::install_executor_hooks(QueryLog);
+    hooks::install_process_utility_hook(ProtectTables);
+
+    hooks::install_post_parse_analyze_hook(|pstate, query, prev| {
+        prev.analyze(pstate, query);
+
+        let mut fingerprints = FINGERPRINTS.lock().expect("fingerprints poisoned");
+        if fingerprints.len() == MAX_FINGERPRINTS {
+            fingerprints.remove(0);
+        }
+
+        fingerprints.push(fingerprint(QueryView::new(query)));
+    });
+
+    // messages which may contain a password are redacted, in the server log and for the client
+    hooks::install_emit_log_hook(|edata| {
+        if edata
+            .message()
+            .map_or(false, |message| message.contains("password"))
+        {
+            edata.set_message("[redacted]");
+            edata.set_detail(None);
+        }
+    });
+}
+
+/// Returns the number of queries planned since the library was loaded, including this one
+#[pg_extern]
+fn hooks_planned_count() -> i64 {
+    PLANNED.load(Ordering::Relaxed)
+}
+
+/// Returns the description of the last query which ended, before this one
+#[pg_extern]
+fn hooks_last_query() -> String {
+    LAST_QUERY.lock().expect("last query poisoned").clone()
+}
+
+/// Sets whether dropping tables is blocked, returning whether it was
+#[pg_extern]
+fn hooks_protect_tables(protect: bool) -> bool {
+    PROTECT_TABLES.swap(protect, Ordering::Relaxed)
+}
+
+/// Returns the fingerprints of the queries analyzed since they were last taken, including this one, one per line
+#[pg_extern]
+fn hooks_take_fingerprints() -> String {
+    FINGERPRINTS
+        .lock()
+        .expect("fingerprints poisoned")
+        .drain(..)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Panics with the message, which is raised as an `ERROR`, e.g. to show that messages with passwords are redacted
+#[pg_extern]
+fn hooks_panic(message: String) -> bool {
+    panic!("{}", message)
+}
+
+/// Counts the rows of the query through an SPI cursor while the executor hooks are running, an `ERROR` raised
+///  while preparing the query or fetching the rows keeps its SQLSTATE
+#[pg_extern]
+fn hooks_spi_count(sql: String) -> i64 {
+    let spi = Spi::connect().expect("failed to connect to SPI");
+    let plan = spi
+        .prepare(&sql, &[])
+        .unwrap_or_else(|err| panic::panic_any(err));
+
+    spi.open_cursor(&plan, &[])
+        .unwrap_or_else(|err| panic::panic_any(err))
+        .count() as i64
+}
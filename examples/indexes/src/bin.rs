@@ -0,0 +1,9 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    has_words_pg_create_stmt,
+    int8_signature_pg_create_stmt,
+    words_pg_create_stmt
+);
@@ -0,0 +1,154 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::index::{
+    Consistent, GinQuery, GinSearchMode, GinSupport, GistSupport, PickSplit, StrategyNumber,
+};
+use pg_extend::pg_magic;
+use pg_extern_attr::{pg_extern, pg_gin, pg_gist};
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// The bit of the signature for a value
+fn signature(value: i64) -> i64 {
+    1_i64 << value.rem_euclid(64)
+}
+
+/// A GiST operator class for `int8` equality, the keys are signatures of all the values below them.
+///
+/// `CREATE INDEX ... USING gist (column int8_signature_ops)`
+#[pg_gist]
+struct Int8Signature;
+
+impl GistSupport for Int8Signature {
+    type Value = i64;
+    type Query = i64;
+    type Key = i64;
+
+    const OPERATORS: &'static [(StrategyNumber, &'static str)] = &[(3, "=")];
+
+    fn compress(value: i64) -> i64 {
+        signature(value)
+    }
+
+    fn consistent(key: &i64, query: &i64, _strategy: StrategyNumber, _is_leaf: bool) -> Consistent {
+        // the signatures are lossy, so even leaf values must be rechecked
+        if key & signature(*query) == 0 {
+            Consistent::No
+        } else {
+            Consistent::Maybe
+        }
+    }
+
+    fn union(keys: &[i64]) -> i64 {
+        keys.iter().fold(0, |union, key| union | key)
+    }
+
+    fn penalty(original: &i64, new: &i64) -> f32 {
+        // the number of bits added to the signature
+        (new & !original).count_ones() as f32
+    }
+
+    fn picksplit(keys: &[i64]) -> PickSplit {
+        let mut indexes = (0..keys.len()).collect::<Vec<_>>();
+        indexes.sort_by_key(|&index| keys[index] as u64);
+
+        let right = indexes.split_off(indexes.len() / 2);
+        PickSplit {
+            left: indexes,
+            right,
+        }
+    }
+
+    fn same(left: &i64, right: &i64) -> bool {
+        left == right
+    }
+}
+
+/// The distinct lowercase words of the text
+fn words(text: &str) -> Vec<String> {
+    let mut words = text
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// True if the text contains all the words of the query, `'The Quick fox' &@ 'quick the'`
+#[pg_extern]
+#[pg_operator(symbol = "&@")]
+fn has_words(text: String, query: String) -> bool {
+    let words = words(&text);
+    self::words(&query)
+        .iter()
+        .all(|word| words.binary_search(word).is_ok())
+}
+
+/// A GIN operator class for `&@`, the keys are the words of the text.
+///
+/// `CREATE INDEX ... USING gin (column words_ops)`
+#[pg_gin]
+struct Words;
+
+impl GinSupport for Words {
+    type Value = String;
+    type Query = String;
+    type Key = String;
+
+    const OPERATORS: &'static [(StrategyNumber, &'static str)] = &[(1, "&@")];
+
+    fn extract_value(value: String) -> Vec<String> {
+        words(&value)
+    }
+
+    fn extract_query(query: String, _strategy: StrategyNumber) -> GinQuery<String> {
+        let keys = words(&query);
+
+        // every text contains no words
+        let search_mode = if keys.is_empty() {
+            GinSearchMode::All
+        } else {
+            GinSearchMode::Default
+        };
+
+        GinQuery { keys, search_mode }
+    }
+
+    fn consistent(check: &[bool], _query: &String, _strategy: StrategyNumber) -> Consistent {
+        if check.iter().all(|&contains| contains) {
+            Consistent::Yes
+        } else {
+            Consistent::No
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_words() {
+        assert!(has_words("The Quick fox".to_string(), "quick the".to_string()));
+        assert!(!has_words("The Quick fox".to_string(), "slow fox".to_string()));
+    }
+
+    #[test]
+    fn test_signature_consistent() {
+        let key = Int8Signature::union(&[signature(1), signature(2)]);
+
+        assert_eq!(Int8Signature::consistent(&key, &2, 3, false), Consistent::Maybe);
+        assert_eq!(Int8Signature::consistent(&key, &3, 3, false), Consistent::No);
+    }
+}
@@ -0,0 +1,8 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    interrupts_sum_to_pg_create_stmt,
+    interrupts_sleep_pg_create_stmt
+);
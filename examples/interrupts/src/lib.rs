@@ -0,0 +1,64 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use pg_extend::interrupts::{self, InterruptsExt, LatchWait};
+use pg_extend::pg_magic;
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// Sums the numbers up to n, which can be cancelled, e.g. by `statement_timeout`
+#[pg_extern]
+fn interrupts_sum_to(n: i64) -> i64 {
+    (0..n).check_interrupts(10_000).fold(0, i64::wrapping_add)
+}
+
+/// Sleeps for the milliseconds, returning true if it was not woken early by the latch being set
+#[pg_extern]
+fn interrupts_sleep(millis: i64) -> bool {
+    LatchWait::new()
+        .timeout(Duration::from_millis(millis as u64))
+        .wait()
+        .timed_out()
+}
+
+/// The number of guards dropped, see `interrupts_wait_guarded`
+static GUARDS_DROPPED: AtomicI64 = AtomicI64::new(0);
+
+/// Counts its drop in `GUARDS_DROPPED`
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        GUARDS_DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Holds a guard while waiting to be cancelled, e.g. by `pg_cancel_backend`, the guard is dropped as the
+///  cancel unwinds the function
+#[pg_extern]
+fn interrupts_wait_guarded() -> bool {
+    let _guard = Guard;
+
+    loop {
+        interrupts::check();
+        LatchWait::new().timeout(Duration::from_millis(10)).wait();
+    }
+}
+
+/// Returns the number of guards dropped by `interrupts_wait_guarded`
+#[pg_extern]
+fn interrupts_guards_dropped() -> i64 {
+    GUARDS_DROPPED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,9 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    locks_try_advisory_pg_create_stmt,
+    locks_hold_advisory_pg_create_stmt,
+    locks_lock_relation_pg_create_stmt
+);
@@ -0,0 +1,37 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::lock::{self, AdvisoryLock, LockMode};
+use pg_extend::{pg_magic, pg_sys};
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// Returns true if the advisory lock on the key is not held by another session, it is released before returning
+#[pg_extern]
+fn locks_try_advisory(key: i64) -> bool {
+    AdvisoryLock::try_session(key).is_some()
+}
+
+/// Takes the advisory lock on the key for the rest of the session, which can be released with
+///  `pg_advisory_unlock`
+#[pg_extern]
+fn locks_hold_advisory(key: i64) -> bool {
+    AdvisoryLock::session(key).hold();
+    true
+}
+
+/// Locks the relation with the oid for the rest of the transaction, returning false if it is locked by another
+///  session
+#[pg_extern]
+fn locks_lock_relation(relation: i64) -> bool {
+    lock::try_lock_relation_oid(relation as pg_sys::Oid, LockMode::AccessExclusive)
+}
@@ -0,0 +1,5 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(notify_send_pg_create_stmt);
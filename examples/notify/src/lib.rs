@@ -0,0 +1,24 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::notify;
+use pg_extend::pg_magic;
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// Sends the payload on the channel when the transaction commits, returning the reason if it could not be sent
+#[pg_extern]
+fn notify_send(channel: String, payload: String) -> Option<String> {
+    notify::notify(&channel, &payload)
+        .err()
+        .map(|err| err.to_string())
+}
@@ -0,0 +1,12 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    distance_pg_create_stmt,
+    abs_eq_pg_create_stmt,
+    abs_ne_pg_create_stmt,
+    square_pg_create_stmt,
+    version_type_pg_create_stmt,
+    version_pg_create_stmt
+);
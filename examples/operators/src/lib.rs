@@ -0,0 +1,118 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::pg_alloc::PgAllocator;
+use pg_extend::pg_datum::{PgDatum, TryFromPgDatum};
+use pg_extend::pg_magic;
+use pg_extend::pg_type::{PgType, PgTypeInfo};
+use pg_extern_attr::{pg_extern, pg_opclass, pg_operator};
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// The distance between two numbers, `1.5 <-> 3.0`
+#[pg_extern]
+#[pg_operator(symbol = "<->", commutator = "<->")]
+fn distance(left: f64, right: f64) -> f64 {
+    (left - right).abs()
+}
+
+/// Equality ignoring the sign, `-1 |=| 1`
+#[pg_extern]
+#[pg_operator(
+    symbol = "|=|",
+    commutator = "|=|",
+    negator = "|<>|",
+    restrict = "eqsel",
+    join = "eqjoinsel"
+)]
+fn abs_eq(left: i32, right: i32) -> bool {
+    left.abs() == right.abs()
+}
+
+/// Inequality ignoring the sign, `-1 |<>| 2`
+#[pg_operator(
+    symbol = "|<>|",
+    commutator = "|<>|",
+    negator = "|=|",
+    restrict = "neqsel",
+    join = "neqjoinsel"
+)]
+#[pg_extern]
+fn abs_ne(left: i32, right: i32) -> bool {
+    left.abs() != right.abs()
+}
+
+/// A prefix operator, `^^ 3`
+#[pg_extern]
+#[pg_operator(symbol = "^^")]
+fn square(value: i32) -> i32 {
+    value * value
+}
+
+/// A version number which can be indexed with btree, hash and brin indexes
+///
+/// This is stored as an int8, but is its own Postgres type, see `version_type_pg_create_stmt`.
+#[pg_opclass(btree, hash, brin)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Version(i64);
+
+impl PgTypeInfo for Version {
+    fn pg_type() -> PgType {
+        PgType::Custom("version")
+    }
+}
+
+impl<'s> TryFromPgDatum<'s> for Version {
+    fn try_from<'mc>(
+        memory_context: &'mc PgAllocator,
+        datum: PgDatum<'mc>,
+    ) -> Result<Self, &'static str>
+    where
+        Self: 's,
+        'mc: 's,
+    {
+        i64::try_from(memory_context, datum).map(Version)
+    }
+}
+
+/// Creates the `version` type, reusing the input and output functions of int8
+pub fn version_type_pg_create_stmt(_library_path: &str) -> String {
+    "
+DO $$
+BEGIN
+    CREATE TYPE version;
+    CREATE FUNCTION version_in(cstring) RETURNS version AS 'int8in' LANGUAGE internal IMMUTABLE STRICT;
+    CREATE FUNCTION version_out(version) RETURNS cstring AS 'int8out' LANGUAGE internal IMMUTABLE STRICT;
+    CREATE TYPE version (INPUT = version_in, OUTPUT = version_out, INTERNALLENGTH = 8, PASSEDBYVALUE, ALIGNMENT = double);
+EXCEPTION WHEN duplicate_object THEN NULL;
+END
+$$;
+"
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abs_eq() {
+        assert!(abs_eq(-1, 1));
+        assert!(!abs_ne(-1, 1));
+    }
+
+    #[test]
+    fn test_version_cmp() {
+        assert_eq!(version_cmp(Version(2), Version(10)), -1);
+        assert!(version_lt(Version(2), Version(10)));
+        assert!(version_eq(Version(3), Version(3)));
+    }
+}
@@ -0,0 +1,9 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    shmem_count_pg_create_stmt,
+    shmem_add_pg_create_stmt,
+    shmem_average_pg_create_stmt
+);
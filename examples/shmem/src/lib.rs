@@ -0,0 +1,79 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::bgworker;
+use pg_extend::pg_magic;
+use pg_extend::shmem::{PgAtomicU64, PgLwLock, ShmemData};
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension, and to request the shared memory once it is loaded
+pg_magic!(version: pg_sys::PG_VERSION_NUM, init: request_shmem);
+
+/// The values added with `shmem_add`, by all backends
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    count: i64,
+    sum: i64,
+}
+
+unsafe impl ShmemData for Totals {}
+
+static TOTALS: PgLwLock<Totals> = PgLwLock::new("shmem.totals");
+static CALLS: PgAtomicU64 = PgAtomicU64::new("shmem.calls");
+
+/// Requests the shared memory, if the library is in `shared_preload_libraries`
+fn request_shmem() {
+    if bgworker::loading_shared_preload_libraries() {
+        TOTALS.request();
+        CALLS.request();
+    }
+}
+
+/// Counts the calls of this function in all backends, returning the count including this call, or `NULL` if the
+///  library is not in `shared_preload_libraries`
+#[pg_extern]
+fn shmem_count() -> Option<i64> {
+    if !CALLS.is_initialized() {
+        return None;
+    }
+
+    Some(CALLS.fetch_add(1) as i64 + 1)
+}
+
+/// Adds the value to the totals, returning the sum, or `NULL` if the library is not in `shared_preload_libraries`
+#[pg_extern]
+fn shmem_add(value: i64) -> Option<i64> {
+    if !TOTALS.is_initialized() {
+        return None;
+    }
+
+    let mut totals = TOTALS.exclusive();
+    totals.count += 1;
+    totals.sum += value;
+
+    Some(totals.sum)
+}
+
+/// Returns the average of the values added, or `NULL` if none have been or the library is not in
+///  `shared_preload_libraries`
+#[pg_extern]
+fn shmem_average() -> Option<f64> {
+    if !TOTALS.is_initialized() {
+        return None;
+    }
+
+    let totals = TOTALS.share();
+    if totals.count == 0 {
+        return None;
+    }
+
+    Some(totals.sum as f64 / totals.count as f64)
+}
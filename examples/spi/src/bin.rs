@@ -0,0 +1,15 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    spi_words_table_pg_create_stmt,
+    spi_add_pg_create_stmt,
+    spi_insert_word_pg_create_stmt,
+    spi_try_insert_word_pg_create_stmt,
+    spi_words_pg_create_stmt,
+    spi_count_words_pg_create_stmt,
+    spi_sum_series_pg_create_stmt,
+    spi_longest_word_pg_create_stmt,
+    spi_row_type_error_pg_create_stmt
+);
@@ -0,0 +1,180 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use pg_extend::pg_magic;
+use pg_extend::pg_type::PgType;
+use pg_extend::spi::{PlanCache, Spi};
+use pg_extern_attr::{pg_extern, PgRow};
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// Adds the numbers with a query, `SELECT $1 + $2`
+#[pg_extern]
+fn spi_add(a: i32, b: i32) -> i32 {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one("SELECT $1 + $2 AS sum", &[a.into(), b.into()])
+        .expect("failed to add")
+        .expect("no rows returned");
+
+    row.get("sum").expect("sum was not an int4")
+}
+
+/// Inserts the word into the `spi_words` table, returning the number of rows inserted
+#[pg_extern]
+fn spi_insert_word(word: String) -> i64 {
+    let spi = Spi::connect().expect("failed to connect to SPI");
+
+    spi.execute("INSERT INTO spi_words (word) VALUES ($1)", &[word.into()])
+        .expect("failed to insert word") as i64
+}
+
+/// Inserts the word into the `spi_words` table, if the insert fails it is rolled back and the error returned,
+///  e.g. `23502` for a NULL word, without aborting the transaction
+#[pg_extern]
+fn spi_try_insert_word(word: Option<String>) -> String {
+    let spi = Spi::connect().expect("failed to connect to SPI");
+
+    match spi.catch_errors(|| spi.execute("INSERT INTO spi_words (word) VALUES ($1)", &[word.into()])) {
+        Ok(count) => format!("inserted {}", count),
+        Err(err) => format!("{}: {}", err.sqlstate().unwrap_or("XX000"), err.message()),
+    }
+}
+
+/// Executes the statement, returning the number of rows processed or the error, e.g. `42601` for a syntax error
+#[pg_extern]
+fn spi_try_execute(sql: String) -> String {
+    let spi = Spi::connect().expect("failed to connect to SPI");
+
+    match spi.catch_errors(|| spi.execute(&sql, &[])) {
+        Ok(count) => format!("processed {}", count),
+        Err(err) => format!("{}: {}", err.sqlstate().unwrap_or("XX000"), err.message()),
+    }
+}
+
+/// Returns the words in `spi_words` at least as long as `min_length`, separated by spaces
+#[pg_extern]
+fn spi_words(min_length: i32) -> String {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let rows = spi
+        .select(
+            "SELECT word FROM spi_words WHERE length(word) >= $1 ORDER BY word",
+            &[min_length.into()],
+        )
+        .expect("failed to select words");
+
+    rows.iter()
+        .map(|row| row.get::<String>("word"))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("word was not text")
+        .join(" ")
+}
+
+/// Counts the words in `spi_words` at least as long as `min_length`, the query is prepared on the first call
+///  of each statement and reused after
+#[pg_extern]
+fn spi_count_words(plan_cache: &PlanCache, min_length: i32) -> i64 {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let plan = plan_cache
+        .get(
+            &spi,
+            "SELECT count(*) AS count FROM spi_words WHERE length(word) >= $1",
+            &[PgType::Int4],
+        )
+        .expect("failed to prepare count");
+
+    let rows = spi
+        .select_plan(plan, &[min_length.into()])
+        .expect("failed to count words");
+    let row = rows.first().expect("no rows returned");
+
+    row.get("count").expect("count was not an int8")
+}
+
+/// Sums the series from 1 to `n`, fetching the rows through a cursor 1000 at a time
+#[pg_extern]
+fn spi_sum_series(n: i64) -> i64 {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let plan = spi
+        .prepare("SELECT generate_series(1, $1) AS i", &[PgType::Int8])
+        .expect("failed to prepare series");
+
+    let cursor = spi
+        .open_cursor(&plan, &[n.into()])
+        .expect("failed to open cursor")
+        .batch_size(1000);
+
+    cursor
+        .map(|row| row.get::<i64>("i").expect("i was not an int8"))
+        .sum()
+}
+
+/// A word and its length, read by the names of the columns
+#[derive(PgRow)]
+struct WordLength {
+    word: String,
+    #[pg_row(name = "length")]
+    len: i32,
+}
+
+/// A word and its length, read by the positions of the columns, the length is mistakenly an `int8`
+#[derive(PgRow)]
+struct WordLengthByPosition(String, i64);
+
+/// Returns the longest word in `spi_words`, and its length, e.g. `ferris: 6`
+#[pg_extern]
+fn spi_longest_word() -> Option<String> {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one(
+            "SELECT word, length(word) AS length FROM spi_words ORDER BY length(word) DESC, word LIMIT 1",
+            &[],
+        )
+        .expect("failed to select longest word")?;
+
+    let word: WordLength = row.to_row().expect("failed to read word");
+    Some(format!("{}: {}", word.word, word.len))
+}
+
+/// Returns the error reading a row with a column of the wrong type
+#[pg_extern]
+fn spi_row_type_error() -> String {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one("SELECT 'ferris' AS word, length('ferris') AS length", &[])
+        .expect("failed to select word")
+        .expect("no rows returned");
+
+    match row.to_row::<WordLengthByPosition>() {
+        Ok(WordLengthByPosition(word, len)) => format!("{}: {}", word, len),
+        Err(err) => err.message().to_string(),
+    }
+}
+
+/// Returns the error reading a column as the wrong type, the length is an `int4`
+#[pg_extern]
+fn spi_get_type_error() -> String {
+    let spi = Spi::connect_read_only().expect("failed to connect to SPI");
+    let row = spi
+        .select_one("SELECT length('ferris') AS length", &[])
+        .expect("failed to select length")
+        .expect("no rows returned");
+
+    match row.get::<i64>("length") {
+        Ok(len) => len.to_string(),
+        Err(err) => err.message().to_string(),
+    }
+}
+
+/// The table used by the functions
+pub fn spi_words_table_pg_create_stmt(_library_path: &str) -> String {
+    "CREATE TABLE IF NOT EXISTS spi_words (word text NOT NULL);".to_string()
+}
@@ -0,0 +1,12 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    uppercase_name_pg_create_stmt,
+    check_positive_pg_create_stmt,
+    notice_change_pg_create_stmt,
+    sequence_names_pg_create_stmt,
+    protect_tables_pg_create_stmt,
+    triggers_spi_execute_pg_create_stmt
+);
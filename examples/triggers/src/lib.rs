@@ -0,0 +1,123 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::panic;
+
+use pg_extend::event_trigger::EventTrigger;
+use pg_extend::native::HeapTuple;
+use pg_extend::pg_error::PgError;
+use pg_extend::spi::Spi;
+use pg_extend::trigger::Trigger;
+use pg_extend::{notice, pg_magic};
+use pg_extern_attr::{pg_event_trigger, pg_extern, pg_trigger};
+
+// This tells Postgres this library is a Postgres extension
+pg_magic!(version: pg_sys::PG_VERSION_NUM);
+
+/// Uppercases the `name` column of the new row, a `BEFORE INSERT OR UPDATE` row level trigger
+#[pg_trigger]
+fn uppercase_name<'mc>(trigger: &Trigger<'mc>) -> Result<Option<HeapTuple<'mc>>, PgError> {
+    let new = trigger
+        .new()
+        .ok_or("uppercase_name must be a row level INSERT or UPDATE trigger")?;
+
+    let name: Option<String> = new.get("name")?;
+    match name {
+        Some(name) => new.set("name", name.to_uppercase()).map(Some),
+        None => Ok(Some(new)),
+    }
+}
+
+/// Rejects rows where the `int4` column, named by the trigger argument, is negative
+#[pg_trigger]
+fn check_positive<'mc>(trigger: &Trigger<'mc>) -> Result<Option<HeapTuple<'mc>>, PgError> {
+    let column = trigger
+        .args()
+        .into_iter()
+        .next()
+        .ok_or("check_positive requires the name of the column as an argument")?;
+    let new = trigger
+        .new()
+        .ok_or("check_positive must be a row level INSERT or UPDATE trigger")?;
+
+    let value: Option<i32> = new.get(&column)?;
+    if let Some(value) = value {
+        if value < 0 {
+            return Err(PgError::new(format!("{} must be positive", column))
+                .with_detail(format!("{} is {}", column, value))
+                .with_sqlstate("23514"));
+        }
+    }
+
+    Ok(Some(new))
+}
+
+/// Notifies the client of each change, an `AFTER` trigger of any level
+#[pg_trigger]
+fn notice_change<'mc>(trigger: &Trigger<'mc>) -> Result<Option<HeapTuple<'mc>>, PgError> {
+    notice!(
+        "{} {:?} {:?} {:?} on {}.{}",
+        trigger.name(),
+        trigger.when(),
+        trigger.event(),
+        trigger.level(),
+        trigger.table_schema(),
+        trigger.table_name(),
+    );
+
+    Ok(None)
+}
+
+/// Requires sequence names to end in `_seq`, an event trigger on `ddl_command_end`
+#[pg_event_trigger(event = "ddl_command_end", tags = "CREATE SEQUENCE, ALTER SEQUENCE")]
+fn sequence_names(event_trigger: &EventTrigger) -> Result<(), PgError> {
+    for command in event_trigger.ddl_commands()? {
+        if command.object_type == "sequence" && !command.object_identity.ends_with("_seq") {
+            return Err(PgError::new(format!(
+                "sequence {} must be named *_seq",
+                command.object_identity
+            ))
+            .with_hint(format!("{} was rejected by sequence_names", event_trigger.tag()))
+            .with_sqlstate("42602"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects dropping tables named `protected_*`, an event trigger on `sql_drop`
+#[pg_event_trigger(event = "sql_drop", tags = "DROP TABLE")]
+fn protect_tables(event_trigger: &EventTrigger) -> Result<(), PgError> {
+    for object in event_trigger.dropped_objects()? {
+        let name = object.object_name.as_deref().unwrap_or_default();
+
+        if object.object_type == "table" && name.starts_with("protected_") {
+            return Err(PgError::new(format!(
+                "table {} is protected",
+                object.object_identity
+            ))
+            .with_sqlstate("42501"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Executes the statement through SPI, returning the number of rows processed, e.g. an `INSERT` firing the
+///  triggers above from within a function. An error is raised with its SQLSTATE.
+#[pg_extern]
+fn triggers_spi_execute(sql: String) -> i64 {
+    let spi = Spi::connect().expect("failed to connect to SPI");
+
+    match spi.execute(&sql, &[]) {
+        Ok(count) => count as i64,
+        Err(err) => panic::panic_any(err),
+    }
+}
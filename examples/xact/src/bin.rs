@@ -0,0 +1,8 @@
+extern crate pg_extend;
+
+use pg_extend::pg_create_stmt_bin;
+
+pg_create_stmt_bin!(
+    xact_buffer_word_pg_create_stmt,
+    xact_take_committed_pg_create_stmt
+);
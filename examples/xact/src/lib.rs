@@ -0,0 +1,74 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate pg_extend;
+extern crate pg_extern_attr;
+
+use std::sync::Mutex;
+
+use pg_extend::pg_magic;
+use pg_extend::pg_sys::SubTransactionId;
+use pg_extend::xact::{self, SubXactEvent, XactEvent};
+use pg_extern_attr::pg_extern;
+
+// This tells Postgres this library is a Postgres extension, and to register the callbacks once it is loaded
+pg_magic!(version: pg_sys::PG_VERSION_NUM, init: register_callbacks);
+
+/// The words buffered by the current transaction, with the subtransaction each was buffered in
+static BUFFER: Mutex<Vec<(SubTransactionId, String)>> = Mutex::new(Vec::new());
+
+/// The words of committed transactions
+static COMMITTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Registers the callbacks which commit or discard the buffered words, called from `_PG_init`
+fn register_callbacks() {
+    xact::on_xact_event(|event| {
+        let mut buffer = BUFFER.lock().expect("buffer poisoned");
+
+        match event {
+            XactEvent::Commit => COMMITTED
+                .lock()
+                .expect("committed poisoned")
+                .extend(buffer.drain(..).map(|(_, word)| word)),
+            XactEvent::Abort => buffer.clear(),
+            _ => (),
+        }
+    });
+
+    xact::on_subxact_event(|event, subid, parent_subid| {
+        let mut buffer = BUFFER.lock().expect("buffer poisoned");
+
+        match event {
+            // the words of a released savepoint now belong to its parent
+            SubXactEvent::CommitSub => buffer
+                .iter_mut()
+                .filter(|(word_subid, _)| *word_subid == subid)
+                .for_each(|(word_subid, _)| *word_subid = parent_subid),
+            SubXactEvent::AbortSub => buffer.retain(|(word_subid, _)| *word_subid != subid),
+            _ => (),
+        }
+    });
+}
+
+/// Buffers the word until the transaction commits, returning the number of buffered words
+#[pg_extern]
+fn xact_buffer_word(word: String) -> i64 {
+    let mut buffer = BUFFER.lock().expect("buffer poisoned");
+    buffer.push((xact::current_subtransaction_id(), word));
+    buffer.len() as i64
+}
+
+/// Returns the words of the transactions committed since the last call, separated by spaces
+#[pg_extern]
+fn xact_take_committed() -> String {
+    COMMITTED
+        .lock()
+        .expect("committed poisoned")
+        .drain(..)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,39 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_bgworker_launch() {
+    test_in_db("bgworker", |mut conn| {
+        let result = conn
+            .query("SELECT bgworker_launch()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let launched: bool = row.get(0);
+        assert!(launched);
+    });
+}
+
+#[test]
+fn test_bgworker_insert_value() {
+    test_in_db("bgworker", |mut conn| {
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS bgworker_results (value int8); DELETE FROM bgworker_results;",
+        )
+        .expect("failed to create table");
+
+        let result = conn
+            .query("SELECT bgworker_insert_value(42)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let status: String = row.get(0);
+        assert_eq!(status, "Stopped");
+
+        let result = conn
+            .query("SELECT value FROM bgworker_results", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let value: i64 = row.get(0);
+        assert_eq!(value, 42);
+    });
+}
@@ -0,0 +1,15 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_dsm_sum_squares() {
+    test_in_db("dsm", |mut conn| {
+        let result = conn
+            .query("SELECT dsm_sum_squares(10)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let sum: i64 = row.get(0);
+        assert_eq!(sum, 385);
+    });
+}
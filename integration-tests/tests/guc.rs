@@ -0,0 +1,70 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_guc_int() {
+    test_in_db("guc", |mut conn| {
+        let result = conn
+            .query("SELECT guc_batch_size()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let batch_size: i32 = row.get(0);
+        assert_eq!(batch_size, 100);
+
+        conn.batch_execute("SET guc.batch_size = 500")
+            .expect("set failed");
+        let result = conn
+            .query("SELECT guc_batch_size()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let batch_size: i32 = row.get(0);
+        assert_eq!(batch_size, 500);
+
+        // outside of the range
+        assert!(conn.batch_execute("SET guc.batch_size = 0").is_err());
+    });
+}
+
+#[test]
+fn test_guc_string_and_enum() {
+    test_in_db("guc", |mut conn| {
+        let greeting = |conn: &mut postgres::Client| -> Option<String> {
+            let result = conn
+                .query("SELECT guc_greeting()", &[])
+                .expect("query failed");
+            let row = result.get(0).expect("no rows returned");
+            row.get(0)
+        };
+
+        assert_eq!(greeting(&mut conn).as_deref(), Some("hello"));
+
+        conn.batch_execute("SET guc.greeting = 'ferris'; SET guc.format = 'loud'")
+            .expect("set failed");
+        assert_eq!(greeting(&mut conn).as_deref(), Some("FERRIS"));
+
+        let result = conn
+            .query("SELECT guc_format()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let format: String = row.get(0);
+        assert_eq!(format, "shout");
+
+        let result = conn
+            .query("SELECT guc_assigned()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let assigned: i64 = row.get(0);
+        assert!(assigned > 0);
+
+        // rejected by the check hook
+        let err = conn
+            .batch_execute("SET guc.greeting = ''")
+            .expect_err("empty greeting was accepted");
+        assert!(format!("{:?}", err).contains("the greeting must not be empty"));
+
+        conn.batch_execute("SET guc.greeting TO DEFAULT")
+            .expect("reset failed");
+        assert_eq!(greeting(&mut conn).as_deref(), Some("HELLO"));
+    });
+}
@@ -0,0 +1,133 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_planner_hook() {
+    test_in_db("hooks", |mut conn| {
+        let planned_count = |conn: &mut postgres::Client| -> i64 {
+            let result = conn
+                .query("SELECT hooks_planned_count()", &[])
+                .expect("query failed");
+            let row = result.get(0).expect("no rows returned");
+            row.get(0)
+        };
+
+        let before = planned_count(&mut conn);
+        conn.batch_execute("SELECT 1").expect("query failed");
+
+        // the query, and the second count, are planned through the hook
+        assert_eq!(planned_count(&mut conn), before + 2);
+    });
+}
+
+#[test]
+fn test_executor_hooks() {
+    test_in_db("hooks", |mut conn| {
+        conn.batch_execute("SELECT generate_series(1, 3)")
+            .expect("query failed");
+
+        let result = conn
+            .query("SELECT hooks_last_query()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let last_query: String = row.get(0);
+        assert_eq!(
+            last_query,
+            "Select rows=3 tuples=3: SELECT generate_series(1, 3)"
+        );
+    });
+}
+
+#[test]
+fn test_executor_hooks_nested_error() {
+    test_in_db("hooks", |mut conn| {
+        // raised while fetching the rows
+        let err = conn
+            .batch_execute(
+                "SELECT hooks_spi_count('SELECT 1 / (i - 2) FROM generate_series(1, 3) i')",
+            )
+            .expect_err("division by zero succeeded");
+        assert_eq!(err.code().map(|code| code.code()), Some("22012"));
+
+        // raised while preparing the query
+        let err = conn
+            .batch_execute("SELECT hooks_spi_count('SELEC 1')")
+            .expect_err("syntax error succeeded");
+        assert_eq!(err.code().map(|code| code.code()), Some("42601"));
+
+        // the connection is still usable after the ERROR
+        conn.batch_execute("SELECT 1").expect("query failed");
+    });
+}
+
+#[test]
+fn test_process_utility_hook() {
+    test_in_db("hooks", |mut conn| {
+        conn.batch_execute("CREATE TABLE hooks_protected (id int)")
+            .expect("create failed");
+
+        conn.batch_execute("SELECT hooks_protect_tables(true)")
+            .expect("protect failed");
+        let err = conn
+            .batch_execute("DROP TABLE hooks_protected")
+            .expect_err("the table was dropped");
+        assert!(format!("{:?}", err).contains("dropping tables is not allowed"));
+
+        conn.batch_execute("SELECT hooks_protect_tables(false)")
+            .expect("unprotect failed");
+        conn.batch_execute("DROP TABLE hooks_protected")
+            .expect("drop failed");
+    });
+}
+
+#[test]
+fn test_post_parse_analyze_hook() {
+    test_in_db("hooks", |mut conn| {
+        let take_fingerprints = |conn: &mut postgres::Client| -> String {
+            let result = conn
+                .query("SELECT hooks_take_fingerprints()", &[])
+                .expect("query failed");
+            let row = result.get(0).expect("no rows returned");
+            row.get(0)
+        };
+
+        conn.batch_execute("CREATE TABLE hooks_analyzed (id int)")
+            .expect("create failed");
+        take_fingerprints(&mut conn);
+
+        conn.batch_execute("SELECT id FROM hooks_analyzed WHERE id = 1")
+            .expect("query failed");
+        conn.batch_execute("SELECT id FROM hooks_analyzed WHERE id = 2")
+            .expect("query failed");
+        conn.batch_execute("SELECT 1").expect("query failed");
+
+        let fingerprints = take_fingerprints(&mut conn);
+        let fingerprints = fingerprints.lines().collect::<Vec<_>>();
+        assert_eq!(fingerprints.len(), 4);
+
+        // queries which only differ in their constants have the same fingerprint
+        assert!(fingerprints[0].starts_with("Select relations=1 targets=id "));
+        assert_eq!(fingerprints[0], fingerprints[1]);
+        assert_ne!(fingerprints[0], fingerprints[2]);
+    });
+}
+
+#[test]
+fn test_emit_log_hook() {
+    test_in_db("hooks", |mut conn| {
+        let err = conn
+            .batch_execute("SELECT hooks_panic('password=hunter2')")
+            .expect_err("the panic was not raised");
+
+        let err = format!("{:?}", err);
+        assert!(err.contains("[redacted]"));
+        assert!(!err.contains("hunter2"));
+
+        // other messages are not changed
+        let err = conn
+            .batch_execute("SELECT hooks_panic('ferris')")
+            .expect_err("the panic was not raised");
+        assert!(format!("{:?}", err).contains("ferris"));
+    });
+}
@@ -0,0 +1,57 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_gist_signature() {
+    test_in_db("indexes", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE numbers (n int8); \
+             INSERT INTO numbers SELECT i FROM generate_series(1, 1000) i; \
+             CREATE INDEX numbers_gist ON numbers USING gist (n int8_signature_ops); \
+             SET enable_seqscan = off;",
+        )
+        .expect("failed to create gist index");
+
+        let result = conn
+            .query("SELECT count(*) FROM numbers WHERE n = 42", &[])
+            .expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 1);
+    });
+}
+
+#[test]
+fn test_gin_words() {
+    test_in_db("indexes", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE documents (body text); \
+             INSERT INTO documents VALUES ('The quick brown fox'), ('the lazy dog'), ('A quick dog'); \
+             CREATE INDEX documents_gin ON documents USING gin (body words_ops); \
+             SET enable_seqscan = off;",
+        )
+        .expect("failed to create gin index");
+
+        let result = conn
+            .query(
+                "SELECT count(*) FROM documents WHERE body &@ 'QUICK dog'",
+                &[],
+            )
+            .expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 1);
+
+        let result = conn
+            .query("SELECT count(*) FROM documents WHERE body &@ ''", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 3);
+    });
+}
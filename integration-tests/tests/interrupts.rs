@@ -0,0 +1,99 @@
+extern crate integration_tests;
+
+use std::thread;
+use std::time::Duration;
+
+use integration_tests::*;
+
+#[test]
+fn test_interrupts_sum_to() {
+    test_in_db("interrupts", |mut conn| {
+        let result = conn
+            .query("SELECT interrupts_sum_to(1000)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let sum: i64 = row.get(0);
+        assert_eq!(sum, 499_500);
+    });
+}
+
+#[test]
+fn test_interrupts_sum_to_cancelled() {
+    test_in_db("interrupts", |mut conn| {
+        conn.batch_execute("SET statement_timeout = 100")
+            .expect("failed to set timeout");
+
+        let result = conn.query("SELECT interrupts_sum_to(9223372036854775807)", &[]);
+        assert!(result.is_err());
+    });
+}
+
+#[test]
+fn test_interrupts_sleep() {
+    test_in_db("interrupts", |mut conn| {
+        let result = conn
+            .query("SELECT interrupts_sleep(10)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let timed_out: bool = row.get(0);
+        assert!(timed_out);
+    });
+}
+
+#[test]
+fn test_interrupts_sleep_cancelled() {
+    test_in_db("interrupts", |mut conn| {
+        conn.batch_execute("SET statement_timeout = 100")
+            .expect("failed to set timeout");
+
+        let result = conn.query("SELECT interrupts_sleep(60000)", &[]);
+        assert!(result.is_err());
+    });
+}
+
+#[test]
+fn test_interrupts_guard_dropped_on_cancel() {
+    test_in_db("interrupts", |mut conn| {
+        let guards_dropped = |conn: &mut postgres::Client| -> i64 {
+            let result = conn
+                .query("SELECT interrupts_guards_dropped()", &[])
+                .expect("query failed");
+            let row = result.get(0).expect("no rows returned");
+            row.get(0)
+        };
+
+        let before = guards_dropped(&mut conn);
+        let result = conn
+            .query("SELECT pg_backend_pid()", &[])
+            .expect("query failed");
+        let pid: i32 = result.get(0).expect("no rows returned").get(0);
+
+        // cancel the function from another connection once it is running
+        let canceller = thread::spawn(move || {
+            let mut conn = db_conn();
+            loop {
+                let result = conn
+                    .query(
+                        "SELECT pg_cancel_backend(pid) FROM pg_stat_activity \
+                         WHERE pid = $1 AND state = 'active' AND query LIKE '%interrupts_wait_guarded%'",
+                        &[&pid],
+                    )
+                    .expect("query failed");
+
+                if !result.is_empty() {
+                    break;
+                }
+
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let err = conn
+            .batch_execute("SELECT interrupts_wait_guarded()")
+            .expect_err("the function was not cancelled");
+        assert_eq!(err.code().map(|code| code.code()), Some("57014"));
+        canceller.join().expect("canceller failed");
+
+        assert_eq!(guards_dropped(&mut conn), before + 1);
+    });
+}
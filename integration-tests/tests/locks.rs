@@ -0,0 +1,76 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_locks_try_advisory() {
+    test_in_db("locks", |mut conn| {
+        let mut other = db_conn();
+        other
+            .query("SELECT pg_advisory_lock(4401)", &[])
+            .expect("failed to lock");
+
+        let result = conn
+            .query("SELECT locks_try_advisory(4401)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let free: bool = row.get(0);
+        assert!(!free);
+
+        other
+            .query("SELECT pg_advisory_unlock(4401)", &[])
+            .expect("failed to unlock");
+
+        let result = conn
+            .query("SELECT locks_try_advisory(4401)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let free: bool = row.get(0);
+        assert!(free);
+    });
+}
+
+#[test]
+fn test_locks_hold_advisory() {
+    test_in_db("locks", |mut conn| {
+        conn.query("SELECT locks_hold_advisory(4402)", &[])
+            .expect("query failed");
+
+        let result = conn
+            .query("SELECT pg_advisory_unlock(4402)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let unlocked: bool = row.get(0);
+        assert!(unlocked);
+    });
+}
+
+#[test]
+fn test_locks_lock_relation() {
+    test_in_db("locks", |mut conn| {
+        conn.batch_execute("CREATE TABLE IF NOT EXISTS locks_test (id int4)")
+            .expect("failed to create table");
+
+        let mut tx = conn.transaction().expect("failed to begin");
+        let result = tx
+            .query(
+                "SELECT locks_lock_relation('locks_test'::regclass::oid::int8)",
+                &[],
+            )
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let locked: bool = row.get(0);
+        assert!(locked);
+
+        let result = tx
+            .query(
+                "SELECT count(*) FROM pg_locks WHERE relation = 'locks_test'::regclass \
+                 AND mode = 'AccessExclusiveLock' AND pid = pg_backend_pid()",
+                &[],
+            )
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let count: i64 = row.get(0);
+        assert_eq!(count, 1);
+    });
+}
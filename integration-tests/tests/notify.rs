@@ -0,0 +1,48 @@
+extern crate integration_tests;
+
+use std::time::Duration;
+
+use integration_tests::*;
+
+#[test]
+fn test_notify_send() {
+    test_in_db("notify", |mut conn| {
+        conn.batch_execute("LISTEN notify_test")
+            .expect("failed to listen");
+
+        let result = conn
+            .query("SELECT notify_send('notify_test', 'hello')", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let err: Option<String> = row.get(0);
+        assert_eq!(err, None);
+
+        let mut notifications = conn.notifications();
+        let notification = notifications
+            .timeout_iter(Duration::from_secs(5))
+            .next()
+            .expect("no notification received")
+            .expect("failed to receive notification");
+        assert_eq!(notification.channel(), "notify_test");
+        assert_eq!(notification.payload(), "hello");
+    });
+}
+
+#[test]
+fn test_notify_send_invalid() {
+    test_in_db("notify", |mut conn| {
+        let result = conn
+            .query("SELECT notify_send('', 'hello')", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let err: Option<String> = row.get(0);
+        assert_eq!(err.as_deref(), Some("channel name cannot be empty"));
+
+        let result = conn
+            .query("SELECT notify_send('notify_test', repeat('x', 8000))", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let err: Option<String> = row.get(0);
+        assert_eq!(err.as_deref(), Some("payload string too long"));
+    });
+}
@@ -0,0 +1,122 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_distance() {
+    test_in_db("operators", |mut conn| {
+        let result = conn
+            .query("SELECT CAST(1.5 as float8) <-> CAST(3.0 as float8)", &[])
+            .expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: f64 = row.get(0);
+
+        assert_eq!(col, 1.5);
+    });
+}
+
+#[test]
+fn test_abs_eq() {
+    test_in_db("operators", |mut conn| {
+        let result = conn
+            .query("SELECT -1 |=| 1, -1 |<>| 1, NOT (-1 |=| 2)", &[])
+            .expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let eq: bool = row.get(0);
+        let ne: bool = row.get(1);
+        let negated: bool = row.get(2);
+
+        assert!(eq);
+        assert!(!ne);
+        assert!(negated);
+    });
+}
+
+#[test]
+fn test_prefix_operator() {
+    test_in_db("operators", |mut conn| {
+        let result = conn.query("SELECT ^^ 3", &[]).expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: i32 = row.get(0);
+
+        assert_eq!(col, 9);
+    });
+}
+
+#[test]
+fn test_version_btree() {
+    test_in_db("operators", |mut conn| {
+        let result = conn
+            .query("SELECT '2'::version < '10'::version", &[])
+            .expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: bool = row.get(0);
+        assert!(col);
+
+        conn.batch_execute(
+            "CREATE TEMP TABLE versions (v version); \
+             INSERT INTO versions SELECT i::text::version FROM generate_series(1, 100) i; \
+             CREATE INDEX versions_btree ON versions USING btree (v);",
+        )
+        .expect("failed to create btree index");
+
+        let result = conn
+            .query("SELECT count(*) FROM versions WHERE v >= '90'::version", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 11);
+    });
+}
+
+#[test]
+fn test_version_hash() {
+    test_in_db("operators", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE hashed_versions (v version); \
+             INSERT INTO hashed_versions SELECT i::text::version FROM generate_series(1, 100) i; \
+             CREATE INDEX hashed_versions_hash ON hashed_versions USING hash (v);",
+        )
+        .expect("failed to create hash index");
+
+        let result = conn
+            .query(
+                "SELECT count(*) FROM hashed_versions WHERE v = '42'::version",
+                &[],
+            )
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 1);
+    });
+}
+
+#[test]
+fn test_version_brin() {
+    test_in_db("operators", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE brin_versions (v version); \
+             INSERT INTO brin_versions SELECT i::text::version FROM generate_series(1, 1000) i; \
+             CREATE INDEX brin_versions_brin ON brin_versions USING brin (v);",
+        )
+        .expect("failed to create brin index");
+
+        let result = conn
+            .query(
+                "SELECT count(*) FROM brin_versions WHERE v < '10'::version",
+                &[],
+            )
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let col: i64 = row.get(0);
+        assert_eq!(col, 9);
+    });
+}
@@ -0,0 +1,39 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_shmem_count() {
+    test_in_db("shmem", |mut conn| {
+        let result = conn
+            .query("SELECT shmem_count(), shmem_count()", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let first: Option<i64> = row.get(0);
+        let second: Option<i64> = row.get(1);
+
+        // the counter is only in shared memory if the library is in shared_preload_libraries
+        match first {
+            Some(first) => assert!(second.expect("second count is NULL") > first),
+            None => assert_eq!(second, None),
+        }
+    });
+}
+
+#[test]
+fn test_shmem_add() {
+    test_in_db("shmem", |mut conn| {
+        let result = conn
+            .query("SELECT shmem_add(0), shmem_add(5)", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let before: Option<i64> = row.get(0);
+        let after: Option<i64> = row.get(1);
+
+        // the totals are only in shared memory if the library is in shared_preload_libraries
+        match before {
+            Some(before) => assert_eq!(after, Some(before + 5)),
+            None => assert_eq!(after, None),
+        }
+    });
+}
@@ -0,0 +1,197 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_spi_add() {
+    test_in_db("spi", |mut conn| {
+        let result = conn.query("SELECT spi_add(1, 2)", &[]).expect("query failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: i32 = row.get(0);
+        assert_eq!(col, 3);
+    });
+}
+
+#[test]
+fn test_spi_words() {
+    test_in_db("spi", |mut conn| {
+        let mut tx = conn.transaction().expect("failed to begin");
+        tx.batch_execute("DELETE FROM spi_words")
+            .expect("failed to clear words");
+
+        let result = tx
+            .query("SELECT spi_insert_word('ferris')", &[])
+            .expect("insert failed");
+        let row = result.get(0).expect("no rows returned");
+        let inserted: i64 = row.get(0);
+        assert_eq!(inserted, 1);
+
+        tx.batch_execute("SELECT spi_insert_word('crab'); SELECT spi_insert_word('rust');")
+            .expect("insert failed");
+
+        let result = tx.query("SELECT spi_words(5)", &[]).expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let words: String = row.get(0);
+        assert_eq!(words, "ferris");
+
+        let result = tx.query("SELECT spi_words(0)", &[]).expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let words: String = row.get(0);
+        assert_eq!(words, "crab ferris rust");
+
+        tx.rollback().expect("failed to rollback");
+    });
+}
+
+#[test]
+fn test_spi_try_insert_word() {
+    test_in_db("spi", |mut conn| {
+        let mut tx = conn.transaction().expect("failed to begin");
+        tx.batch_execute("DELETE FROM spi_words")
+            .expect("failed to clear words");
+
+        let result = tx
+            .query(
+                "SELECT spi_try_insert_word('ferris'), spi_try_insert_word(NULL), spi_try_insert_word('crab')",
+                &[],
+            )
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+
+        let inserted: String = row.get(0);
+        assert_eq!(inserted, "inserted 1");
+
+        // the failed insert is rolled back, without aborting the transaction
+        let failed: String = row.get(1);
+        assert!(failed.starts_with("23502: "), "unexpected error: {}", failed);
+
+        let inserted: String = row.get(2);
+        assert_eq!(inserted, "inserted 1");
+
+        let result = tx.query("SELECT spi_words(0)", &[]).expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let words: String = row.get(0);
+        assert_eq!(words, "crab ferris");
+
+        tx.rollback().expect("failed to rollback");
+    });
+}
+
+#[test]
+fn test_spi_try_execute() {
+    test_in_db("spi", |mut conn| {
+        let mut tx = conn.transaction().expect("failed to begin");
+
+        let result = tx
+            .query("SELECT spi_try_execute('SELEC 1')", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let failed: String = row.get(0);
+        assert!(failed.starts_with("42601: "), "unexpected error: {}", failed);
+
+        // the syntax error did not abort the transaction
+        let result = tx
+            .query("SELECT spi_try_execute('SELECT 1')", &[])
+            .expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let processed: String = row.get(0);
+        assert_eq!(processed, "processed 1");
+
+        tx.rollback().expect("failed to rollback");
+    });
+}
+
+#[test]
+fn test_spi_count_words() {
+    test_in_db("spi", |mut conn| {
+        let mut tx = conn.transaction().expect("failed to begin");
+        tx.batch_execute(
+            "DELETE FROM spi_words; \
+             INSERT INTO spi_words VALUES ('ferris'), ('crab'), ('rust');",
+        )
+        .expect("failed to insert words");
+
+        // the plan is prepared for the first row, and reused for the others
+        let result = tx
+            .query(
+                "SELECT spi_count_words(length) FROM (VALUES (0), (5), (10)) AS lengths (length)",
+                &[],
+            )
+            .expect("query failed");
+        let counts = result.iter().map(|row| row.get(0)).collect::<Vec<i64>>();
+        assert_eq!(counts, vec![3, 1, 0]);
+
+        tx.rollback().expect("failed to rollback");
+    });
+}
+
+#[test]
+fn test_spi_sum_series() {
+    test_in_db("spi", |mut conn| {
+        let result = conn
+            .query("SELECT spi_sum_series(10000), spi_sum_series(0)", &[])
+            .expect("query failed");
+
+        let row = result.get(0).expect("no rows returned");
+        let sum: i64 = row.get(0);
+        assert_eq!(sum, 50_005_000);
+
+        let empty: i64 = row.get(1);
+        assert_eq!(empty, 0);
+    });
+}
+
+#[test]
+fn test_spi_longest_word() {
+    test_in_db("spi", |mut conn| {
+        let mut tx = conn.transaction().expect("failed to begin");
+        tx.batch_execute(
+            "DELETE FROM spi_words; \
+             INSERT INTO spi_words VALUES ('ferris'), ('crab'), ('rust');",
+        )
+        .expect("failed to insert words");
+
+        let result = tx.query("SELECT spi_longest_word()", &[]).expect("query failed");
+        let row = result.get(0).expect("no rows returned");
+        let longest: Option<String> = row.get(0);
+        assert_eq!(longest.as_deref(), Some("ferris: 6"));
+
+        tx.rollback().expect("failed to rollback");
+    });
+}
+
+#[test]
+fn test_spi_row_type_error() {
+    test_in_db("spi", |mut conn| {
+        let result = conn
+            .query("SELECT spi_row_type_error()", &[])
+            .expect("query failed");
+
+        let row = result.get(0).expect("no rows returned");
+        let error: String = row.get(0);
+        assert!(
+            error.starts_with("column number 2 is of type integer"),
+            "unexpected error: {}",
+            error
+        );
+    });
+}
+
+#[test]
+fn test_spi_get_type_error() {
+    test_in_db("spi", |mut conn| {
+        let result = conn
+            .query("SELECT spi_get_type_error()", &[])
+            .expect("query failed");
+
+        let row = result.get(0).expect("no rows returned");
+        let error: String = row.get(0);
+        assert!(
+            error.starts_with("column \"length\" is of type integer"),
+            "unexpected error: {}",
+            error
+        );
+    });
+}
@@ -0,0 +1,134 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_uppercase_name() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE people (name text, age int4); \
+             CREATE TRIGGER people_uppercase_name BEFORE INSERT OR UPDATE ON people \
+             FOR EACH ROW EXECUTE PROCEDURE uppercase_name();",
+        )
+        .expect("failed to create trigger");
+
+        let result = conn
+            .query("INSERT INTO people VALUES ('ferris', 10) RETURNING name", &[])
+            .expect("insert failed");
+        assert_eq!(result.len(), 1);
+
+        let row = result.get(0).expect("no rows returned");
+        let col: String = row.get(0);
+        assert_eq!(col, "FERRIS");
+
+        let result = conn
+            .query("UPDATE people SET name = 'crab' RETURNING name", &[])
+            .expect("update failed");
+        let row = result.get(0).expect("no rows returned");
+        let col: String = row.get(0);
+        assert_eq!(col, "CRAB");
+    });
+}
+
+#[test]
+fn test_check_positive() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE ages (age int4); \
+             CREATE TRIGGER ages_check_positive BEFORE INSERT ON ages \
+             FOR EACH ROW EXECUTE PROCEDURE check_positive('age');",
+        )
+        .expect("failed to create trigger");
+
+        conn.execute("INSERT INTO ages VALUES (1)", &[])
+            .expect("insert failed");
+
+        let err = conn
+            .execute("INSERT INTO ages VALUES (-1)", &[])
+            .expect_err("negative age should be rejected");
+        assert!(err.to_string().contains("age must be positive"));
+    });
+}
+
+#[test]
+fn test_check_positive_from_spi() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE spi_ages (age int4); \
+             CREATE TRIGGER spi_ages_check_positive BEFORE INSERT ON spi_ages \
+             FOR EACH ROW EXECUTE PROCEDURE check_positive('age');",
+        )
+        .expect("failed to create trigger");
+
+        let result = conn
+            .query("SELECT triggers_spi_execute('INSERT INTO spi_ages VALUES (1)')", &[])
+            .expect("insert failed");
+        let row = result.get(0).expect("no rows returned");
+        let inserted: i64 = row.get(0);
+        assert_eq!(inserted, 1);
+
+        // the error of the trigger is raised within the SPI statement of the function
+        let err = conn
+            .batch_execute("SELECT triggers_spi_execute('INSERT INTO spi_ages VALUES (-1)')")
+            .expect_err("negative age should be rejected");
+        assert_eq!(err.code().map(|code| code.code()), Some("23514"));
+        assert!(err.to_string().contains("age must be positive"));
+
+        // the connection is still usable after the ERROR
+        conn.batch_execute("SELECT 1").expect("query failed");
+    });
+}
+
+#[test]
+fn test_notice_change() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE changes (id int4); \
+             CREATE TRIGGER changes_notice_row AFTER INSERT ON changes \
+             FOR EACH ROW EXECUTE PROCEDURE notice_change(); \
+             CREATE TRIGGER changes_notice_statement AFTER TRUNCATE ON changes \
+             FOR EACH STATEMENT EXECUTE PROCEDURE notice_change();",
+        )
+        .expect("failed to create triggers");
+
+        let inserted = conn
+            .execute("INSERT INTO changes VALUES (1), (2)", &[])
+            .expect("insert failed");
+        assert_eq!(inserted, 2);
+
+        conn.batch_execute("TRUNCATE changes")
+            .expect("truncate failed");
+    });
+}
+
+#[test]
+fn test_sequence_names() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute("CREATE TEMP SEQUENCE ids_seq")
+            .expect("sequence ending in _seq should be allowed");
+
+        let err = conn
+            .batch_execute("CREATE TEMP SEQUENCE ids")
+            .expect_err("sequence not ending in _seq should be rejected");
+        assert!(err.to_string().contains("must be named *_seq"));
+    });
+}
+
+#[test]
+fn test_protect_tables() {
+    test_in_db("triggers", |mut conn| {
+        conn.batch_execute(
+            "CREATE TEMP TABLE protected_people (name text); \
+             CREATE TEMP TABLE people_scratch (name text);",
+        )
+        .expect("failed to create tables");
+
+        conn.batch_execute("DROP TABLE people_scratch")
+            .expect("unprotected table should be dropped");
+
+        let err = conn
+            .batch_execute("DROP TABLE protected_people")
+            .expect_err("protected table should not be dropped");
+        assert!(err.to_string().contains("is protected"));
+    });
+}
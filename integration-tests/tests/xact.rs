@@ -0,0 +1,41 @@
+extern crate integration_tests;
+
+use integration_tests::*;
+
+#[test]
+fn test_xact_callbacks() {
+    test_in_db("xact", |mut conn| {
+        let take_committed = |conn: &mut postgres::Client| -> String {
+            let result = conn
+                .query("SELECT xact_take_committed()", &[])
+                .expect("query failed");
+            let row = result.get(0).expect("no rows returned");
+            row.get(0)
+        };
+
+        // a word buffered outside of a transaction block is committed with its statement
+        conn.batch_execute("SELECT xact_buffer_word('hello')")
+            .expect("buffer failed");
+        assert_eq!(take_committed(&mut conn), "hello");
+
+        conn.batch_execute(
+            "BEGIN; SELECT xact_buffer_word('ferris'); SELECT xact_buffer_word('crab'); COMMIT;",
+        )
+        .expect("commit failed");
+        assert_eq!(take_committed(&mut conn), "ferris crab");
+
+        conn.batch_execute("BEGIN; SELECT xact_buffer_word('rust'); ROLLBACK;")
+            .expect("rollback failed");
+        assert_eq!(take_committed(&mut conn), "");
+
+        conn.batch_execute(
+            "BEGIN; \
+             SELECT xact_buffer_word('a'); \
+             SAVEPOINT s; SELECT xact_buffer_word('b'); ROLLBACK TO SAVEPOINT s; \
+             SAVEPOINT t; SELECT xact_buffer_word('c'); RELEASE SAVEPOINT t; \
+             COMMIT;",
+        )
+        .expect("savepoints failed");
+        assert_eq!(take_committed(&mut conn), "a c");
+    });
+}
@@ -153,6 +153,181 @@ fn get_bindings(pg_include: &str) -> bindgen::Builder {
         .whitelist_type("DefElem")
         .whitelist_type("Value")
         .whitelist_var("InvalidBuffer")
+        // Index support function whitelisting
+        .whitelist_type("GISTENTRY")
+        .whitelist_type("GistEntryVector")
+        .whitelist_type("GIST_SPLITVEC")
+        .whitelist_type("GISTPageOpaqueData")
+        .whitelist_type("PageHeaderData")
+        .whitelist_var("F_LEAF")
+        .whitelist_function("datumGetSize")
+        .whitelist_type("GinTernaryValue")
+        .whitelist_type("StrategyNumber")
+        .whitelist_var("GIN_.*")
+        // Trigger whitelisting
+        .whitelist_function("errcode")
+        .whitelist_function("errdetail")
+        .whitelist_function("errhint")
+        .whitelist_function("get_namespace_name")
+        .whitelist_function("heap_modify_tuple_by_cols")
+        .whitelist_function("SPI_.*")
+        .whitelist_type("TriggerData")
+        .whitelist_var("TRIGGER_EVENT_.*")
+        // Event trigger whitelisting
+        .whitelist_type("EventTriggerData")
+        .whitelist_var("SPI_.*")
+        // SPI whitelisting
+        .whitelist_function("parseTypeString")
+        // Row whitelisting
+        .whitelist_function("format_type_be")
+        // Subtransaction whitelisting
+        .whitelist_function("BeginInternalSubTransaction")
+        .whitelist_function("ReleaseCurrentSubTransaction")
+        .whitelist_function("RollbackAndReleaseCurrentSubTransaction")
+        .whitelist_function("CopyErrorData")
+        .whitelist_function("FlushErrorState")
+        .whitelist_function("FreeErrorData")
+        .whitelist_var("CurrentResourceOwner")
+        .whitelist_var("error_context_stack")
+        // Transaction callback whitelisting
+        .whitelist_function("RegisterXactCallback")
+        .whitelist_function("UnregisterXactCallback")
+        .whitelist_function("RegisterSubXactCallback")
+        .whitelist_function("UnregisterSubXactCallback")
+        .whitelist_function("GetCurrentSubTransactionId")
+        .whitelist_var("XactEvent_.*")
+        .whitelist_var("SubXactEvent_.*")
+        // GUC whitelisting
+        .whitelist_function("DefineCustom.*Variable")
+        .whitelist_function("EmitWarningsOnPlaceholders")
+        .whitelist_function("MemoryContextStrdup")
+        .whitelist_type("config_enum_entry")
+        .whitelist_var("GucContext_.*")
+        .whitelist_var("GUC_.*")
+        .whitelist_var("ErrorContext")
+        // Background worker whitelisting
+        .whitelist_function("RegisterBackgroundWorker")
+        .whitelist_function("RegisterDynamicBackgroundWorker")
+        .whitelist_function("BackgroundWorkerInitializeConnection")
+        .whitelist_function("BackgroundWorkerUnblockSignals")
+        .whitelist_function("pqsignal")
+        .whitelist_function("WaitLatch")
+        .whitelist_function("ResetLatch")
+        .whitelist_function("SetLatch")
+        .whitelist_function("proc_exit")
+        .whitelist_function("ProcessConfigFile")
+        .whitelist_function("SetCurrentStatementStartTimestamp")
+        .whitelist_function("StartTransactionCommand")
+        .whitelist_function("CommitTransactionCommand")
+        .whitelist_function("PushActiveSnapshot")
+        .whitelist_function("PopActiveSnapshot")
+        .whitelist_function("GetTransactionSnapshot")
+        .whitelist_type("BackgroundWorker")
+        .whitelist_var("BGW.*")
+        .whitelist_var("BgWorkerStartTime_.*")
+        .whitelist_var("WL_.*")
+        .whitelist_var("MyLatch")
+        .whitelist_var("process_shared_preload_libraries_in_progress")
+        // Background worker handle whitelisting
+        .whitelist_function("BackgroundWorkerInitializeConnectionByOid")
+        .whitelist_function("GetBackgroundWorkerPid")
+        .whitelist_function("WaitForBackgroundWorkerStartup")
+        .whitelist_function("WaitForBackgroundWorkerShutdown")
+        .whitelist_function("TerminateBackgroundWorker")
+        .whitelist_var("BgwHandleStatus_.*")
+        .whitelist_var("MyBgworkerEntry")
+        .whitelist_var("MyProcPid")
+        .whitelist_var("MyDatabaseId")
+        .whitelist_var("TopMemoryContext")
+        // Shared memory whitelisting
+        .whitelist_function("RequestAddinShmemSpace")
+        .whitelist_function("ShmemInitStruct")
+        .whitelist_function("RequestNamedLWLockTranche")
+        .whitelist_function("GetNamedLWLockTranche")
+        .whitelist_function("LWLockAcquire")
+        .whitelist_function("LWLockRelease")
+        .whitelist_type("LWLockPadded")
+        .whitelist_var("LWLockMode_.*")
+        .whitelist_var("MainLWLockArray")
+        .whitelist_var("shmem_startup_hook")
+        // Dynamic shared memory whitelisting
+        .whitelist_function("dsm_create")
+        .whitelist_function("dsm_attach")
+        .whitelist_function("dsm_detach")
+        .whitelist_function("dsm_pin_mapping")
+        .whitelist_function("dsm_segment_handle")
+        .whitelist_function("dsm_segment_address")
+        .whitelist_function("dsm_segment_map_length")
+        .whitelist_function("shm_mq_create")
+        .whitelist_function("shm_mq_set_sender")
+        .whitelist_function("shm_mq_set_receiver")
+        .whitelist_function("shm_mq_attach")
+        .whitelist_function("shm_mq_detach")
+        .whitelist_function("shm_mq_send")
+        .whitelist_function("shm_mq_receive")
+        .whitelist_var("shm_mq_result_.*")
+        .whitelist_var("MyProc")
+        // Interrupt whitelisting
+        .whitelist_function("ProcessInterrupts")
+        .whitelist_function("WaitLatchOrSocket")
+        .whitelist_var("InterruptPending")
+        .whitelist_var("PGINVALID_SOCKET")
+        // Lock whitelisting
+        .whitelist_function("LockAcquire")
+        .whitelist_function("LockRelease")
+        .whitelist_function("LockRelationOid")
+        .whitelist_function("ConditionalLockRelationOid")
+        .whitelist_function("UnlockRelationOid")
+        .whitelist_type("LOCKTAG")
+        .whitelist_var("LockTagType_.*")
+        .whitelist_var("LockAcquireResult_.*")
+        .whitelist_var("USER_LOCKMETHOD")
+        .whitelist_var("AccessShareLock")
+        .whitelist_var("RowShareLock")
+        .whitelist_var("RowExclusiveLock")
+        .whitelist_var("ShareUpdateExclusiveLock")
+        .whitelist_var("ShareLock")
+        .whitelist_var("ShareRowExclusiveLock")
+        .whitelist_var("ExclusiveLock")
+        .whitelist_var("AccessExclusiveLock")
+        // Notify whitelisting
+        .whitelist_function("Async_Notify")
+        .whitelist_function("IsTransactionState")
+        .whitelist_var("ParallelWorkerNumber")
+        .whitelist_var("BLCKSZ")
+        .whitelist_var("SIGTERM")
+        .whitelist_var("SIGHUP")
+        // Hook whitelisting
+        .whitelist_function("standard_planner")
+        .whitelist_type("PlannedStmt")
+        .whitelist_type("ParamListInfo")
+        .whitelist_var("planner_hook")
+        .whitelist_function("standard_ExecutorStart")
+        .whitelist_function("standard_ExecutorRun")
+        .whitelist_function("standard_ExecutorFinish")
+        .whitelist_function("standard_ExecutorEnd")
+        .whitelist_function("InstrAlloc")
+        .whitelist_function("InstrEndLoop")
+        .whitelist_type("QueryDesc")
+        .whitelist_var("ExecutorStart_hook")
+        .whitelist_var("ExecutorRun_hook")
+        .whitelist_var("ExecutorFinish_hook")
+        .whitelist_var("ExecutorEnd_hook")
+        .whitelist_var("CmdType_.*")
+        .whitelist_var("InstrumentOption_.*")
+        .whitelist_function("standard_ProcessUtility")
+        .whitelist_type("DropStmt")
+        .whitelist_var("ProcessUtility_hook")
+        .whitelist_var("ProcessUtilityContext_.*")
+        .whitelist_var("ObjectType_.*")
+        .whitelist_function("query_tree_walker")
+        .whitelist_function("expression_tree_walker")
+        .whitelist_type("ParseState")
+        .whitelist_type("RangeTblEntry")
+        .whitelist_type("TargetEntry")
+        .whitelist_var("post_parse_analyze_hook")
+        .whitelist_var("RTEKind_.*")
+        .whitelist_var("emit_log_hook")
 }
 
 #[cfg(unix)]
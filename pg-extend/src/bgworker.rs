@@ -0,0 +1,504 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for background workers, processes started by Postgres which run a Rust function, e.g. to run
+//!  periodic jobs.
+//!
+//! The main function of the worker is exported with `#[pg_bgworker]`, and the worker is registered with a
+//!  `BackgroundWorkerBuilder`, either when the library is loaded from `shared_preload_libraries`, or later from
+//!  a function, which gets a `BackgroundWorkerHandle` to wait for or terminate the worker.
+//!
+//! ```rust,ignore
+//! pg_magic!(version: pg_sys::PG_VERSION_NUM, init: init);
+//!
+//! fn init() {
+//!     BackgroundWorkerBuilder::new("vacuum scheduler", "myext", "pg_vacuum_scheduler")
+//!         .database_connection()
+//!         .restart_time(Some(Duration::from_secs(10)))
+//!         .register();
+//! }
+//!
+//! #[pg_bgworker]
+//! fn vacuum_scheduler(worker: &BackgroundWorker) {
+//!     worker.connect("postgres", None);
+//!
+//!     while worker.wait(Duration::from_secs(60)) {
+//!         worker.transaction(|| run_scheduled_vacuums());
+//!     }
+//! }
+//! ```
+
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_long};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::pg_error::PgError;
+use crate::pg_sys;
+
+/// Set by the `SIGTERM` handler, the worker should exit
+static GOT_SIGTERM: AtomicBool = AtomicBool::new(false);
+/// Set by the `SIGHUP` handler, the configuration should be reloaded
+static GOT_SIGHUP: AtomicBool = AtomicBool::new(false);
+
+/// When the postmaster starts a worker, `BgWorkerStartTime`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartTime {
+    /// As soon as the postmaster has started, before the database can be connected to,
+    ///  `BgWorkerStart_PostmasterStart`
+    PostmasterStart,
+    /// Once the database is consistent, e.g. during recovery on a hot standby, `BgWorkerStart_ConsistentState`
+    ConsistentState,
+    /// Once recovery has finished and the database can be written to, `BgWorkerStart_RecoveryFinished`
+    RecoveryFinished,
+}
+
+impl From<StartTime> for pg_sys::BgWorkerStartTime {
+    fn from(start_time: StartTime) -> Self {
+        match start_time {
+            StartTime::PostmasterStart => pg_sys::BgWorkerStartTime_BgWorkerStart_PostmasterStart,
+            StartTime::ConsistentState => pg_sys::BgWorkerStartTime_BgWorkerStart_ConsistentState,
+            StartTime::RecoveryFinished => pg_sys::BgWorkerStartTime_BgWorkerStart_RecoveryFinished,
+        }
+    }
+}
+
+/// The registration of a background worker, `BackgroundWorker`
+///
+/// The worker runs the function, exported with `#[pg_bgworker]`, of the library. By default it starts once
+///  recovery has finished, has no access to shared memory and is not restarted if it exits.
+pub struct BackgroundWorkerBuilder {
+    name: String,
+    #[cfg_attr(any(postgres9, postgres10), allow(dead_code))]
+    worker_type: Option<String>,
+    library: String,
+    function: String,
+    flags: c_int,
+    start_time: StartTime,
+    restart_time: Option<Duration>,
+    main_arg: pg_sys::Datum,
+    extra: Vec<u8>,
+}
+
+impl BackgroundWorkerBuilder {
+    /// Starts the registration of a worker, shown with the name in `pg_stat_activity`, running the function of
+    ///  the library, e.g. `pg_my_worker` for `#[pg_bgworker] fn my_worker`
+    ///
+    /// The name, library and function are truncated to `BGW_MAXLEN - 1` bytes.
+    pub fn new(name: &str, library: &str, function: &str) -> Self {
+        BackgroundWorkerBuilder {
+            name: name.to_string(),
+            worker_type: None,
+            library: library.to_string(),
+            function: function.to_string(),
+            flags: 0,
+            start_time: StartTime::RecoveryFinished,
+            restart_time: None,
+            main_arg: 0,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Sets the type of the worker, shown as the `backend_type` in `pg_stat_activity`, the default is the name
+    #[cfg(not(any(postgres9, postgres10)))]
+    pub fn worker_type(mut self, worker_type: &str) -> Self {
+        self.worker_type = Some(worker_type.to_string());
+        self
+    }
+
+    /// Gives the worker access to shared memory, `BGWORKER_SHMEM_ACCESS`
+    pub fn shared_memory_access(mut self) -> Self {
+        self.flags |= pg_sys::BGWORKER_SHMEM_ACCESS as c_int;
+        self
+    }
+
+    /// Allows the worker to connect to a database with `BackgroundWorker::connect`,
+    ///  `BGWORKER_BACKEND_DATABASE_CONNECTION`, this also gives it access to shared memory
+    pub fn database_connection(mut self) -> Self {
+        self.flags |=
+            (pg_sys::BGWORKER_SHMEM_ACCESS | pg_sys::BGWORKER_BACKEND_DATABASE_CONNECTION) as c_int;
+        self
+    }
+
+    /// Sets when the worker is started
+    pub fn start_time(mut self, start_time: StartTime) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    /// Sets how long after the worker exits with an error, or crashes, it is restarted, `None` to never restart
+    ///  it, `BGW_NEVER_RESTART`
+    ///
+    /// The worker is not restarted if its function returns. The time is rounded down to seconds.
+    pub fn restart_time(mut self, restart_time: Option<Duration>) -> Self {
+        self.restart_time = restart_time;
+        self
+    }
+
+    /// Sets the argument passed to the worker, `bgw_main_arg`, returned by `BackgroundWorker::main_arg`
+    ///
+    /// This must be a value passed by value, e.g. an integer or an `Oid`, pointers are not valid in the worker.
+    pub fn main_arg(mut self, main_arg: pg_sys::Datum) -> Self {
+        self.main_arg = main_arg;
+        self
+    }
+
+    /// Sets the bytes passed to the worker, `bgw_extra`, returned by `BackgroundWorker::extra`
+    ///
+    /// # Panics
+    ///
+    /// If there are more than `BGW_EXTRALEN`, 128, bytes
+    pub fn extra(mut self, extra: &[u8]) -> Self {
+        assert!(
+            extra.len() <= pg_sys::BGW_EXTRALEN as usize,
+            "bgw_extra is limited to {} bytes",
+            pg_sys::BGW_EXTRALEN
+        );
+
+        self.extra = extra.to_vec();
+        self
+    }
+
+    /// Registers the worker to be started by the postmaster, `RegisterBackgroundWorker`
+    ///
+    /// This must be called from the `init` function of `pg_magic!`, with the library loaded from
+    ///  `shared_preload_libraries`, see `loading_shared_preload_libraries`, otherwise the worker is not
+    ///  registered and a `LOG` message is reported.
+    pub fn register(self) {
+        let mut worker = self.to_pg();
+        unsafe { crate::guard_pg(|| pg_sys::RegisterBackgroundWorker(&mut worker)) };
+    }
+
+    /// Registers the worker to be started now by the postmaster, `RegisterDynamicBackgroundWorker`, returning
+    ///  the handle of the worker
+    ///
+    /// This can be called from any function. An error is returned if there is no free slot for the worker,
+    ///  see `max_worker_processes`. This backend is notified when the worker starts and stops, which the waits
+    ///  of the handle rely on.
+    pub fn register_dynamic(self) -> Result<BackgroundWorkerHandle, PgError> {
+        let mut worker = self.to_pg();
+        worker.bgw_notify_pid = unsafe { pg_sys::MyProcPid };
+
+        let mut handle: *mut pg_sys::BackgroundWorkerHandle = ptr::null_mut();
+        let registered = unsafe {
+            // the handle is allocated in the current memory context, it must live as long as the Rust handle
+            let memory_context = pg_sys::CurrentMemoryContext;
+            pg_sys::CurrentMemoryContext = pg_sys::TopMemoryContext;
+            let registered = crate::guard_pg(|| {
+                pg_sys::RegisterDynamicBackgroundWorker(&mut worker, &mut handle)
+            });
+            pg_sys::CurrentMemoryContext = memory_context;
+
+            registered
+        };
+
+        if pgbool!(registered) {
+            Ok(BackgroundWorkerHandle { handle })
+        } else {
+            Err(PgError::new("could not register background worker")
+                .with_hint("You may need to increase max_worker_processes.")
+                .with_sqlstate("53000"))
+        }
+    }
+
+    /// Returns the `BackgroundWorker` for registering the worker
+    fn to_pg(&self) -> pg_sys::BackgroundWorker {
+        let mut worker: pg_sys::BackgroundWorker = unsafe { mem::zeroed() };
+
+        copy_name(&mut worker.bgw_name, &self.name);
+        #[cfg(not(any(postgres9, postgres10)))]
+        copy_name(
+            &mut worker.bgw_type,
+            self.worker_type.as_ref().unwrap_or(&self.name),
+        );
+        copy_name(&mut worker.bgw_library_name, &self.library);
+        copy_name(&mut worker.bgw_function_name, &self.function);
+
+        worker.bgw_flags = self.flags;
+        worker.bgw_start_time = self.start_time.into();
+        worker.bgw_main_arg = self.main_arg;
+        for (dest, src) in worker.bgw_extra.iter_mut().zip(&self.extra) {
+            *dest = *src as c_char;
+        }
+
+        worker.bgw_restart_time = self
+            .restart_time
+            .map_or(pg_sys::BGW_NEVER_RESTART, |restart_time| {
+                restart_time.as_secs() as c_int
+            });
+
+        worker
+    }
+}
+
+/// The status of a worker registered with `BackgroundWorkerBuilder::register_dynamic`, `BgwHandleStatus`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkerStatus {
+    /// The worker is running, with the process id, `BGWH_STARTED`
+    Started(pg_sys::pid_t),
+    /// The worker has not been started yet by the postmaster, `BGWH_NOT_YET_STARTED`
+    NotYetStarted,
+    /// The worker has exited, or could not be started, `BGWH_STOPPED`
+    Stopped,
+    /// The postmaster has died, the status of the worker is unknown, `BGWH_POSTMASTER_DIED`
+    PostmasterDied,
+}
+
+impl WorkerStatus {
+    fn from_pg(status: pg_sys::BgwHandleStatus, pid: pg_sys::pid_t) -> Self {
+        match status {
+            pg_sys::BgwHandleStatus_BGWH_STARTED => WorkerStatus::Started(pid),
+            pg_sys::BgwHandleStatus_BGWH_NOT_YET_STARTED => WorkerStatus::NotYetStarted,
+            pg_sys::BgwHandleStatus_BGWH_STOPPED => WorkerStatus::Stopped,
+            _ => WorkerStatus::PostmasterDied,
+        }
+    }
+}
+
+/// The handle of a worker registered with `BackgroundWorkerBuilder::register_dynamic`, `BackgroundWorkerHandle`
+///
+/// Dropping the handle does not affect the worker.
+pub struct BackgroundWorkerHandle {
+    handle: *mut pg_sys::BackgroundWorkerHandle,
+}
+
+impl BackgroundWorkerHandle {
+    /// Returns the status of the worker, `GetBackgroundWorkerPid`
+    pub fn status(&self) -> WorkerStatus {
+        let mut pid: pg_sys::pid_t = 0;
+        let status =
+            unsafe { crate::guard_pg(|| pg_sys::GetBackgroundWorkerPid(self.handle, &mut pid)) };
+
+        WorkerStatus::from_pg(status, pid)
+    }
+
+    /// Waits for the postmaster to start the worker, `WaitForBackgroundWorkerStartup`, returning
+    ///  `WorkerStatus::Started` with its process id, or `WorkerStatus::Stopped` if it could not be started
+    ///
+    /// The wait can be interrupted by a cancel, which raises an `ERROR`.
+    pub fn wait_for_startup(&self) -> WorkerStatus {
+        let mut pid: pg_sys::pid_t = 0;
+        let status = unsafe {
+            crate::guard_pg(|| pg_sys::WaitForBackgroundWorkerStartup(self.handle, &mut pid))
+        };
+
+        WorkerStatus::from_pg(status, pid)
+    }
+
+    /// Waits for the worker to exit, `WaitForBackgroundWorkerShutdown`, returning `WorkerStatus::Stopped`
+    ///
+    /// The wait can be interrupted by a cancel, which raises an `ERROR`.
+    pub fn wait_for_shutdown(&self) -> WorkerStatus {
+        let status =
+            unsafe { crate::guard_pg(|| pg_sys::WaitForBackgroundWorkerShutdown(self.handle)) };
+
+        WorkerStatus::from_pg(status, 0)
+    }
+
+    /// Asks the postmaster to terminate the worker, `TerminateBackgroundWorker`, which sends it `SIGTERM` and
+    ///  does not restart it
+    pub fn terminate(&self) {
+        unsafe { crate::guard_pg(|| pg_sys::TerminateBackgroundWorker(self.handle)) };
+    }
+
+    /// Returns the `BackgroundWorkerHandle` this wraps
+    pub(crate) fn as_pg(&self) -> *mut pg_sys::BackgroundWorkerHandle {
+        self.handle
+    }
+}
+
+impl Drop for BackgroundWorkerHandle {
+    fn drop(&mut self) {
+        // the handle is freed along with its memory context after an ERROR, which may be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe { crate::guard_pg(|| pg_sys::pfree(self.handle as *mut _)) };
+    }
+}
+
+/// Returns true while the library is being loaded from `shared_preload_libraries`, when workers can be registered
+///  with `BackgroundWorkerBuilder::register`
+pub fn loading_shared_preload_libraries() -> bool {
+    unsafe { pgbool!(pg_sys::process_shared_preload_libraries_in_progress) }
+}
+
+/// Copies the name into the `char[BGW_MAXLEN]`, truncated to leave space for the NULL terminator
+fn copy_name(dest: &mut [c_char], name: &str) {
+    let len = name.len().min(dest.len() - 1);
+
+    for (dest, src) in dest.iter_mut().zip(&name.as_bytes()[..len]) {
+        *dest = *src as c_char;
+    }
+
+    dest[len] = 0;
+}
+
+/// The running background worker, passed to the function exported with `#[pg_bgworker]`
+///
+/// The worker should exit by returning from its function once `wait` returns `false`, which it does after the
+///  postmaster sends `SIGTERM`, e.g. when the server shuts down.
+pub struct BackgroundWorker {
+    main_arg: pg_sys::Datum,
+}
+
+impl BackgroundWorker {
+    /// Returns the argument passed to the worker, set with `BackgroundWorkerBuilder::main_arg`
+    pub fn main_arg(&self) -> pg_sys::Datum {
+        self.main_arg
+    }
+
+    /// Returns the `BGW_EXTRALEN` bytes passed to the worker, set with `BackgroundWorkerBuilder::extra`, followed
+    ///  by zeros
+    pub fn extra(&self) -> &[u8] {
+        unsafe {
+            let extra = &(*pg_sys::MyBgworkerEntry).bgw_extra;
+            std::slice::from_raw_parts(extra.as_ptr() as *const u8, extra.len())
+        }
+    }
+
+    /// Connects the worker to the database, as the user or otherwise the bootstrap superuser,
+    ///  `BackgroundWorkerInitializeConnection`
+    ///
+    /// This can only be called once, and requires `BackgroundWorkerBuilder::database_connection`.
+    pub fn connect(&self, database: &str, user: Option<&str>) {
+        let database = CString::new(database).expect("database names do not contain NULL bytes");
+        let user =
+            user.map(|user| CString::new(user).expect("user names do not contain NULL bytes"));
+        let user_ptr = user.as_ref().map_or(ptr::null(), |user| user.as_ptr());
+
+        unsafe {
+            #[cfg(any(postgres9, postgres10))]
+            crate::guard_pg(|| {
+                pg_sys::BackgroundWorkerInitializeConnection(
+                    database.as_ptr() as *mut c_char,
+                    user_ptr as *mut c_char,
+                )
+            });
+
+            #[cfg(not(any(postgres9, postgres10)))]
+            crate::guard_pg(|| {
+                pg_sys::BackgroundWorkerInitializeConnection(database.as_ptr(), user_ptr, 0)
+            });
+        }
+    }
+
+    /// Waits on the latch of the worker for up to the timeout, `WaitLatch`, returning `false` if the worker
+    ///  should exit
+    ///
+    /// The wait ends early if the latch is set, e.g. by a signal. The configuration is reloaded if `SIGHUP` was
+    ///  received. If the postmaster has died the worker exits.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        if self.is_terminating() {
+            return false;
+        }
+
+        let timeout = timeout.as_millis().min(c_long::MAX as u128) as c_long;
+        let events =
+            (pg_sys::WL_LATCH_SET | pg_sys::WL_TIMEOUT | pg_sys::WL_POSTMASTER_DEATH) as c_int;
+
+        unsafe {
+            #[cfg(postgres9)]
+            let rc = crate::guard_pg(|| pg_sys::WaitLatch(pg_sys::MyLatch, events, timeout));
+            #[cfg(not(postgres9))]
+            let rc = crate::guard_pg(|| {
+                pg_sys::WaitLatch(pg_sys::MyLatch, events, timeout, pg_sys::PG_WAIT_EXTENSION)
+            });
+            crate::guard_pg(|| pg_sys::ResetLatch(pg_sys::MyLatch));
+
+            // there is no postmaster left to manage the worker
+            if rc & pg_sys::WL_POSTMASTER_DEATH as c_int != 0 {
+                pg_sys::proc_exit(1);
+            }
+
+            if GOT_SIGHUP.swap(false, Ordering::SeqCst) {
+                crate::guard_pg(|| pg_sys::ProcessConfigFile(pg_sys::GucContext_PGC_SIGHUP));
+            }
+        }
+
+        !self.is_terminating()
+    }
+
+    /// Connects the worker to the database, as the user or otherwise the bootstrap superuser,
+    ///  `BackgroundWorkerInitializeConnectionByOid`, e.g. to the database of the backend which registered it,
+    ///  passed as an argument
+    ///
+    /// This can only be called once, and requires `BackgroundWorkerBuilder::database_connection`.
+    pub fn connect_by_oid(&self, database: pg_sys::Oid, user: Option<pg_sys::Oid>) {
+        let user = user.unwrap_or(0); // InvalidOid
+
+        unsafe {
+            #[cfg(any(postgres9, postgres10))]
+            crate::guard_pg(|| pg_sys::BackgroundWorkerInitializeConnectionByOid(database, user));
+
+            #[cfg(not(any(postgres9, postgres10)))]
+            crate::guard_pg(|| {
+                pg_sys::BackgroundWorkerInitializeConnectionByOid(database, user, 0)
+            });
+        }
+    }
+
+    /// Returns true once the worker has received `SIGTERM`, and should exit
+    pub fn is_terminating(&self) -> bool {
+        GOT_SIGTERM.load(Ordering::SeqCst)
+    }
+
+    /// Runs the closure in a transaction, with a snapshot for executing SQL, e.g. with `Spi`
+    ///
+    /// The transaction is committed if the closure returns, an `ERROR` or panic aborts it and exits the worker.
+    pub fn transaction<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        unsafe {
+            crate::guard_pg(|| {
+                pg_sys::SetCurrentStatementStartTimestamp();
+                pg_sys::StartTransactionCommand();
+                pg_sys::PushActiveSnapshot(pg_sys::GetTransactionSnapshot());
+            });
+
+            let result = f();
+
+            crate::guard_pg(|| {
+                pg_sys::PopActiveSnapshot();
+                pg_sys::CommitTransactionCommand();
+            });
+
+            result
+        }
+    }
+}
+
+unsafe extern "C" fn handle_sigterm(_signal: c_int) {
+    GOT_SIGTERM.store(true, Ordering::SeqCst);
+    pg_sys::SetLatch(pg_sys::MyLatch);
+}
+
+unsafe extern "C" fn handle_sighup(_signal: c_int) {
+    GOT_SIGHUP.store(true, Ordering::SeqCst);
+    pg_sys::SetLatch(pg_sys::MyLatch);
+}
+
+/// Calls the main function of a background worker, this is used by the functions generated by `#[pg_bgworker]`
+///
+/// The handlers of `SIGTERM` and `SIGHUP` are installed and signals unblocked before the function is called. A
+///  panic is raised as an `ERROR`, which exits the worker.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn call_worker_main<F>(name: &str, main_arg: pg_sys::Datum, worker_main: F)
+where
+    F: FnOnce(&BackgroundWorker),
+{
+    crate::call_library_fn(name, || {
+        crate::guard_pg(|| {
+            pg_sys::pqsignal(pg_sys::SIGTERM as c_int, Some(handle_sigterm));
+            pg_sys::pqsignal(pg_sys::SIGHUP as c_int, Some(handle_sighup));
+            pg_sys::BackgroundWorkerUnblockSignals();
+        });
+
+        worker_main(&BackgroundWorker { main_arg })
+    });
+}
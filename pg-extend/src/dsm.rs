@@ -0,0 +1,97 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for dynamic shared memory segments, which are created while the server is running, e.g. to share
+//!  data with a background worker, unlike `shmem` which is allocated when the server starts.
+//!
+//! A segment is created by one backend, and attached to by others with its handle, which is passed to them, e.g.
+//!  as the `main_arg` of a background worker. See `shm_mq` for sending messages through a segment.
+//!
+//! ```rust,ignore
+//! let segment = DsmSegment::create(1024);
+//!
+//! let worker = BackgroundWorkerBuilder::new("squares", "myext", "pg_squares_worker")
+//!     .shared_memory_access()
+//!     .main_arg(segment.handle() as pg_sys::Datum)
+//!     .register_dynamic()?;
+//!
+//! #[pg_bgworker]
+//! fn squares_worker(worker: &BackgroundWorker) {
+//!     let segment = DsmSegment::attach(worker.main_arg() as pg_sys::dsm_handle)
+//!         .expect("the segment was destroyed");
+//! }
+//! ```
+
+use crate::pg_sys;
+
+/// A mapping of a dynamic shared memory segment, `dsm_segment`
+///
+/// The segment is detached when this is dropped, and destroyed once every backend has detached from it. If it
+///  was created or attached to in a transaction, it is also detached at the end of the transaction, so it must
+///  not be used after it, unless `pin_mapping` is called.
+pub struct DsmSegment {
+    segment: *mut pg_sys::dsm_segment,
+}
+
+impl DsmSegment {
+    /// Creates a segment of the size, in bytes, and attaches to it, `dsm_create`
+    pub fn create(size: usize) -> Self {
+        let segment = unsafe { crate::guard_pg(|| pg_sys::dsm_create(size, 0)) };
+
+        Self { segment }
+    }
+
+    /// Attaches to the segment with the handle, `dsm_attach`, returning `None` if it has been destroyed
+    pub fn attach(handle: pg_sys::dsm_handle) -> Option<Self> {
+        let segment = unsafe { crate::guard_pg(|| pg_sys::dsm_attach(handle)) };
+
+        if segment.is_null() {
+            None
+        } else {
+            Some(Self { segment })
+        }
+    }
+
+    /// Returns the handle of the segment, `dsm_segment_handle`, which other backends attach to it with
+    pub fn handle(&self) -> pg_sys::dsm_handle {
+        unsafe { crate::guard_pg(|| pg_sys::dsm_segment_handle(self.segment)) }
+    }
+
+    /// Returns the size of the segment in bytes, `dsm_segment_map_length`
+    pub fn size(&self) -> usize {
+        unsafe { crate::guard_pg(|| pg_sys::dsm_segment_map_length(self.segment)) }
+    }
+
+    /// Returns the address the segment is mapped at in this backend, `dsm_segment_address`, this differs between
+    ///  backends, and access to it must be synchronized between them
+    pub fn as_ptr(&self) -> *mut u8 {
+        unsafe { crate::guard_pg(|| pg_sys::dsm_segment_address(self.segment)) as *mut u8 }
+    }
+
+    /// Keeps the segment attached until it is dropped, or the session ends, rather than the end of the transaction,
+    ///  `dsm_pin_mapping`
+    pub fn pin_mapping(&self) {
+        unsafe { crate::guard_pg(|| pg_sys::dsm_pin_mapping(self.segment)) };
+    }
+
+    /// Returns the `dsm_segment` this wraps
+    pub(crate) fn as_pg(&self) -> *mut pg_sys::dsm_segment {
+        self.segment
+    }
+}
+
+impl Drop for DsmSegment {
+    fn drop(&mut self) {
+        // the abort of the transaction detaches the segment after an ERROR, which may be why this is unwinding,
+        //  a pinned mapping stays attached until the session ends
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe { crate::guard_pg(|| pg_sys::dsm_detach(self.segment)) };
+    }
+}
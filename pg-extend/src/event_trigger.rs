@@ -0,0 +1,226 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for event trigger functions, see `#[pg_event_trigger]` in pg-extern-attr.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::pg_error::PgError;
+use crate::pg_sys;
+use crate::spi::Spi;
+
+/// The event which fired the event trigger
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventTriggerEvent {
+    /// `ddl_command_start`
+    DdlCommandStart,
+    /// `ddl_command_end`, `EventTrigger::ddl_commands` returns the commands that were executed
+    DdlCommandEnd,
+    /// `sql_drop`, `EventTrigger::dropped_objects` returns the objects that were dropped
+    SqlDrop,
+    /// `table_rewrite`
+    TableRewrite,
+    /// An event not known to this library
+    Other(String),
+}
+
+impl From<&str> for EventTriggerEvent {
+    fn from(event: &str) -> Self {
+        match event {
+            "ddl_command_start" => EventTriggerEvent::DdlCommandStart,
+            "ddl_command_end" => EventTriggerEvent::DdlCommandEnd,
+            "sql_drop" => EventTriggerEvent::SqlDrop,
+            "table_rewrite" => EventTriggerEvent::TableRewrite,
+            _ => EventTriggerEvent::Other(event.to_string()),
+        }
+    }
+}
+
+/// A command executed by the DDL statement, a row of `pg_event_trigger_ddl_commands()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DdlCommand {
+    /// The OID of the object, `objid`
+    pub object_id: pg_sys::Oid,
+    /// The command tag, e.g. `CREATE TABLE`
+    pub command_tag: String,
+    /// The type of the object, e.g. `table`
+    pub object_type: String,
+    /// The schema of the object, if any
+    pub schema_name: Option<String>,
+    /// The schema qualified name of the object, e.g. `public.people`
+    pub object_identity: String,
+    /// True if the command is part of an extension script
+    pub in_extension: bool,
+}
+
+/// An object dropped by the DDL statement, a row of `pg_event_trigger_dropped_objects()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DroppedObject {
+    /// The OID of the object, `objid`
+    pub object_id: pg_sys::Oid,
+    /// True if the object was named in the `DROP` statement, rather than dropped as a dependency
+    pub original: bool,
+    /// True if the object was dropped as a normal dependency, e.g. not with `CASCADE`
+    pub normal: bool,
+    /// True if the object was temporary
+    pub is_temporary: bool,
+    /// The type of the object, e.g. `table`
+    pub object_type: String,
+    /// The schema of the object, if any
+    pub schema_name: Option<String>,
+    /// The name of the object, if it is unique in its schema
+    pub object_name: Option<String>,
+    /// The schema qualified name of the object, e.g. `public.people`
+    pub object_identity: String,
+}
+
+/// The data passed to an event trigger function, wraps `EventTriggerData`
+pub struct EventTrigger<'a> {
+    data: &'a pg_sys::EventTriggerData,
+}
+
+impl<'a> EventTrigger<'a> {
+    /// Returns the event trigger data of the function call, or an error if the function was not called as an
+    ///  event trigger, i.e. `CALLED_AS_EVENT_TRIGGER`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_func_call_info(
+        func_call_info: pg_sys::FunctionCallInfo,
+    ) -> Result<Self, PgError> {
+        let context = func_call_info
+            .as_ref()
+            .expect("func_call_info was unexpectedly NULL")
+            .context;
+
+        if context.is_null() || (*context).type_ != pg_sys::NodeTag_T_EventTriggerData {
+            return Err(
+                PgError::new("event trigger function called, but not by an event trigger")
+                    .with_sqlstate("39P03"),
+            );
+        }
+
+        Ok(EventTrigger {
+            data: &*(context as *const pg_sys::EventTriggerData),
+        })
+    }
+
+    /// The event which fired the event trigger
+    pub fn event(&self) -> EventTriggerEvent {
+        unsafe { EventTriggerEvent::from(to_str(self.data.event)) }
+    }
+
+    /// The command tag of the statement, e.g. `CREATE TABLE`
+    pub fn tag(&self) -> String {
+        unsafe { to_str(self.data.tag).to_string() }
+    }
+
+    /// The `NodeTag` of the parse tree of the statement, e.g. `pg_sys::NodeTag_T_CreateStmt`
+    pub fn parse_tree_tag(&self) -> Option<pg_sys::NodeTag> {
+        unsafe { self.data.parsetree.as_ref().map(|node| node.type_) }
+    }
+
+    /// The commands executed by the statement, only available to `ddl_command_end` event triggers
+    pub fn ddl_commands(&self) -> Result<Vec<DdlCommand>, PgError> {
+        self.expect_event(EventTriggerEvent::DdlCommandEnd, "pg_event_trigger_ddl_commands")?;
+
+        let spi = Spi::connect_read_only()?;
+        let rows = spi.select(
+            "SELECT objid::int8, command_tag, object_type, schema_name, object_identity, in_extension \
+             FROM pg_event_trigger_ddl_commands()",
+            &[],
+        )?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(DdlCommand {
+                    object_id: row.get::<i64>("objid")? as pg_sys::Oid,
+                    command_tag: row.get("command_tag")?,
+                    object_type: row.get("object_type")?,
+                    schema_name: row.get("schema_name")?,
+                    object_identity: row.get("object_identity")?,
+                    in_extension: row.get("in_extension")?,
+                })
+            })
+            .collect()
+    }
+
+    /// The objects dropped by the statement, only available to `sql_drop` event triggers
+    pub fn dropped_objects(&self) -> Result<Vec<DroppedObject>, PgError> {
+        self.expect_event(EventTriggerEvent::SqlDrop, "pg_event_trigger_dropped_objects")?;
+
+        let spi = Spi::connect_read_only()?;
+        let rows = spi.select(
+            "SELECT objid::int8, original, normal, is_temporary, object_type, schema_name, object_name, \
+             object_identity FROM pg_event_trigger_dropped_objects()",
+            &[],
+        )?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(DroppedObject {
+                    object_id: row.get::<i64>("objid")? as pg_sys::Oid,
+                    original: row.get("original")?,
+                    normal: row.get("normal")?,
+                    is_temporary: row.get("is_temporary")?,
+                    object_type: row.get("object_type")?,
+                    schema_name: row.get("schema_name")?,
+                    object_name: row.get("object_name")?,
+                    object_identity: row.get("object_identity")?,
+                })
+            })
+            .collect()
+    }
+
+    fn expect_event(&self, event: EventTriggerEvent, function: &str) -> Result<(), PgError> {
+        if self.event() != event {
+            return Err(PgError::new(format!(
+                "{} can not be called in a {:?} event trigger",
+                function,
+                self.event()
+            ))
+            .with_sqlstate("39P03"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Calls the event trigger function, this is used by the functions generated with `#[pg_event_trigger]`.
+///
+/// An error is raised as an `ERROR`, as is any panic, which aborts the statement.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn call_event_trigger<F>(
+    name: &str,
+    func_call_info: pg_sys::FunctionCallInfo,
+    event_trigger_fn: F,
+) -> pg_sys::Datum
+where
+    F: FnOnce(&EventTrigger) -> Result<(), PgError>,
+{
+    // guard the Postgres process against the panic, and give us an oportunity to cleanup, an ERROR unwinds
+    //  the event trigger function before its longjmp is continued by raise_panic
+    let result = crate::catch_unwind_pg(|| -> Result<(), PgError> {
+        let event_trigger = EventTrigger::from_func_call_info(func_call_info)?;
+        event_trigger_fn(&event_trigger)
+    });
+
+    // the error is raised through raise_panic, which is safe within an outer catch_unwind_pg, e.g. when the DDL
+    //  is executed through SPI from a `#[pg_extern]` function
+    match result {
+        Ok(Ok(())) => 0 as pg_sys::Datum,
+        Ok(Err(err)) => crate::raise_panic(name, Box::new(err)),
+        Err(err) => crate::raise_panic(name, err),
+    }
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> &'a str {
+    if value.is_null() {
+        return "";
+    }
+
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
@@ -0,0 +1,638 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for defining custom configuration variables, GUCs, e.g. `myext.batch_size`, which can be set with
+//!  `SET`, in `postgresql.conf` or with `ALTER SYSTEM`.
+//!
+//! The value of a GUC is stored in a `static` `GucSetting`, which is defined with `Guc`, usually from the `init`
+//!  function of `pg_magic!`, and can then be read with `GucSetting::get` from any function.
+//!
+//! ```rust,ignore
+//! static BATCH_SIZE: GucSetting<i32> = GucSetting::new(100);
+//!
+//! fn init() {
+//!     Guc::new("myext.batch_size", "The number of rows written at a time")
+//!         .range(1, 10_000)
+//!         .define(&BATCH_SIZE);
+//! }
+//!
+//! #[pg_extern]
+//! fn batch_size() -> i32 {
+//!     BATCH_SIZE.get()
+//! }
+//! ```
+
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::mem::MaybeUninit;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::pg_bool::PgBool;
+use crate::pg_sys;
+use crate::JumpContext;
+
+type CheckHook<T> =
+    unsafe extern "C" fn(*mut <T as GucValue>::Raw, *mut *mut c_void, pg_sys::GucSource) -> PgBool;
+type AssignHook<T> = unsafe extern "C" fn(<T as GucValue>::AssignRaw, *mut c_void);
+
+/// Where a GUC can be set, `GucContext`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GucContext {
+    /// Only at server start, in `postgresql.conf` or on the command line, `PGC_POSTMASTER`. This requires the
+    ///  library to be in `shared_preload_libraries`.
+    Postmaster,
+    /// In `postgresql.conf`, and reloaded on `SIGHUP`, `PGC_SIGHUP`
+    Sighup,
+    /// When a session starts, by superusers, `PGC_SU_BACKEND`
+    SuBackend,
+    /// When a session starts, `PGC_BACKEND`
+    Backend,
+    /// With `SET`, by superusers, `PGC_SUSET`
+    Suset,
+    /// With `SET`, by any user, `PGC_USERSET`
+    Userset,
+}
+
+impl From<GucContext> for pg_sys::GucContext {
+    fn from(context: GucContext) -> Self {
+        match context {
+            GucContext::Postmaster => pg_sys::GucContext_PGC_POSTMASTER,
+            GucContext::Sighup => pg_sys::GucContext_PGC_SIGHUP,
+            GucContext::SuBackend => pg_sys::GucContext_PGC_SU_BACKEND,
+            GucContext::Backend => pg_sys::GucContext_PGC_BACKEND,
+            GucContext::Suset => pg_sys::GucContext_PGC_SUSET,
+            GucContext::Userset => pg_sys::GucContext_PGC_USERSET,
+        }
+    }
+}
+
+/// The unit of a numeric GUC, which allows it to be set with a unit, e.g. `SET myext.timeout = '5s'`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GucUnit {
+    /// `GUC_UNIT_BYTE`
+    #[cfg(not(any(postgres9, postgres10)))]
+    Bytes,
+    /// `GUC_UNIT_KB`
+    Kilobytes,
+    /// `GUC_UNIT_MB`
+    #[cfg(not(any(postgres9, postgres10)))]
+    Megabytes,
+    /// Blocks of `BLCKSZ`, `GUC_UNIT_BLOCKS`
+    Blocks,
+    /// WAL blocks of `XLOG_BLCKSZ`, `GUC_UNIT_XBLOCKS`
+    XlogBlocks,
+    /// `GUC_UNIT_MS`
+    Milliseconds,
+    /// `GUC_UNIT_S`
+    Seconds,
+    /// `GUC_UNIT_MIN`
+    Minutes,
+}
+
+impl GucUnit {
+    fn flag(self) -> c_int {
+        let flag = match self {
+            #[cfg(not(any(postgres9, postgres10)))]
+            GucUnit::Bytes => pg_sys::GUC_UNIT_BYTE,
+            GucUnit::Kilobytes => pg_sys::GUC_UNIT_KB,
+            #[cfg(not(any(postgres9, postgres10)))]
+            GucUnit::Megabytes => pg_sys::GUC_UNIT_MB,
+            GucUnit::Blocks => pg_sys::GUC_UNIT_BLOCKS,
+            GucUnit::XlogBlocks => pg_sys::GUC_UNIT_XBLOCKS,
+            GucUnit::Milliseconds => pg_sys::GUC_UNIT_MS,
+            GucUnit::Seconds => pg_sys::GUC_UNIT_S,
+            GucUnit::Minutes => pg_sys::GUC_UNIT_MIN,
+        };
+
+        flag as c_int
+    }
+}
+
+/// A Rust enum which can be the value of a GUC, which is set by the names of its values
+///
+/// ```rust,ignore
+/// #[derive(Clone, Copy, PartialEq)]
+/// enum Format {
+///     Text,
+///     Json,
+/// }
+///
+/// impl GucEnum for Format {
+///     const VALUES: &'static [(&'static str, Self)] =
+///         &[("text", Format::Text), ("json", Format::Json), ("plain", Format::Text)];
+/// }
+/// ```
+pub trait GucEnum: Copy + PartialEq + 'static {
+    /// The names of the values, names are case-insensitive. A value may have more than one name, the first is
+    ///  the one shown by `SHOW`, and the others are hidden from the list of allowed values in errors.
+    const VALUES: &'static [(&'static str, Self)];
+}
+
+/// A type which can be the value of a GUC, this is implemented for `i32`, `bool`, `f64`, `Option<CString>` and
+///  `GucEnum`s
+pub trait GucValue: Sized + 'static {
+    /// The value given to `GucSetting::new`, the value of the GUC until it is set
+    type Boot: Copy;
+    /// The C variable Postgres stores the value in
+    type Raw: Copy;
+    /// The value passed to an assign hook
+    type AssignRaw: Copy;
+
+    /// Converts the default value
+    fn from_boot(boot: Self::Boot) -> Self;
+
+    /// Converts the value stored by Postgres
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn from_raw(raw: Self::Raw) -> Self;
+
+    /// Converts the value passed to an assign hook
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn from_assign_raw(raw: Self::AssignRaw) -> Self;
+
+    /// Defines the GUC, `DefineCustom*Variable`, with Postgres storing its value in `variable`
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn define(guc: &Guc<Self>, variable: *mut Self::Raw, boot: Self::Boot);
+}
+
+/// A numeric value of a GUC, which can have a range and a unit, this is implemented for `i32` and `f64`
+pub trait GucNumber: GucValue {}
+
+impl GucNumber for i32 {}
+impl GucNumber for f64 {}
+
+impl GucValue for i32 {
+    type Boot = i32;
+    type Raw = c_int;
+    type AssignRaw = c_int;
+
+    fn from_boot(boot: i32) -> Self {
+        boot
+    }
+
+    unsafe fn from_raw(raw: c_int) -> Self {
+        raw
+    }
+
+    unsafe fn from_assign_raw(raw: c_int) -> Self {
+        raw
+    }
+
+    unsafe fn define(guc: &Guc<Self>, variable: *mut c_int, boot: i32) {
+        let (min, max) = guc.range.unwrap_or((i32::MIN, i32::MAX));
+
+        crate::guard_pg(|| {
+            pg_sys::DefineCustomIntVariable(
+                guc.name.as_ptr(),
+                guc.short_desc.as_ptr(),
+                guc.long_desc_ptr(),
+                variable,
+                boot,
+                min,
+                max,
+                guc.context.into(),
+                guc.flags,
+                guc.check_hook,
+                guc.assign_hook,
+                None,
+            )
+        });
+    }
+}
+
+impl GucValue for bool {
+    type Boot = bool;
+    type Raw = PgBool;
+    type AssignRaw = PgBool;
+
+    fn from_boot(boot: bool) -> Self {
+        boot
+    }
+
+    unsafe fn from_raw(raw: PgBool) -> Self {
+        pgbool!(raw)
+    }
+
+    unsafe fn from_assign_raw(raw: PgBool) -> Self {
+        pgbool!(raw)
+    }
+
+    unsafe fn define(guc: &Guc<Self>, variable: *mut PgBool, boot: bool) {
+        crate::guard_pg(|| {
+            pg_sys::DefineCustomBoolVariable(
+                guc.name.as_ptr(),
+                guc.short_desc.as_ptr(),
+                guc.long_desc_ptr(),
+                variable,
+                pgbool!(boot),
+                guc.context.into(),
+                guc.flags,
+                guc.check_hook,
+                guc.assign_hook,
+                None,
+            )
+        });
+    }
+}
+
+impl GucValue for f64 {
+    type Boot = f64;
+    type Raw = f64;
+    type AssignRaw = f64;
+
+    fn from_boot(boot: f64) -> Self {
+        boot
+    }
+
+    unsafe fn from_raw(raw: f64) -> Self {
+        raw
+    }
+
+    unsafe fn from_assign_raw(raw: f64) -> Self {
+        raw
+    }
+
+    unsafe fn define(guc: &Guc<Self>, variable: *mut f64, boot: f64) {
+        let (min, max) = guc.range.unwrap_or((f64::MIN, f64::MAX));
+
+        crate::guard_pg(|| {
+            pg_sys::DefineCustomRealVariable(
+                guc.name.as_ptr(),
+                guc.short_desc.as_ptr(),
+                guc.long_desc_ptr(),
+                variable,
+                boot,
+                min,
+                max,
+                guc.context.into(),
+                guc.flags,
+                guc.check_hook,
+                guc.assign_hook,
+                None,
+            )
+        });
+    }
+}
+
+impl GucValue for Option<CString> {
+    type Boot = Option<&'static str>;
+    type Raw = *mut c_char;
+    type AssignRaw = *const c_char;
+
+    fn from_boot(boot: Option<&'static str>) -> Self {
+        boot.map(|boot| CString::new(boot).expect("GUC values do not contain NULL bytes"))
+    }
+
+    unsafe fn from_raw(raw: *mut c_char) -> Self {
+        Self::from_assign_raw(raw)
+    }
+
+    unsafe fn from_assign_raw(raw: *const c_char) -> Self {
+        if raw.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(raw).to_owned())
+        }
+    }
+
+    unsafe fn define(guc: &Guc<Self>, variable: *mut *mut c_char, boot: Option<&'static str>) {
+        // Postgres keeps the pointer to the default value, for RESET
+        let boot =
+            Self::from_boot(boot).map_or(ptr::null(), |boot| CString::into_raw(boot) as *const _);
+
+        crate::guard_pg(|| {
+            pg_sys::DefineCustomStringVariable(
+                guc.name.as_ptr(),
+                guc.short_desc.as_ptr(),
+                guc.long_desc_ptr(),
+                variable,
+                boot,
+                guc.context.into(),
+                guc.flags,
+                guc.check_hook,
+                guc.assign_hook,
+                None,
+            )
+        });
+    }
+}
+
+/// The value of an enum GUC is the index of its name in `GucEnum::VALUES`
+impl<E: GucEnum> GucValue for E {
+    type Boot = E;
+    type Raw = c_int;
+    type AssignRaw = c_int;
+
+    fn from_boot(boot: E) -> Self {
+        boot
+    }
+
+    unsafe fn from_raw(raw: c_int) -> Self {
+        E::VALUES[raw as usize].1
+    }
+
+    unsafe fn from_assign_raw(raw: c_int) -> Self {
+        E::VALUES[raw as usize].1
+    }
+
+    unsafe fn define(guc: &Guc<Self>, variable: *mut c_int, boot: E) {
+        let boot = E::VALUES
+            .iter()
+            .position(|(_, value)| *value == boot)
+            .expect("the default value of the GUC is not in GucEnum::VALUES");
+
+        // Postgres keeps the pointer to the options, for the life of the backend
+        let options = E::VALUES
+            .iter()
+            .enumerate()
+            .map(|(i, (name, value))| pg_sys::config_enum_entry {
+                name: CString::into_raw(
+                    CString::new(*name).expect("GUC enum names do not contain NULL bytes"),
+                ) as *const _,
+                val: i as c_int,
+                hidden: pgbool!(E::VALUES[..i].iter().any(|(_, other)| other == value)),
+            })
+            .chain(Some(pg_sys::config_enum_entry {
+                name: ptr::null(),
+                val: 0,
+                hidden: pgbool!(false),
+            }))
+            .collect::<Vec<_>>();
+        let options = Box::leak(options.into_boxed_slice());
+
+        crate::guard_pg(|| {
+            pg_sys::DefineCustomEnumVariable(
+                guc.name.as_ptr(),
+                guc.short_desc.as_ptr(),
+                guc.long_desc_ptr(),
+                variable,
+                boot as c_int,
+                options.as_ptr(),
+                guc.context.into(),
+                guc.flags,
+                guc.check_hook,
+                guc.assign_hook,
+                None,
+            )
+        });
+    }
+}
+
+/// The value of a GUC, which must be a `static`, defined with `Guc::define`
+///
+/// Postgres backends are single threaded, the value must only be read from the thread Postgres called into the
+///  library on.
+pub struct GucSetting<T: GucValue> {
+    boot: T::Boot,
+    defined: Cell<bool>,
+    value: UnsafeCell<MaybeUninit<T::Raw>>,
+}
+
+// Postgres only reads and writes the value from the backend's thread
+unsafe impl<T: GucValue> Sync for GucSetting<T> {}
+
+impl<T: GucValue> GucSetting<T> {
+    /// Creates the setting with its default value, which is also its value until it is defined
+    pub const fn new(boot: T::Boot) -> Self {
+        GucSetting {
+            boot,
+            defined: Cell::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns the current value of the GUC
+    pub fn get(&self) -> T {
+        if self.defined.get() {
+            unsafe { T::from_raw((*self.value.get()).assume_init()) }
+        } else {
+            T::from_boot(self.boot)
+        }
+    }
+}
+
+/// The definition of a GUC, `DefineCustom*Variable`
+///
+/// The name must be prefixed with the name of the extension, e.g. `myext.batch_size`. By default the GUC can be
+///  set by any user, `GucContext::Userset`.
+pub struct Guc<T: GucValue> {
+    name: CString,
+    short_desc: CString,
+    long_desc: Option<CString>,
+    context: GucContext,
+    flags: c_int,
+    range: Option<(T, T)>,
+    check_hook: Option<CheckHook<T>>,
+    assign_hook: Option<AssignHook<T>>,
+    hooks: Vec<(TypeId, Rc<dyn Any>)>,
+}
+
+impl<T: GucValue> Guc<T> {
+    /// Starts the definition of a GUC, with the description shown by `SHOW ALL` and in `pg_settings`
+    pub fn new(name: &str, short_description: &str) -> Self {
+        Guc {
+            name: CString::new(name).expect("GUC names do not contain NULL bytes"),
+            short_desc: CString::new(short_description)
+                .expect("descriptions do not contain NULL bytes"),
+            long_desc: None,
+            context: GucContext::Userset,
+            flags: 0,
+            range: None,
+            check_hook: None,
+            assign_hook: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Sets the longer description of the GUC, shown in `pg_settings`
+    pub fn long_description(mut self, long_description: &str) -> Self {
+        self.long_desc =
+            Some(CString::new(long_description).expect("descriptions do not contain NULL bytes"));
+        self
+    }
+
+    /// Sets where the GUC can be set
+    pub fn context(mut self, context: GucContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Sets the closure called to validate a new value of the GUC before it is set, an `Err` rejects the value
+    ///  with the message as the detail of the error
+    ///
+    /// A panic in the closure also rejects the value. Each closure can only be used as the hook of one GUC.
+    pub fn check_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&T) -> Result<(), String> + 'static,
+    {
+        self.check_hook = Some(call_check_hook::<T, F>);
+        self.hooks
+            .push((TypeId::of::<F>(), Rc::new(hook) as Rc<dyn Any>));
+        self
+    }
+
+    /// Sets the closure called with the new value of the GUC when it is set, e.g. to update state derived from
+    ///  it
+    ///
+    /// The value can not be rejected at this point, a panic in the closure is logged as a `WARNING`. Each
+    ///  closure can only be used as the hook of one GUC.
+    pub fn assign_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&T) + 'static,
+    {
+        self.assign_hook = Some(call_assign_hook::<T, F>);
+        self.hooks
+            .push((TypeId::of::<F>(), Rc::new(hook) as Rc<dyn Any>));
+        self
+    }
+
+    /// Defines the GUC, with Postgres storing its value in the setting
+    ///
+    /// This should be called from the `init` function of `pg_magic!`. A value set before the library was loaded,
+    ///  e.g. in `postgresql.conf`, is then checked and assigned.
+    ///
+    /// # Panics
+    ///
+    /// If the setting, or a hook closure, has already been used for a GUC
+    pub fn define(self, setting: &'static GucSetting<T>) {
+        assert!(
+            !setting.defined.get(),
+            "the GucSetting has already been defined"
+        );
+
+        HOOKS.with(|hooks| {
+            let mut hooks = hooks.borrow_mut();
+            for (type_id, hook) in &self.hooks {
+                let replaced = hooks.insert(*type_id, Rc::clone(hook));
+                assert!(
+                    replaced.is_none(),
+                    "the hook closure is already used for a GUC"
+                );
+            }
+        });
+
+        unsafe { T::define(&self, (*setting.value.get()).as_mut_ptr(), setting.boot) };
+        setting.defined.set(true);
+    }
+
+    fn long_desc_ptr(&self) -> *const c_char {
+        self.long_desc
+            .as_ref()
+            .map_or(ptr::null(), |desc| desc.as_ptr())
+    }
+}
+
+impl<T: GucNumber> Guc<T> {
+    /// Sets the minimum and maximum values of the GUC, the default value must be within them
+    pub fn range(mut self, min: T, max: T) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Sets the unit of the value, it can then be set in other units, e.g. `'1min'` for a GUC in seconds
+    pub fn unit(mut self, unit: GucUnit) -> Self {
+        self.flags |= unit.flag();
+        self
+    }
+}
+
+/// Reports a `WARNING` for every GUC with the prefix that is set but not defined by the library,
+///  `EmitWarningsOnPlaceholders`, e.g. a misspelled `myext.bacth_size` in `postgresql.conf`
+///
+/// This should be called after all of the GUCs of the library are defined.
+pub fn emit_warnings_on_placeholders(prefix: &str) {
+    // Postgres keeps the prefix, for reserving it against later placeholders
+    let prefix = CString::new(prefix).expect("GUC prefixes do not contain NULL bytes");
+    let prefix = CString::into_raw(prefix);
+
+    unsafe { crate::guard_pg(|| pg_sys::EmitWarningsOnPlaceholders(prefix)) };
+}
+
+thread_local! {
+    /// The hook closures of the GUCs, by the type of the closure which is what the hook functions are generic over
+    static HOOKS: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Returns the hook closure of type `F`
+fn find_hook<F: 'static>() -> Rc<dyn Any> {
+    HOOKS.with(|hooks| {
+        let hooks = hooks.borrow();
+        let hook = hooks
+            .get(&TypeId::of::<F>())
+            .expect("GUC hook called before it was defined, this is a bug in pg-extend-rs");
+
+        Rc::clone(hook)
+    })
+}
+
+unsafe extern "C" fn call_check_hook<T, F>(
+    newval: *mut T::Raw,
+    _extra: *mut *mut c_void,
+    _source: pg_sys::GucSource,
+) -> PgBool
+where
+    T: GucValue,
+    F: Fn(&T) -> Result<(), String> + 'static,
+{
+    let hook = find_hook::<F>();
+    let hook = hook
+        .downcast_ref::<F>()
+        .expect("GUC hook has the wrong type");
+
+    let msg = match crate::catch_unwind_pg(|| hook(&T::from_raw(*newval))) {
+        Ok(Ok(())) => return pgbool!(true),
+        Ok(Err(msg)) => msg,
+        Err(err) => {
+            if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+                jump_context.continue_longjmp();
+            }
+
+            match crate::panic_message(&*err) {
+                Some(msg) => format!("panic executing Rust 'check_hook': {}", msg),
+                None => "panic executing Rust 'check_hook'".to_string(),
+            }
+        }
+    };
+
+    // the message is the detail of the error Postgres raises for the rejected value
+    let msg =
+        CString::new(msg.replace('\0', "")).expect("NULL bytes were removed from the message");
+    pg_sys::GUC_check_errdetail_string =
+        pg_sys::MemoryContextStrdup(pg_sys::ErrorContext, msg.as_ptr());
+
+    pgbool!(false)
+}
+
+unsafe extern "C" fn call_assign_hook<T, F>(newval: T::AssignRaw, _extra: *mut c_void)
+where
+    T: GucValue,
+    F: Fn(&T) + 'static,
+{
+    let hook = find_hook::<F>();
+    let hook = hook
+        .downcast_ref::<F>()
+        .expect("GUC hook has the wrong type");
+
+    let err = match crate::catch_unwind_pg(|| hook(&T::from_assign_raw(newval))) {
+        Ok(()) => return,
+        Err(err) => err,
+    };
+
+    if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+        jump_context.continue_longjmp();
+    }
+
+    // an assign hook must not fail, the value has already been accepted
+    if let Some(msg) = crate::panic_message(&*err) {
+        crate::warn!("panic executing Rust 'assign_hook': {}", msg);
+    } else {
+        crate::warn!("panic executing Rust 'assign_hook'");
+    }
+}
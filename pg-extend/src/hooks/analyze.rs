@@ -0,0 +1,130 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The `post_parse_analyze_hook`, which is called with the tree of every query once it has been parsed and
+//!  analyzed, e.g. to fingerprint it.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::rc::Rc;
+
+use crate::pg_sys;
+
+type PostParseAnalyzeHook = dyn Fn(&ParseState, &mut pg_sys::Query, &PrevPostParseAnalyze);
+
+thread_local! {
+    /// The installed hooks, the last is called first
+    static POST_PARSE_ANALYZE_HOOKS: RefCell<Vec<Rc<PostParseAnalyzeHook>>> = RefCell::new(Vec::new());
+}
+
+/// The `post_parse_analyze_hook` which was installed before the first of ours
+static mut PREV_POST_PARSE_ANALYZE_HOOK: pg_sys::post_parse_analyze_hook_type = None;
+
+/// The state of the analysis of a query, `ParseState`
+pub struct ParseState {
+    pstate: *mut pg_sys::ParseState,
+}
+
+impl ParseState {
+    /// Returns the text the query was parsed from, `p_sourcetext`, which may contain other queries, or be empty if
+    ///  it is not known
+    pub fn source_text(&self) -> &str {
+        let source_text = unsafe { (*self.pstate).p_sourcetext };
+        if source_text.is_null() {
+            return "";
+        }
+
+        unsafe { CStr::from_ptr(source_text) }
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    /// Returns the `ParseState` this wraps
+    pub fn as_pg(&self) -> *mut pg_sys::ParseState {
+        self.pstate
+    }
+}
+
+/// The hooks a hook chains to, the hook installed before it, e.g. of `pg_stat_statements`
+pub struct PrevPostParseAnalyze {
+    /// The number of our hooks installed before this one
+    index: usize,
+}
+
+impl PrevPostParseAnalyze {
+    /// Calls the previous hooks with the query
+    pub fn analyze(&self, pstate: &ParseState, query: &mut pg_sys::Query) {
+        unsafe { post_parse_analyze_at(self.index, pstate, query) }
+    }
+}
+
+/// Installs the hook into `post_parse_analyze_hook`, which is called with the state of the analysis, the query
+///  and the previous hooks, which it should chain to
+///
+/// The previous hooks should usually be called first, e.g. so that the `queryId` set by `pg_stat_statements` is
+///  seen.
+///
+/// ```rust,ignore
+/// fn init() {
+///     hooks::install_post_parse_analyze_hook(|pstate, query, prev| {
+///         prev.analyze(pstate, query);
+///
+///         let query = QueryView::new(query);
+///         for oid in query.range_table().filter_map(|rte| rte.relation_oid()) {
+///             notice!("the query reads relation {}", oid);
+///         }
+///     });
+/// }
+/// ```
+pub fn install_post_parse_analyze_hook<F>(hook: F)
+where
+    F: Fn(&ParseState, &mut pg_sys::Query, &PrevPostParseAnalyze) + 'static,
+{
+    POST_PARSE_ANALYZE_HOOKS.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+
+        if hooks.is_empty() {
+            unsafe {
+                PREV_POST_PARSE_ANALYZE_HOOK = pg_sys::post_parse_analyze_hook;
+                pg_sys::post_parse_analyze_hook = Some(post_parse_analyze);
+            }
+        }
+
+        hooks.push(Rc::new(hook));
+    });
+}
+
+/// Calls the `index`th of our hooks, the first being 1, or the previous hook if it is zero
+unsafe fn post_parse_analyze_at(index: usize, pstate: &ParseState, query: &mut pg_sys::Query) {
+    if index == 0 {
+        if let Some(prev_hook) = PREV_POST_PARSE_ANALYZE_HOOK {
+            crate::guard_pg(|| prev_hook(pstate.pstate, query));
+        }
+
+        return;
+    }
+
+    // the hook is cloned out, so that a query can be analyzed by the hook, e.g. with SPI
+    let hook = POST_PARSE_ANALYZE_HOOKS.with(|hooks| Rc::clone(&hooks.borrow()[index - 1]));
+    let prev = PrevPostParseAnalyze { index: index - 1 };
+
+    hook(pstate, query, &prev)
+}
+
+/// The `post_parse_analyze_hook`, calls the last installed hook
+unsafe extern "C" fn post_parse_analyze(
+    pstate: *mut pg_sys::ParseState,
+    query: *mut pg_sys::Query,
+) {
+    super::call_hook("post_parse_analyze_hook", || {
+        let pstate = ParseState { pstate };
+        let query = query.as_mut().expect("the analyzed query was NULL");
+        let index = POST_PARSE_ANALYZE_HOOKS.with(|hooks| hooks.borrow().len());
+
+        post_parse_analyze_at(index, &pstate, query)
+    })
+}
@@ -0,0 +1,220 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The `emit_log_hook`, which is called with every message before it is written to the server log and sent to
+//!  the client, e.g. to ship the messages elsewhere, or to suppress or redact them.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use std::io::{self, Write};
+use std::os::raw::{c_char, c_int};
+use std::rc::Rc;
+
+use crate::pg_error::{unpack_sqlstate, CaughtPgError};
+use crate::pg_sys;
+use crate::JumpContext;
+
+type EmitLogHook = dyn Fn(&mut ErrorDataView);
+
+thread_local! {
+    /// The installed hooks, the last is called first
+    static EMIT_LOG_HOOKS: RefCell<Vec<Rc<EmitLogHook>>> = RefCell::new(Vec::new());
+
+    /// True while the hooks are called, messages logged by them do not call them again
+    // a const initializer requires Rust 1.59
+    #[allow(clippy::missing_const_for_thread_local)]
+    static IN_EMIT_LOG_HOOKS: Cell<bool> = Cell::new(false);
+}
+
+/// The `emit_log_hook` which was installed before the first of ours
+static mut PREV_EMIT_LOG_HOOK: pg_sys::emit_log_hook_type = None;
+
+/// The message being reported, `ErrorData`
+pub struct ErrorDataView<'a> {
+    edata: &'a mut pg_sys::ErrorData,
+}
+
+impl<'a> ErrorDataView<'a> {
+    /// Returns the level of the message, e.g. `pg_sys::WARNING`
+    pub fn elevel(&self) -> c_int {
+        self.edata.elevel
+    }
+
+    /// Returns the SQLSTATE code of the message, e.g. `42P01` for `undefined_table`
+    pub fn sqlstate(&self) -> String {
+        unpack_sqlstate(self.edata.sqlerrcode)
+    }
+
+    /// Returns the primary message
+    pub fn message(&self) -> Option<&str> {
+        unsafe { to_str(self.edata.message) }
+    }
+
+    /// Returns the detail of the message
+    pub fn detail(&self) -> Option<&str> {
+        unsafe { to_str(self.edata.detail) }
+    }
+
+    /// Returns the name of the source file the message was reported from
+    pub fn filename(&self) -> Option<&str> {
+        unsafe { to_str(self.edata.filename) }
+    }
+
+    /// Returns the line of the source file the message was reported from
+    pub fn lineno(&self) -> c_int {
+        self.edata.lineno
+    }
+
+    /// Returns the name of the function the message was reported from
+    pub fn funcname(&self) -> Option<&str> {
+        unsafe { to_str(self.edata.funcname) }
+    }
+
+    /// Returns true if the message will be written to the server log
+    pub fn output_to_server(&self) -> bool {
+        pgbool!(self.edata.output_to_server)
+    }
+
+    /// Sets whether the message is written to the server log, e.g. false to suppress it
+    pub fn set_output_to_server(&mut self, output_to_server: bool) {
+        self.edata.output_to_server = pgbool!(output_to_server);
+    }
+
+    /// Returns true if the message will be sent to the client
+    pub fn output_to_client(&self) -> bool {
+        pgbool!(self.edata.output_to_client)
+    }
+
+    /// Sets whether the message is sent to the client, e.g. false to suppress it
+    pub fn set_output_to_client(&mut self, output_to_client: bool) {
+        self.edata.output_to_client = pgbool!(output_to_client);
+    }
+
+    /// Replaces the primary message, which is written to the server log and sent to the client
+    pub fn set_message(&mut self, message: &str) {
+        self.edata.message = unsafe { error_strdup(message) };
+    }
+
+    /// Replaces the detail of the message, which is written to the server log and sent to the client
+    pub fn set_detail(&mut self, detail: Option<&str>) {
+        self.edata.detail = match detail {
+            Some(detail) => unsafe { error_strdup(detail) },
+            None => std::ptr::null_mut(),
+        };
+    }
+
+    /// Returns the `ErrorData` this wraps
+    pub fn as_pg(&mut self) -> &mut pg_sys::ErrorData {
+        self.edata
+    }
+}
+
+/// Installs the hook into `emit_log_hook`, which is called with every message which is written to the server log,
+///  before the hooks installed before it
+///
+/// The hook must not raise an `ERROR`, as it is called while a message, possibly an `ERROR`, is being reported. So
+///  a panic in the hook, or an `ERROR` raised by Postgres, is written to stderr, which is usually the server log,
+///  rather than raised. The hook is not called for messages logged by the hooks, which are only written to the
+///  server log and sent to the client.
+///
+/// ```rust,ignore
+/// fn init() {
+///     hooks::install_emit_log_hook(|edata| {
+///         if edata.message().map_or(false, |message| message.contains("password")) {
+///             edata.set_output_to_server(false);
+///         }
+///     });
+/// }
+/// ```
+pub fn install_emit_log_hook<F: Fn(&mut ErrorDataView) + 'static>(hook: F) {
+    EMIT_LOG_HOOKS.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+
+        if hooks.is_empty() {
+            unsafe {
+                PREV_EMIT_LOG_HOOK = pg_sys::emit_log_hook;
+                pg_sys::emit_log_hook = Some(emit_log);
+            }
+        }
+
+        hooks.push(Rc::new(hook));
+    });
+}
+
+/// Copies the string into `ErrorContext`, where the message is allocated
+unsafe fn error_strdup(value: &str) -> *mut c_char {
+    let value =
+        CString::new(value.replace('\0', "")).expect("NULL bytes were removed from the value");
+
+    crate::guard_pg(|| pg_sys::MemoryContextStrdup(pg_sys::ErrorContext, value.as_ptr()))
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    value
+        .as_ref()
+        .map(|value| CStr::from_ptr(value).to_str().unwrap_or_default())
+}
+
+/// Calls each of our hooks, this must not raise an `ERROR`, or unwind into Postgres
+unsafe fn call_emit_log_hooks(edata: &mut pg_sys::ErrorData) {
+    let len = EMIT_LOG_HOOKS.with(|hooks| hooks.borrow().len());
+
+    for index in (0..len).rev() {
+        // the hook is cloned out, so that it can install another
+        let hook = EMIT_LOG_HOOKS.with(|hooks| Rc::clone(&hooks.borrow()[index]));
+        let result = crate::catch_unwind_pg(|| hook(&mut ErrorDataView { edata: &mut *edata }));
+
+        let err = match result {
+            Ok(()) => continue,
+            Err(err) => err,
+        };
+
+        // an ERROR raised by Postgres, e.g. out of memory, is flushed and written to stderr like a panic, the
+        //  message being reported continues
+        if err.is::<JumpContext>() {
+            // the error must be copied out of ErrorContext, which the message is reported in
+            let memory_context = pg_sys::CurrentMemoryContext;
+            pg_sys::CurrentMemoryContext = pg_sys::TopMemoryContext;
+            let caught = CaughtPgError::take();
+            pg_sys::CurrentMemoryContext = memory_context;
+
+            let _ = writeln!(
+                io::stderr(),
+                "ERROR executing Rust 'emit_log_hook': {}",
+                caught
+            );
+            continue;
+        }
+
+        // logging the panic would call the hooks again, and an ERROR can not be raised while reporting one
+        let _ = match crate::panic_message(&*err) {
+            Some(msg) => writeln!(
+                io::stderr(),
+                "panic executing Rust 'emit_log_hook': {}",
+                msg
+            ),
+            None => writeln!(io::stderr(), "panic executing Rust 'emit_log_hook'"),
+        };
+    }
+}
+
+/// The `emit_log_hook`, calls our hooks, then the previous hook
+unsafe extern "C" fn emit_log(edata: *mut pg_sys::ErrorData) {
+    let in_hooks = IN_EMIT_LOG_HOOKS.with(|in_hooks| in_hooks.replace(true));
+
+    if !in_hooks {
+        if let Some(edata) = edata.as_mut() {
+            call_emit_log_hooks(edata);
+        }
+
+        IN_EMIT_LOG_HOOKS.with(|in_hooks| in_hooks.set(false));
+    }
+
+    if let Some(prev_hook) = PREV_EMIT_LOG_HOOK {
+        prev_hook(edata);
+    }
+}
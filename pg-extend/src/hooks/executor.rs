@@ -0,0 +1,425 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The executor hooks, `ExecutorStart_hook`, `ExecutorRun_hook`, `ExecutorFinish_hook` and `ExecutorEnd_hook`,
+//!  which are called to execute every planned query, e.g. to audit or time them.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::pg_alloc::PgAllocator;
+#[cfg(not(postgres9))]
+use crate::pg_bool::PgBool;
+use crate::pg_sys;
+
+thread_local! {
+    /// The installed hooks, the last is called first
+    static EXECUTOR_HOOKS: RefCell<Vec<Rc<dyn ExecutorHooks>>> = RefCell::new(Vec::new());
+}
+
+/// The executor hooks which were installed before the first of ours
+static mut PREV_EXECUTOR_START_HOOK: pg_sys::ExecutorStart_hook_type = None;
+static mut PREV_EXECUTOR_RUN_HOOK: pg_sys::ExecutorRun_hook_type = None;
+static mut PREV_EXECUTOR_FINISH_HOOK: pg_sys::ExecutorFinish_hook_type = None;
+static mut PREV_EXECUTOR_END_HOOK: pg_sys::ExecutorEnd_hook_type = None;
+
+/// The type of a query, `CmdType`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmdType {
+    /// `CMD_SELECT`
+    Select,
+    /// `CMD_UPDATE`
+    Update,
+    /// `CMD_INSERT`
+    Insert,
+    /// `CMD_DELETE`
+    Delete,
+    /// A utility command, e.g. `CREATE TABLE`, `CMD_UTILITY`
+    Utility,
+    /// A query which does nothing, e.g. a rule which is `DO INSTEAD NOTHING`, `CMD_NOTHING`
+    Nothing,
+    /// `CMD_UNKNOWN`
+    Unknown,
+}
+
+impl From<pg_sys::CmdType> for CmdType {
+    fn from(cmd_type: pg_sys::CmdType) -> Self {
+        match cmd_type {
+            pg_sys::CmdType_CMD_SELECT => CmdType::Select,
+            pg_sys::CmdType_CMD_UPDATE => CmdType::Update,
+            pg_sys::CmdType_CMD_INSERT => CmdType::Insert,
+            pg_sys::CmdType_CMD_DELETE => CmdType::Delete,
+            pg_sys::CmdType_CMD_UTILITY => CmdType::Utility,
+            pg_sys::CmdType_CMD_NOTHING => CmdType::Nothing,
+            _ => CmdType::Unknown,
+        }
+    }
+}
+
+/// The totals of the instrumentation of a query, see `QueryDesc::track_totals`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstrumentationTotals {
+    /// The time spent executing the query
+    pub total_time: Duration,
+    /// The number of tuples returned by the query
+    pub tuples: f64,
+    /// The number of shared buffers found in the buffer cache
+    pub shared_blks_hit: i64,
+    /// The number of shared blocks read from disk
+    pub shared_blks_read: i64,
+    /// The number of shared blocks dirtied
+    pub shared_blks_dirtied: i64,
+    /// The number of shared blocks written to disk
+    pub shared_blks_written: i64,
+    /// The number of temporary blocks read from disk
+    pub temp_blks_read: i64,
+    /// The number of temporary blocks written to disk
+    pub temp_blks_written: i64,
+}
+
+/// The query being executed, `QueryDesc`
+pub struct QueryDesc {
+    desc: *mut pg_sys::QueryDesc,
+}
+
+impl QueryDesc {
+    /// Returns the text of the query, which may contain other queries of the same statement, or be empty if it is
+    ///  not known
+    pub fn source_text(&self) -> &str {
+        let source_text = unsafe { (*self.desc).sourceText };
+        if source_text.is_null() {
+            return "";
+        }
+
+        unsafe { CStr::from_ptr(source_text) }
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    /// Returns the type of the query
+    pub fn operation(&self) -> CmdType {
+        unsafe { (*self.desc).operation }.into()
+    }
+
+    /// Returns the number of rows processed by the query so far, `es_processed`, this is zero before it is started
+    pub fn processed(&self) -> u64 {
+        let estate = unsafe { (*self.desc).estate };
+        if estate.is_null() {
+            return 0;
+        }
+
+        unsafe { (*estate).es_processed }
+    }
+
+    /// Tracks the totals of the instrumentation of the query, `totaltime`, this must be called in
+    ///  `ExecutorHooks::executor_start` after the previous executor start, as the query is then started
+    ///
+    /// # Panics
+    ///
+    /// If the query has not been started.
+    pub fn track_totals(&mut self) {
+        unsafe {
+            if !(*self.desc).totaltime.is_null() {
+                return;
+            }
+
+            let estate = (*self.desc).estate;
+            assert!(!estate.is_null(), "the query has not been started");
+
+            // the instrumentation must live as long as the query
+            let totaltime = PgAllocator::from_raw((*estate).es_query_cxt).exec_with_guard(|| {
+                pg_sys::InstrAlloc(1, pg_sys::InstrumentOption_INSTRUMENT_ALL as c_int)
+            });
+            (*self.desc).totaltime = totaltime;
+        }
+    }
+
+    /// Returns the totals of the instrumentation of the query, `InstrEndLoop`, if they are tracked, by this hook
+    ///  with `track_totals`, or by another extension, e.g. `pg_stat_statements`
+    ///
+    /// The totals are complete in `ExecutorHooks::executor_end`, before the previous executor end.
+    pub fn totals(&mut self) -> Option<InstrumentationTotals> {
+        let totaltime = unsafe { (*self.desc).totaltime };
+        if totaltime.is_null() {
+            return None;
+        }
+
+        unsafe {
+            crate::guard_pg(|| pg_sys::InstrEndLoop(totaltime));
+
+            let totaltime = &*totaltime;
+            let bufusage = &totaltime.bufusage;
+
+            // the counts are a `long`, which is only 64 bits on some platforms
+            #[allow(clippy::unnecessary_cast)]
+            let totals = InstrumentationTotals {
+                total_time: Duration::from_secs_f64(totaltime.total.max(0.0)),
+                tuples: totaltime.ntuples,
+                shared_blks_hit: bufusage.shared_blks_hit as i64,
+                shared_blks_read: bufusage.shared_blks_read as i64,
+                shared_blks_dirtied: bufusage.shared_blks_dirtied as i64,
+                shared_blks_written: bufusage.shared_blks_written as i64,
+                temp_blks_read: bufusage.temp_blks_read as i64,
+                temp_blks_written: bufusage.temp_blks_written as i64,
+            };
+
+            Some(totals)
+        }
+    }
+
+    /// Returns the `QueryDesc` this wraps
+    pub fn as_pg(&self) -> *mut pg_sys::QueryDesc {
+        self.desc
+    }
+}
+
+/// The executor hooks, each of which by default calls the previous executor, the hook installed before it, or
+///  otherwise `standard_Executor*`
+///
+/// Hooks are called for every query executed by the backend, including queries nested in functions, so they
+///  must not assume that one query ends before the next starts.
+pub trait ExecutorHooks {
+    /// Called to start the query, `ExecutorStart`, with the `EXEC_FLAG_*` flags
+    fn executor_start(&self, query_desc: &mut QueryDesc, eflags: c_int, prev: &PrevExecutor) {
+        prev.start(query_desc, eflags)
+    }
+
+    /// Called to run the query, `ExecutorRun`, fetching at most `count` rows, or all if it is zero
+    ///
+    /// `execute_once` is always true before Postgres 10, which does not support running a query more than once.
+    fn executor_run(
+        &self,
+        query_desc: &mut QueryDesc,
+        direction: pg_sys::ScanDirection,
+        count: u64,
+        execute_once: bool,
+        prev: &PrevExecutor,
+    ) {
+        prev.run(query_desc, direction, count, execute_once)
+    }
+
+    /// Called once the query has been run, to run the `AFTER` triggers, `ExecutorFinish`
+    fn executor_finish(&self, query_desc: &mut QueryDesc, prev: &PrevExecutor) {
+        prev.finish(query_desc)
+    }
+
+    /// Called to end the query, `ExecutorEnd`, which frees the state of the query
+    fn executor_end(&self, query_desc: &mut QueryDesc, prev: &PrevExecutor) {
+        prev.end(query_desc)
+    }
+}
+
+/// The executor the hooks chain to, the hooks installed before them, or otherwise `standard_Executor*`
+pub struct PrevExecutor {
+    /// The number of our hooks installed before these
+    index: usize,
+}
+
+impl PrevExecutor {
+    /// Starts the query with the previous executor
+    pub fn start(&self, query_desc: &mut QueryDesc, eflags: c_int) {
+        unsafe { executor_start_at(self.index, query_desc, eflags) }
+    }
+
+    /// Runs the query with the previous executor
+    pub fn run(
+        &self,
+        query_desc: &mut QueryDesc,
+        direction: pg_sys::ScanDirection,
+        count: u64,
+        execute_once: bool,
+    ) {
+        unsafe { executor_run_at(self.index, query_desc, direction, count, execute_once) }
+    }
+
+    /// Finishes the query with the previous executor
+    pub fn finish(&self, query_desc: &mut QueryDesc) {
+        unsafe { executor_finish_at(self.index, query_desc) }
+    }
+
+    /// Ends the query with the previous executor
+    pub fn end(&self, query_desc: &mut QueryDesc) {
+        unsafe { executor_end_at(self.index, query_desc) }
+    }
+}
+
+/// Installs the hooks into the executor hooks, this should be called from the `init` function of `pg_magic!`
+///
+/// ```rust,ignore
+/// struct Audit;
+///
+/// impl ExecutorHooks for Audit {
+///     fn executor_end(&self, query_desc: &mut QueryDesc, prev: &PrevExecutor) {
+///         log!("{} rows: {}", query_desc.processed(), query_desc.source_text());
+///         prev.end(query_desc)
+///     }
+/// }
+///
+/// fn init() {
+///     hooks::install_executor_hooks(Audit);
+/// }
+/// ```
+pub fn install_executor_hooks<H: ExecutorHooks + 'static>(hooks: H) {
+    EXECUTOR_HOOKS.with(|executor_hooks| {
+        let mut executor_hooks = executor_hooks.borrow_mut();
+
+        if executor_hooks.is_empty() {
+            unsafe {
+                PREV_EXECUTOR_START_HOOK = pg_sys::ExecutorStart_hook;
+                PREV_EXECUTOR_RUN_HOOK = pg_sys::ExecutorRun_hook;
+                PREV_EXECUTOR_FINISH_HOOK = pg_sys::ExecutorFinish_hook;
+                PREV_EXECUTOR_END_HOOK = pg_sys::ExecutorEnd_hook;
+
+                pg_sys::ExecutorStart_hook = Some(executor_start);
+                pg_sys::ExecutorRun_hook = Some(executor_run);
+                pg_sys::ExecutorFinish_hook = Some(executor_finish);
+                pg_sys::ExecutorEnd_hook = Some(executor_end);
+            }
+        }
+
+        executor_hooks.push(Rc::new(hooks));
+    });
+}
+
+/// Returns the `index`th of our hooks, the first being 1, which is cloned out, so that the executor can be
+///  reentered by the hook, e.g. with SPI
+fn hooks_at(index: usize) -> Rc<dyn ExecutorHooks> {
+    EXECUTOR_HOOKS.with(|hooks| Rc::clone(&hooks.borrow()[index - 1]))
+}
+
+/// Returns the number of our hooks
+fn hooks_len() -> usize {
+    EXECUTOR_HOOKS.with(|hooks| hooks.borrow().len())
+}
+
+/// Starts the query with the `index`th of our hooks, or the previous executor if it is zero
+unsafe fn executor_start_at(index: usize, query_desc: &mut QueryDesc, eflags: c_int) {
+    if index == 0 {
+        let desc = query_desc.desc;
+        return crate::guard_pg(|| match PREV_EXECUTOR_START_HOOK {
+            Some(prev_hook) => prev_hook(desc, eflags),
+            None => pg_sys::standard_ExecutorStart(desc, eflags),
+        });
+    }
+
+    let prev = PrevExecutor { index: index - 1 };
+    hooks_at(index).executor_start(query_desc, eflags, &prev)
+}
+
+/// Runs the query with the `index`th of our hooks, or the previous executor if it is zero
+unsafe fn executor_run_at(
+    index: usize,
+    query_desc: &mut QueryDesc,
+    direction: pg_sys::ScanDirection,
+    count: u64,
+    execute_once: bool,
+) {
+    if index == 0 {
+        let desc = query_desc.desc;
+
+        #[cfg(postgres9)]
+        let _ = execute_once;
+        #[cfg(postgres9)]
+        return crate::guard_pg(|| match PREV_EXECUTOR_RUN_HOOK {
+            Some(prev_hook) => prev_hook(desc, direction, count),
+            None => pg_sys::standard_ExecutorRun(desc, direction, count),
+        });
+
+        #[cfg(not(postgres9))]
+        return crate::guard_pg(|| match PREV_EXECUTOR_RUN_HOOK {
+            Some(prev_hook) => prev_hook(desc, direction, count, pgbool!(execute_once)),
+            None => pg_sys::standard_ExecutorRun(desc, direction, count, pgbool!(execute_once)),
+        });
+    }
+
+    let prev = PrevExecutor { index: index - 1 };
+    hooks_at(index).executor_run(query_desc, direction, count, execute_once, &prev)
+}
+
+/// Finishes the query with the `index`th of our hooks, or the previous executor if it is zero
+unsafe fn executor_finish_at(index: usize, query_desc: &mut QueryDesc) {
+    if index == 0 {
+        let desc = query_desc.desc;
+        return crate::guard_pg(|| match PREV_EXECUTOR_FINISH_HOOK {
+            Some(prev_hook) => prev_hook(desc),
+            None => pg_sys::standard_ExecutorFinish(desc),
+        });
+    }
+
+    let prev = PrevExecutor { index: index - 1 };
+    hooks_at(index).executor_finish(query_desc, &prev)
+}
+
+/// Ends the query with the `index`th of our hooks, or the previous executor if it is zero
+unsafe fn executor_end_at(index: usize, query_desc: &mut QueryDesc) {
+    if index == 0 {
+        let desc = query_desc.desc;
+        return crate::guard_pg(|| match PREV_EXECUTOR_END_HOOK {
+            Some(prev_hook) => prev_hook(desc),
+            None => pg_sys::standard_ExecutorEnd(desc),
+        });
+    }
+
+    let prev = PrevExecutor { index: index - 1 };
+    hooks_at(index).executor_end(query_desc, &prev)
+}
+
+/// The `ExecutorStart_hook`, calls the last installed hook
+unsafe extern "C" fn executor_start(desc: *mut pg_sys::QueryDesc, eflags: c_int) {
+    super::call_hook("ExecutorStart_hook", || {
+        executor_start_at(hooks_len(), &mut QueryDesc { desc }, eflags)
+    })
+}
+
+/// The `ExecutorRun_hook`, calls the last installed hook
+#[cfg(postgres9)]
+unsafe extern "C" fn executor_run(
+    desc: *mut pg_sys::QueryDesc,
+    direction: pg_sys::ScanDirection,
+    count: u64,
+) {
+    super::call_hook("ExecutorRun_hook", || {
+        executor_run_at(hooks_len(), &mut QueryDesc { desc }, direction, count, true)
+    })
+}
+
+/// The `ExecutorRun_hook`, calls the last installed hook
+#[cfg(not(postgres9))]
+unsafe extern "C" fn executor_run(
+    desc: *mut pg_sys::QueryDesc,
+    direction: pg_sys::ScanDirection,
+    count: u64,
+    execute_once: PgBool,
+) {
+    super::call_hook("ExecutorRun_hook", || {
+        let execute_once: bool = pgbool!(execute_once);
+        executor_run_at(
+            hooks_len(),
+            &mut QueryDesc { desc },
+            direction,
+            count,
+            execute_once,
+        )
+    })
+}
+
+/// The `ExecutorFinish_hook`, calls the last installed hook
+unsafe extern "C" fn executor_finish(desc: *mut pg_sys::QueryDesc) {
+    super::call_hook("ExecutorFinish_hook", || {
+        executor_finish_at(hooks_len(), &mut QueryDesc { desc })
+    })
+}
+
+/// The `ExecutorEnd_hook`, calls the last installed hook
+unsafe extern "C" fn executor_end(desc: *mut pg_sys::QueryDesc) {
+    super::call_hook("ExecutorEnd_hook", || {
+        executor_end_at(hooks_len(), &mut QueryDesc { desc })
+    })
+}
@@ -0,0 +1,49 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for the hooks Postgres calls extensions from, e.g. to rewrite queries while they are planned.
+//!
+//! Hooks are installed from the `init` function of `pg_magic!`, and are chained with the hooks installed before
+//!  them, by other extensions or by an earlier install, which are called through the argument passed to the hook.
+//!
+//! A panic in a hook is raised as an `ERROR`, and an `ERROR` raised by Postgres within it unwinds the Rust code
+//!  before continuing.
+
+mod analyze;
+mod emit_log;
+mod executor;
+mod planner;
+mod query;
+mod utility;
+
+pub use analyze::{install_post_parse_analyze_hook, ParseState, PrevPostParseAnalyze};
+pub use emit_log::{install_emit_log_hook, ErrorDataView};
+pub use executor::{
+    install_executor_hooks, CmdType, ExecutorHooks, InstrumentationTotals, PrevExecutor, QueryDesc,
+};
+pub use planner::{install_planner_hook, BoundParams, PlannedStmt, PrevPlanner};
+pub use query::{QueryView, RangeTableEntry, RteKind, TargetEntry};
+pub use utility::{
+    install_process_utility_hook, PrevProcessUtility, ProcessUtilityContext, ProcessUtilityHook,
+    UtilityStmt,
+};
+
+use crate::JumpContext;
+
+/// Calls the hook, raising a panic as an `ERROR`, and continuing the longjmp of an `ERROR` once it is unwound
+unsafe fn call_hook<R, F: FnOnce() -> R>(name: &str, hook: F) -> R {
+    let err = match crate::catch_unwind_pg(hook) {
+        Ok(result) => return result,
+        Err(err) => err,
+    };
+
+    if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+        jump_context.continue_longjmp();
+    }
+
+    crate::raise_panic(name, err);
+}
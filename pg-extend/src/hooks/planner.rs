@@ -0,0 +1,168 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The `planner_hook`, which is called to plan every query, e.g. to rewrite it or collect statistics.
+
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use crate::pg_sys;
+
+type PlannerHook = dyn Fn(&mut pg_sys::Query, c_int, BoundParams, &PrevPlanner) -> PlannedStmt;
+
+thread_local! {
+    /// The installed hooks, the last is called first
+    static PLANNER_HOOKS: RefCell<Vec<Rc<PlannerHook>>> = RefCell::new(Vec::new());
+}
+
+/// The `planner_hook` which was installed before the first of ours
+static mut PREV_PLANNER_HOOK: pg_sys::planner_hook_type = None;
+
+/// The plan of a query, `PlannedStmt`, allocated in the current memory context
+pub struct PlannedStmt {
+    stmt: NonNull<pg_sys::PlannedStmt>,
+}
+
+impl PlannedStmt {
+    /// Wraps the plan returned by a planner
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid `PlannedStmt`, which outlives the query being planned.
+    pub unsafe fn from_pg(stmt: *mut pg_sys::PlannedStmt) -> Self {
+        Self {
+            stmt: NonNull::new(stmt).expect("the planner returned a NULL plan"),
+        }
+    }
+
+    /// Returns the `PlannedStmt` this wraps
+    pub fn into_pg(self) -> *mut pg_sys::PlannedStmt {
+        self.stmt.as_ptr()
+    }
+}
+
+impl Deref for PlannedStmt {
+    type Target = pg_sys::PlannedStmt;
+
+    fn deref(&self) -> &pg_sys::PlannedStmt {
+        unsafe { self.stmt.as_ref() }
+    }
+}
+
+impl DerefMut for PlannedStmt {
+    fn deref_mut(&mut self) -> &mut pg_sys::PlannedStmt {
+        unsafe { self.stmt.as_mut() }
+    }
+}
+
+/// The values of the parameters of a query, `ParamListInfo`, which is NULL if it has none
+#[derive(Clone, Copy)]
+pub struct BoundParams {
+    params: pg_sys::ParamListInfo,
+}
+
+impl BoundParams {
+    pub(super) fn new(params: pg_sys::ParamListInfo) -> Self {
+        Self { params }
+    }
+
+    /// Returns the `ParamListInfo` this wraps
+    pub fn as_pg(self) -> pg_sys::ParamListInfo {
+        self.params
+    }
+}
+
+/// The planner a hook chains to, the hook installed before it, or otherwise `standard_planner`
+pub struct PrevPlanner {
+    /// The number of our hooks installed before this one
+    index: usize,
+}
+
+impl PrevPlanner {
+    /// Plans the query with the previous planner
+    pub fn plan(
+        &self,
+        query: &mut pg_sys::Query,
+        cursor_options: c_int,
+        bound_params: BoundParams,
+    ) -> PlannedStmt {
+        unsafe { call_planner(self.index, query, cursor_options, bound_params) }
+    }
+}
+
+/// Installs the hook into `planner_hook`, which is called with the query, the `CURSOR_OPT_*` flags, the values of
+///  any parameters, and the previous planner, which it usually chains to
+///
+/// The signature of `planner_hook` is the same from Postgres 9.6 through 12.
+///
+/// ```rust,ignore
+/// fn init() {
+///     hooks::install_planner_hook(|query, cursor_options, bound_params, prev| {
+///         notice!("planning a query of command type {}", query.commandType);
+///         prev.plan(query, cursor_options, bound_params)
+///     });
+/// }
+/// ```
+pub fn install_planner_hook<F>(hook: F)
+where
+    F: Fn(&mut pg_sys::Query, c_int, BoundParams, &PrevPlanner) -> PlannedStmt + 'static,
+{
+    PLANNER_HOOKS.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+
+        if hooks.is_empty() {
+            unsafe {
+                PREV_PLANNER_HOOK = pg_sys::planner_hook;
+                pg_sys::planner_hook = Some(planner);
+            }
+        }
+
+        hooks.push(Rc::new(hook));
+    });
+}
+
+/// Calls the `index`th of our hooks, the first being 1, or the previous planner if it is zero
+unsafe fn call_planner(
+    index: usize,
+    query: &mut pg_sys::Query,
+    cursor_options: c_int,
+    bound_params: BoundParams,
+) -> PlannedStmt {
+    if index == 0 {
+        let stmt = crate::guard_pg(|| match PREV_PLANNER_HOOK {
+            Some(prev_hook) => prev_hook(query, cursor_options, bound_params.params),
+            None => pg_sys::standard_planner(query, cursor_options, bound_params.params),
+        });
+
+        return PlannedStmt::from_pg(stmt);
+    }
+
+    // the hook is cloned out, so that planning can be reentered by the hook, e.g. with SPI
+    let hook = PLANNER_HOOKS.with(|hooks| Rc::clone(&hooks.borrow()[index - 1]));
+    let prev = PrevPlanner { index: index - 1 };
+
+    hook(query, cursor_options, bound_params, &prev)
+}
+
+/// The `planner_hook`, calls the last installed hook
+unsafe extern "C" fn planner(
+    parse: *mut pg_sys::Query,
+    cursor_options: c_int,
+    bound_params: pg_sys::ParamListInfo,
+) -> *mut pg_sys::PlannedStmt {
+    let bound_params = BoundParams::new(bound_params);
+
+    super::call_hook("planner_hook", || {
+        let query = parse.as_mut().expect("the query to plan was NULL");
+        let index = PLANNER_HOOKS.with(|hooks| hooks.borrow().len());
+
+        call_planner(index, query, cursor_options, bound_params).into_pg()
+    })
+}
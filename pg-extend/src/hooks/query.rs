@@ -0,0 +1,267 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A view of the tree of a `Query`, as passed to the planner and post parse analysis hooks.
+
+use std::any::Any;
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::CmdType;
+use crate::pg_bool::PgBool;
+use crate::pg_sys;
+
+/// A read only view of a `Query`
+#[derive(Clone, Copy)]
+pub struct QueryView<'a> {
+    query: &'a pg_sys::Query,
+}
+
+impl<'a> QueryView<'a> {
+    /// Creates a view of the query
+    pub fn new(query: &'a pg_sys::Query) -> Self {
+        Self { query }
+    }
+
+    /// Returns the type of the query
+    pub fn command_type(&self) -> CmdType {
+        self.query.commandType.into()
+    }
+
+    /// Returns the identifier of the query, `queryId`, which is zero unless it was set by an extension, e.g.
+    ///  `pg_stat_statements`, which sets it in its post parse analysis hook
+    pub fn query_id(&self) -> u64 {
+        #[cfg(any(postgres9, postgres10))]
+        return u64::from(self.query.queryId);
+
+        #[cfg(not(any(postgres9, postgres10)))]
+        return self.query.queryId;
+    }
+
+    /// Returns the `NodeTag` of the statement, if this is a utility command, e.g. `pg_sys::NodeTag_T_CreateStmt`
+    pub fn utility_tag(&self) -> Option<pg_sys::NodeTag> {
+        unsafe { self.query.utilityStmt.as_ref() }.map(|node| node.type_)
+    }
+
+    /// Returns the entries of the range table, the relations, subqueries, functions etc. the query reads from
+    pub fn range_table(&self) -> impl Iterator<Item = RangeTableEntry<'a>> {
+        list_iter(self.query.rtable).map(|rte| RangeTableEntry {
+            rte: unsafe { &*(rte as *const pg_sys::RangeTblEntry) },
+        })
+    }
+
+    /// Returns the entries of the target list, the columns the query returns, or the columns it sets
+    pub fn target_list(&self) -> impl Iterator<Item = TargetEntry<'a>> {
+        list_iter(self.query.targetList).map(|tle| TargetEntry {
+            tle: unsafe { &*(tle as *const pg_sys::TargetEntry) },
+        })
+    }
+
+    /// Walks the nodes of the query, `query_tree_walker`, calling the visitor with each node, which returns true
+    ///  to stop the walk, e.g. to fingerprint the query by the tags of its nodes
+    ///
+    /// The nodes of subqueries are walked, including the `Query` nodes of the subqueries. Returns true if the
+    ///  walk was stopped.
+    pub fn walk<F: FnMut(&pg_sys::Node) -> bool>(&self, mut visitor: F) -> bool {
+        let mut context = WalkContext {
+            visitor: &mut visitor,
+            panic: None,
+        };
+
+        let stopped = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::query_tree_walker(
+                    self.query as *const pg_sys::Query as *mut pg_sys::Query,
+                    walker_fn(),
+                    &mut context as *mut WalkContext as *mut c_void,
+                    0,
+                )
+            })
+        };
+
+        // a panic of the visitor must not unwind through Postgres, so it is resumed once the walk has returned
+        if let Some(err) = context.panic {
+            panic::resume_unwind(err);
+        }
+
+        pgbool!(stopped)
+    }
+
+    /// Returns the `Query` this views
+    pub fn as_pg(&self) -> &'a pg_sys::Query {
+        self.query
+    }
+}
+
+/// The type of an entry of the range table, `RTEKind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RteKind {
+    /// A table, view etc., `RTE_RELATION`
+    Relation,
+    /// A subquery in `FROM`, `RTE_SUBQUERY`
+    Subquery,
+    /// A join, `RTE_JOIN`
+    Join,
+    /// A function in `FROM`, `RTE_FUNCTION`
+    Function,
+    /// A `VALUES` list, `RTE_VALUES`
+    Values,
+    /// A reference to a `WITH` query, `RTE_CTE`
+    Cte,
+    /// Any other entry, e.g. `RTE_TABLEFUNC`
+    Other,
+}
+
+impl From<pg_sys::RTEKind> for RteKind {
+    fn from(kind: pg_sys::RTEKind) -> Self {
+        match kind {
+            pg_sys::RTEKind_RTE_RELATION => RteKind::Relation,
+            pg_sys::RTEKind_RTE_SUBQUERY => RteKind::Subquery,
+            pg_sys::RTEKind_RTE_JOIN => RteKind::Join,
+            pg_sys::RTEKind_RTE_FUNCTION => RteKind::Function,
+            pg_sys::RTEKind_RTE_VALUES => RteKind::Values,
+            pg_sys::RTEKind_RTE_CTE => RteKind::Cte,
+            _ => RteKind::Other,
+        }
+    }
+}
+
+/// An entry of the range table of a query, `RangeTblEntry`
+#[derive(Clone, Copy)]
+pub struct RangeTableEntry<'a> {
+    rte: &'a pg_sys::RangeTblEntry,
+}
+
+impl<'a> RangeTableEntry<'a> {
+    /// Returns the type of the entry
+    pub fn kind(&self) -> RteKind {
+        self.rte.rtekind.into()
+    }
+
+    /// Returns the OID of the relation, if this is a `RteKind::Relation`
+    pub fn relation_oid(&self) -> Option<pg_sys::Oid> {
+        if self.kind() == RteKind::Relation {
+            Some(self.rte.relid)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the query, if this is a `RteKind::Subquery`
+    pub fn subquery(&self) -> Option<QueryView<'a>> {
+        unsafe { self.rte.subquery.as_ref() }.map(QueryView::new)
+    }
+
+    /// Returns the name the entry is referred to by in the query, its alias or otherwise e.g. the name of the
+    ///  relation
+    pub fn alias(&self) -> &'a str {
+        match unsafe { self.rte.eref.as_ref() } {
+            Some(eref) => unsafe { to_str(eref.aliasname) },
+            None => "",
+        }
+    }
+
+    /// Returns the `RangeTblEntry` this wraps
+    pub fn as_pg(&self) -> &'a pg_sys::RangeTblEntry {
+        self.rte
+    }
+}
+
+/// An entry of the target list of a query, `TargetEntry`
+#[derive(Clone, Copy)]
+pub struct TargetEntry<'a> {
+    tle: &'a pg_sys::TargetEntry,
+}
+
+impl<'a> TargetEntry<'a> {
+    /// Returns the name of the column, `resname`, if it has one
+    pub fn name(&self) -> Option<&'a str> {
+        if self.tle.resname.is_null() {
+            None
+        } else {
+            Some(unsafe { to_str(self.tle.resname) })
+        }
+    }
+
+    /// Returns the number of the column, starting from 1, `resno`
+    pub fn resno(&self) -> pg_sys::AttrNumber {
+        self.tle.resno
+    }
+
+    /// Returns true if the column is only used internally, e.g. for sorting, and not returned, `resjunk`
+    pub fn is_junk(&self) -> bool {
+        pgbool!(self.tle.resjunk)
+    }
+
+    /// Returns the `NodeTag` of the expression of the column, e.g. `pg_sys::NodeTag_T_Var` for a column of a
+    ///  relation
+    pub fn expr_tag(&self) -> Option<pg_sys::NodeTag> {
+        unsafe { (self.tle.expr as *const pg_sys::Node).as_ref() }.map(|node| node.type_)
+    }
+
+    /// Returns the `TargetEntry` this wraps
+    pub fn as_pg(&self) -> &'a pg_sys::TargetEntry {
+        self.tle
+    }
+}
+
+/// Iterates over the pointers of a `List`, which is NULL if it is empty
+fn list_iter(list: *mut pg_sys::List) -> impl Iterator<Item = *mut c_void> {
+    let length = unsafe { list.as_ref() }.map_or(0, |list| list.length);
+
+    (0..length).map(move |i| unsafe { pg_sys::list_nth(list, i) })
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> &'a str {
+    if value.is_null() {
+        return "";
+    }
+
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+/// The state of `QueryView::walk`, passed through Postgres to the walker
+struct WalkContext<'f> {
+    visitor: &'f mut dyn FnMut(&pg_sys::Node) -> bool,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+/// The walker as the type the tree walkers take it as, which is declared without its arguments
+fn walker_fn() -> Option<unsafe extern "C" fn() -> PgBool> {
+    type Walker = unsafe extern "C" fn(*mut pg_sys::Node, *mut c_void) -> PgBool;
+
+    Some(unsafe { mem::transmute::<Walker, unsafe extern "C" fn() -> PgBool>(walker) })
+}
+
+/// Calls the visitor with the node, then walks its children, this must not unwind into Postgres
+unsafe extern "C" fn walker(node: *mut pg_sys::Node, context: *mut c_void) -> PgBool {
+    let context = &mut *(context as *mut WalkContext);
+    let node = match node.as_ref() {
+        Some(node) => node,
+        None => return pgbool!(false),
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| (context.visitor)(node))) {
+        Ok(true) => return pgbool!(true),
+        Ok(false) => (),
+        Err(err) => {
+            context.panic = Some(err);
+            return pgbool!(true);
+        }
+    }
+
+    let node = node as *const pg_sys::Node as *mut pg_sys::Node;
+    let context = context as *mut WalkContext as *mut c_void;
+
+    if (*node).type_ == pg_sys::NodeTag_T_Query {
+        pg_sys::query_tree_walker(node as *mut pg_sys::Query, walker_fn(), context, 0)
+    } else {
+        pg_sys::expression_tree_walker(node, walker_fn(), context)
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The `ProcessUtility_hook`, which is called to execute every utility command, e.g. DDL, to block or augment
+//!  them.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+use super::BoundParams;
+use crate::pg_error::PgError;
+use crate::pg_sys;
+
+thread_local! {
+    /// The installed hooks, the last is called first
+    static PROCESS_UTILITY_HOOKS: RefCell<Vec<Rc<dyn ProcessUtilityHook>>> = RefCell::new(Vec::new());
+}
+
+/// The `ProcessUtility_hook` which was installed before the first of ours
+static mut PREV_PROCESS_UTILITY_HOOK: pg_sys::ProcessUtility_hook_type = None;
+
+/// Where the utility command is executed from, `ProcessUtilityContext`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessUtilityContext {
+    /// A command sent by the client, `PROCESS_UTILITY_TOPLEVEL`
+    TopLevel,
+    /// A command in a query, e.g. in a function, `PROCESS_UTILITY_QUERY`
+    Query,
+    /// A command in a query which may commit, e.g. in a procedure, `PROCESS_UTILITY_QUERY_NONATOMIC`, this is
+    ///  only used from Postgres 11
+    QueryNonatomic,
+    /// A command which is part of another, e.g. a `CREATE INDEX` of `CREATE TABLE`, `PROCESS_UTILITY_SUBCOMMAND`
+    Subcommand,
+}
+
+impl From<pg_sys::ProcessUtilityContext> for ProcessUtilityContext {
+    fn from(context: pg_sys::ProcessUtilityContext) -> Self {
+        match context {
+            pg_sys::ProcessUtilityContext_PROCESS_UTILITY_TOPLEVEL => {
+                ProcessUtilityContext::TopLevel
+            }
+            pg_sys::ProcessUtilityContext_PROCESS_UTILITY_QUERY => ProcessUtilityContext::Query,
+            #[cfg(not(any(postgres9, postgres10)))]
+            pg_sys::ProcessUtilityContext_PROCESS_UTILITY_QUERY_NONATOMIC => {
+                ProcessUtilityContext::QueryNonatomic
+            }
+            _ => ProcessUtilityContext::Subcommand,
+        }
+    }
+}
+
+/// The utility command being executed, with the arguments of `ProcessUtility`
+///
+/// Before Postgres 10, the command is passed as its parse tree, from 10 it is wrapped in a `PlannedStmt`, with a
+///  `QueryEnvironment`, this is hidden by the accessors.
+pub struct UtilityStmt {
+    #[cfg(postgres9)]
+    parsetree: *mut pg_sys::Node,
+    #[cfg(not(postgres9))]
+    pstmt: *mut pg_sys::PlannedStmt,
+    query_string: *const c_char,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    #[cfg(not(postgres9))]
+    query_env: *mut pg_sys::QueryEnvironment,
+    dest: *mut pg_sys::DestReceiver,
+    completion_tag: *mut c_char,
+}
+
+impl UtilityStmt {
+    /// Returns the parse tree of the command, which can be cast to the type of its `node_tag`, e.g.
+    ///  `pg_sys::DropStmt` for `pg_sys::NodeTag_T_DropStmt`
+    pub fn node(&self) -> *mut pg_sys::Node {
+        #[cfg(postgres9)]
+        return self.parsetree;
+
+        #[cfg(not(postgres9))]
+        return unsafe { (*self.pstmt).utilityStmt };
+    }
+
+    /// Returns the `NodeTag` of the parse tree of the command, e.g. `pg_sys::NodeTag_T_CreateStmt`
+    pub fn node_tag(&self) -> pg_sys::NodeTag {
+        unsafe { (*self.node()).type_ }
+    }
+
+    /// Returns the text of the query the command is part of, which may contain other commands, or be empty if it
+    ///  is not known
+    pub fn query_string(&self) -> &str {
+        if self.query_string.is_null() {
+            return "";
+        }
+
+        unsafe { CStr::from_ptr(self.query_string) }
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    /// Returns where the command is executed from
+    pub fn context(&self) -> ProcessUtilityContext {
+        self.context.into()
+    }
+
+    /// Returns the values of the parameters of the command
+    pub fn params(&self) -> BoundParams {
+        BoundParams::new(self.params)
+    }
+}
+
+/// A hook called to execute every utility command, which usually chains to the previous implementation, the
+///  hook installed before it, or otherwise `standard_ProcessUtility`
+///
+/// The command is blocked by returning an error, rather than calling the previous implementation.
+pub trait ProcessUtilityHook {
+    /// Called to execute the command, `ProcessUtility`, the error returned is raised once the hook is unwound
+    fn process_utility(
+        &self,
+        stmt: &mut UtilityStmt,
+        prev: &PrevProcessUtility,
+    ) -> Result<(), PgError>;
+}
+
+/// The implementation of `ProcessUtility` a hook chains to
+pub struct PrevProcessUtility {
+    /// The number of our hooks installed before this one
+    index: usize,
+}
+
+impl PrevProcessUtility {
+    /// Executes the command with the previous implementation, returning the error of an earlier hook of ours
+    pub fn process(&self, stmt: &mut UtilityStmt) -> Result<(), PgError> {
+        unsafe { process_utility_at(self.index, stmt) }
+    }
+}
+
+/// Installs the hook into `ProcessUtility_hook`, this should be called from the `init` function of `pg_magic!`
+///
+/// The signature of `ProcessUtility_hook` differs before Postgres 10, which is hidden by `UtilityStmt`.
+///
+/// ```rust,ignore
+/// struct NoTruncate;
+///
+/// impl ProcessUtilityHook for NoTruncate {
+///     fn process_utility(
+///         &self,
+///         stmt: &mut UtilityStmt,
+///         prev: &PrevProcessUtility,
+///     ) -> Result<(), PgError> {
+///         if stmt.node_tag() == pg_sys::NodeTag_T_TruncateStmt {
+///             return Err(PgError::new("TRUNCATE is not allowed"));
+///         }
+///
+///         prev.process(stmt)
+///     }
+/// }
+///
+/// fn init() {
+///     hooks::install_process_utility_hook(NoTruncate);
+/// }
+/// ```
+pub fn install_process_utility_hook<H: ProcessUtilityHook + 'static>(hook: H) {
+    PROCESS_UTILITY_HOOKS.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+
+        if hooks.is_empty() {
+            unsafe {
+                PREV_PROCESS_UTILITY_HOOK = pg_sys::ProcessUtility_hook;
+                pg_sys::ProcessUtility_hook = Some(process_utility);
+            }
+        }
+
+        hooks.push(Rc::new(hook));
+    });
+}
+
+/// Executes the command with the `index`th of our hooks, the first being 1, or the previous implementation if it
+///  is zero
+unsafe fn process_utility_at(index: usize, stmt: &mut UtilityStmt) -> Result<(), PgError> {
+    if index == 0 {
+        #[cfg(postgres9)]
+        crate::guard_pg(|| {
+            let process_utility =
+                PREV_PROCESS_UTILITY_HOOK.unwrap_or(pg_sys::standard_ProcessUtility);
+
+            process_utility(
+                stmt.parsetree,
+                stmt.query_string,
+                stmt.context,
+                stmt.params,
+                stmt.dest,
+                stmt.completion_tag,
+            )
+        });
+
+        #[cfg(not(postgres9))]
+        crate::guard_pg(|| {
+            let process_utility =
+                PREV_PROCESS_UTILITY_HOOK.unwrap_or(pg_sys::standard_ProcessUtility);
+
+            process_utility(
+                stmt.pstmt,
+                stmt.query_string,
+                stmt.context,
+                stmt.params,
+                stmt.query_env,
+                stmt.dest,
+                stmt.completion_tag,
+            )
+        });
+
+        return Ok(());
+    }
+
+    // the hook is cloned out, so that a command can be executed by the hook, e.g. with SPI
+    let hook = PROCESS_UTILITY_HOOKS.with(|hooks| Rc::clone(&hooks.borrow()[index - 1]));
+    let prev = PrevProcessUtility { index: index - 1 };
+
+    hook.process_utility(stmt, &prev)
+}
+
+/// Calls the last installed hook, and raises the error it returns
+unsafe fn call_process_utility(mut stmt: UtilityStmt) {
+    let result = super::call_hook("ProcessUtility_hook", || {
+        let index = PROCESS_UTILITY_HOOKS.with(|hooks| hooks.borrow().len());
+        process_utility_at(index, &mut stmt)
+    });
+
+    // raise_panic catches the ERROR raised for the error, which must not unwind into Postgres when the statement
+    //  is executed within a catch_unwind_pg, e.g. through SPI from a `#[pg_extern]` function
+    if let Err(err) = result {
+        crate::raise_panic("ProcessUtility_hook", Box::new(err));
+    }
+}
+
+/// The `ProcessUtility_hook`
+#[cfg(postgres9)]
+unsafe extern "C" fn process_utility(
+    parsetree: *mut pg_sys::Node,
+    query_string: *const c_char,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    dest: *mut pg_sys::DestReceiver,
+    completion_tag: *mut c_char,
+) {
+    call_process_utility(UtilityStmt {
+        parsetree,
+        query_string,
+        context,
+        params,
+        dest,
+        completion_tag,
+    })
+}
+
+/// The `ProcessUtility_hook`
+#[cfg(not(postgres9))]
+unsafe extern "C" fn process_utility(
+    pstmt: *mut pg_sys::PlannedStmt,
+    query_string: *const c_char,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    query_env: *mut pg_sys::QueryEnvironment,
+    dest: *mut pg_sys::DestReceiver,
+    completion_tag: *mut c_char,
+) {
+    call_process_utility(UtilityStmt {
+        pstmt,
+        query_string,
+        context,
+        params,
+        query_env,
+        dest,
+        completion_tag,
+    })
+}
@@ -0,0 +1,28 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::cmp::Ordering;
+
+/// The btree comparison support function, `BTORDER_PROC`, for any `Ord` type.
+///
+/// Returns a negative value if `left < right`, zero if they are equal and a positive value if `left > right`.
+///
+/// ```
+/// extern crate pg_extend;
+/// use pg_extend::index::btree_cmp;
+///
+/// assert_eq!(btree_cmp(&1, &2), -1);
+/// assert_eq!(btree_cmp(&2, &2), 0);
+/// assert_eq!(btree_cmp(&3, &2), 1);
+/// ```
+pub fn btree_cmp<T: Ord + ?Sized>(left: &T, right: &T) -> i32 {
+    match left.cmp(right) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
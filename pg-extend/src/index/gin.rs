@@ -0,0 +1,311 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::os::raw::c_int;
+use std::slice;
+
+use crate::index::{
+    btree_cmp, datum_to, key_datum, sql_operators, sql_type, support_arg, Consistent,
+    StrategyNumber,
+};
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::{PgDatum, TryFromPgDatum};
+use crate::pg_sys;
+use crate::pg_type::PgTypeInfo;
+
+/// Which indexed values are searched when the query has no keys, or in addition to those matching the keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GinSearchMode {
+    /// Only values that contain at least one of the keys, no keys matches nothing
+    Default,
+    /// Also values that have no keys
+    IncludeEmpty,
+    /// All values, including those without keys
+    All,
+}
+
+impl From<GinSearchMode> for c_int {
+    fn from(mode: GinSearchMode) -> Self {
+        match mode {
+            GinSearchMode::Default => pg_sys::GIN_SEARCH_MODE_DEFAULT as c_int,
+            GinSearchMode::IncludeEmpty => pg_sys::GIN_SEARCH_MODE_INCLUDE_EMPTY as c_int,
+            GinSearchMode::All => pg_sys::GIN_SEARCH_MODE_ALL as c_int,
+        }
+    }
+}
+
+/// The keys extracted from a query
+pub struct GinQuery<K> {
+    /// The keys to search for, these are passed to the consistent functions in the same order
+    pub keys: Vec<K>,
+    /// How the index is searched
+    pub search_mode: GinSearchMode,
+}
+
+impl<K> From<Vec<K>> for GinQuery<K> {
+    fn from(keys: Vec<K>) -> Self {
+        GinQuery {
+            keys,
+            search_mode: GinSearchMode::Default,
+        }
+    }
+}
+
+/// The support functions of a GIN operator class.
+///
+/// Use `#[pg_gin]` from pg-extern-attr on the implementing type to generate the Postgres functions and the
+///  `CREATE OPERATOR CLASS ... USING gin` statement. The operators must be created separately, e.g. with
+///  `#[pg_operator]`.
+pub trait GinSupport {
+    /// The type being indexed, `FOR TYPE` of the operator class and the left argument of the operators
+    type Value: for<'s> TryFromPgDatum<'s> + PgTypeInfo;
+    /// The right argument of the operators
+    type Query: for<'s> TryFromPgDatum<'s> + PgTypeInfo;
+    /// The keys stored in the index, `STORAGE` of the operator class, these are compared with `Ord`
+    type Key: for<'s> TryFromPgDatum<'s> + Into<PgDatum<'static>> + PgTypeInfo + Ord;
+
+    /// The operators of the operator class and their strategy numbers, e.g. `&[(3, "&&")]`
+    const OPERATORS: &'static [(StrategyNumber, &'static str)];
+
+    /// Returns the keys of a value being added to the index, the `extractValue` support function
+    fn extract_value(value: Self::Value) -> Vec<Self::Key>;
+
+    /// Returns the keys to search for with the operator of `strategy`, the `extractQuery` support function
+    fn extract_query(query: Self::Query, strategy: StrategyNumber) -> GinQuery<Self::Key>;
+
+    /// Returns if a value matches the query, `check` is true for each key of the query the value contains
+    fn consistent(check: &[bool], query: &Self::Query, strategy: StrategyNumber) -> Consistent;
+
+    /// The same as `consistent`, but whether the value contains a key may be `Consistent::Maybe`
+    ///
+    /// The default calls `consistent` with the unknown keys present, which is correct as long as
+    ///  `consistent` only matches more when more keys are present.
+    fn tri_consistent(
+        check: &[Consistent],
+        query: &Self::Query,
+        strategy: StrategyNumber,
+    ) -> Consistent {
+        let present = check
+            .iter()
+            .map(|check| *check != Consistent::No)
+            .collect::<Vec<bool>>();
+
+        match Self::consistent(&present, query, strategy) {
+            Consistent::No => Consistent::No,
+            _ if check.contains(&Consistent::Maybe) => Consistent::Maybe,
+            consistent => consistent,
+        }
+    }
+}
+
+/// Contains the GIN support functions called by Postgres. You should not interact with this directly,
+///   instead use `#[pg_gin]` from pg-extern-attr
+pub struct GinWrapper<T: GinSupport>(PhantomData<T>);
+
+impl<T: GinSupport> GinWrapper<T> {
+    /// `compare(storage, storage) RETURNS int4`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn compare(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let left: T::Key = datum_to(&memory_context, support_arg(func_call_info, 0));
+        let right: T::Key = datum_to(&memory_context, support_arg(func_call_info, 1));
+
+        PgDatum::from(btree_cmp(&left, &right)).into_datum()
+    }
+
+    /// `extractValue(value, internal, internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn extract_value(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let value: T::Value = datum_to(&memory_context, support_arg(func_call_info, 0));
+        let nkeys = support_arg(func_call_info, 1) as *mut i32;
+
+        let keys = T::extract_value(value);
+
+        *nkeys = keys.len() as i32;
+        keys_array(keys) as pg_sys::Datum
+    }
+
+    /// `extractQuery(query, internal, int2, internal, internal, internal, internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn extract_query(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let query: T::Query = datum_to(&memory_context, support_arg(func_call_info, 0));
+        let nkeys = support_arg(func_call_info, 1) as *mut i32;
+        let strategy = support_arg(func_call_info, 2) as StrategyNumber;
+        let search_mode = support_arg(func_call_info, 6) as *mut c_int;
+
+        let GinQuery {
+            keys,
+            search_mode: mode,
+        } = T::extract_query(query, strategy);
+
+        *nkeys = keys.len() as i32;
+        *search_mode = c_int::from(mode);
+        keys_array(keys) as pg_sys::Datum
+    }
+
+    /// `consistent(internal, int2, query, int4, internal, internal, internal, internal) RETURNS bool`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn consistent(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let check = support_arg(func_call_info, 0) as *const u8;
+        let strategy = support_arg(func_call_info, 1) as StrategyNumber;
+        let query: T::Query = datum_to(&memory_context, support_arg(func_call_info, 2));
+        let nkeys = support_arg(func_call_info, 3) as i32;
+        let recheck = support_arg(func_call_info, 5) as *mut u8;
+
+        let check = check_slice(check, nkeys)
+            .iter()
+            .map(|check| *check != 0)
+            .collect::<Vec<bool>>();
+
+        let (matches, needs_recheck) = match T::consistent(&check, &query, strategy) {
+            Consistent::No => (false, false),
+            Consistent::Yes => (true, false),
+            Consistent::Maybe => (true, true),
+        };
+
+        *recheck = needs_recheck as u8;
+        PgDatum::from(matches).into_datum()
+    }
+
+    /// `triConsistent(internal, int2, query, int4, internal, internal, internal) RETURNS "char"`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn tri_consistent(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let check = support_arg(func_call_info, 0) as *const pg_sys::GinTernaryValue;
+        let strategy = support_arg(func_call_info, 1) as StrategyNumber;
+        let query: T::Query = datum_to(&memory_context, support_arg(func_call_info, 2));
+        let nkeys = support_arg(func_call_info, 3) as i32;
+
+        let check = check_slice(check, nkeys)
+            .iter()
+            .map(|check| match *check as u32 {
+                pg_sys::GIN_FALSE => Consistent::No,
+                pg_sys::GIN_TRUE => Consistent::Yes,
+                _ => Consistent::Maybe,
+            })
+            .collect::<Vec<Consistent>>();
+
+        let result = match T::tri_consistent(&check, &query, strategy) {
+            Consistent::No => pg_sys::GIN_FALSE,
+            Consistent::Yes => pg_sys::GIN_TRUE,
+            Consistent::Maybe => pg_sys::GIN_MAYBE,
+        };
+
+        result as pg_sys::Datum
+    }
+
+    /// Returns the statements to create the support functions and the operator class `{name}_ops`
+    ///
+    /// The support functions are named `{name}_gin_{function}`, these are exported as `pg_{name}_gin_{function}`
+    ///  by `#[pg_gin]`.
+    pub fn create_stmt(name: &str, library_path: &str) -> String {
+        let value = sql_type::<T::Value>();
+        let query = sql_type::<T::Query>();
+        let key = sql_type::<T::Key>();
+
+        // (support number, function, arguments, return type)
+        let functions = [
+            (1, "compare", format!("{0}, {0}", key), "int4"),
+            (
+                2,
+                "extract_value",
+                format!("{}, internal, internal", value),
+                "internal",
+            ),
+            (
+                3,
+                "extract_query",
+                format!(
+                    "{}, internal, int2, internal, internal, internal, internal",
+                    query
+                ),
+                "internal",
+            ),
+            (
+                4,
+                "consistent",
+                format!(
+                    "internal, int2, {}, int4, internal, internal, internal, internal",
+                    query
+                ),
+                "bool",
+            ),
+            (
+                6,
+                "tri_consistent",
+                format!(
+                    "internal, int2, {}, int4, internal, internal, internal",
+                    query
+                ),
+                "\"char\"",
+            ),
+        ];
+
+        let mut stmts = Vec::new();
+        let mut items = sql_operators(T::OPERATORS, value, query);
+
+        for (support, function, args, ret) in functions.iter() {
+            stmts.push(format!(
+                "CREATE or REPLACE FUNCTION {name}_gin_{function}({args}) RETURNS {ret} AS '{library_path}', 'pg_{name}_gin_{function}' LANGUAGE C STRICT;",
+                name = name,
+                function = function,
+                args = args,
+                ret = ret,
+                library_path = library_path,
+            ));
+            items.push(format!(
+                "FUNCTION {} {}_gin_{}({})",
+                support, name, function, args
+            ));
+        }
+
+        items.push(format!("STORAGE {}", key));
+        // there is no `CREATE OR REPLACE OPERATOR CLASS`, an existing operator class is kept as indexes may use it
+        stmts.push(format!(
+            "DO $$ BEGIN CREATE OPERATOR CLASS {}_ops FOR TYPE {} USING gin AS {}; \
+             EXCEPTION WHEN duplicate_object THEN NULL; END $$;",
+            name,
+            value,
+            items.join(", ")
+        ));
+
+        stmts.join("\n")
+    }
+}
+
+/// Returns the keys as a palloc'd array of Datums, owned by Postgres
+unsafe fn keys_array<K: Into<PgDatum<'static>>>(keys: Vec<K>) -> *mut pg_sys::Datum {
+    if keys.is_empty() {
+        return std::ptr::null_mut();
+    }
+
+    let array = pg_sys::palloc(size_of::<pg_sys::Datum>() * keys.len()) as *mut pg_sys::Datum;
+    for (i, key) in keys.into_iter().enumerate() {
+        *array.add(i) = key_datum(key);
+    }
+
+    array
+}
+
+/// The check array passed to the consistent functions, one for each key of the query
+unsafe fn check_slice<'a, C>(check: *const C, nkeys: i32) -> &'a [C] {
+    if check.is_null() || nkeys <= 0 {
+        return &[];
+    }
+
+    slice::from_raw_parts(check, nkeys as usize)
+}
@@ -0,0 +1,439 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::os::raw::{c_char, c_int};
+
+use crate::index::{
+    datum_to, key_datum, sql_operators, sql_type, support_arg, Consistent, StrategyNumber,
+};
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::{PgDatum, TryFromPgDatum};
+use crate::pg_sys;
+use crate::pg_type::PgTypeInfo;
+
+/// An entry of a GiST index, wraps `GISTENTRY`
+pub struct GistEntry<'mc> {
+    entry: &'mc pg_sys::GISTENTRY,
+    memory_context: &'mc PgAllocator,
+}
+
+impl<'mc> GistEntry<'mc> {
+    /// Wraps a `GISTENTRY` passed to a support function
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw(
+        memory_context: &'mc PgAllocator,
+        entry: *const pg_sys::GISTENTRY,
+    ) -> Self {
+        GistEntry {
+            entry: entry.as_ref().expect("GISTENTRY was unexpectedly NULL"),
+            memory_context,
+        }
+    }
+
+    /// Returns the key, this is the indexed value for a leaf entry that is being compressed
+    pub fn key<T>(&self) -> Result<T, &'static str>
+    where
+        T: TryFromPgDatum<'mc> + 'mc,
+    {
+        let datum = unsafe { PgDatum::from_raw(self.memory_context, self.entry.key, false) };
+        T::try_from(self.memory_context, datum)
+    }
+
+    /// Returns true if the key is a value to be added to the index, i.e. it has not been compressed
+    pub fn is_leaf(&self) -> bool {
+        pgbool!(self.entry.leafkey)
+    }
+
+    /// Returns true if the entry is on a leaf page, `GIST_LEAF`, i.e. its key is that of an indexed value
+    ///  rather than the union of the keys of a child page
+    pub fn is_leaf_page(&self) -> bool {
+        unsafe {
+            // GistPageGetOpaque, the flags are in the special space at the end of the page
+            let page = self.entry.page as *const u8;
+            let header = &*(page as *const pg_sys::PageHeaderData);
+            let opaque = &*(page.add(header.pd_special as usize) as *const pg_sys::GISTPageOpaqueData);
+
+            (opaque.flags & pg_sys::F_LEAF as u16) != 0
+        }
+    }
+
+    /// The offset of the entry on its page
+    pub fn offset(&self) -> pg_sys::OffsetNumber {
+        self.entry.offset
+    }
+}
+
+/// The entries passed to the union and picksplit support functions, wraps `GistEntryVector`
+pub struct GistEntryVector<'mc> {
+    vector: &'mc pg_sys::GistEntryVector,
+    memory_context: &'mc PgAllocator,
+}
+
+impl<'mc> GistEntryVector<'mc> {
+    /// Wraps a `GistEntryVector` passed to a support function
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw(
+        memory_context: &'mc PgAllocator,
+        vector: *const pg_sys::GistEntryVector,
+    ) -> Self {
+        GistEntryVector {
+            vector: vector
+                .as_ref()
+                .expect("GistEntryVector was unexpectedly NULL"),
+            memory_context,
+        }
+    }
+
+    /// The number of entries in the vector
+    pub fn len(&self) -> usize {
+        self.vector.n as usize
+    }
+
+    /// Returns true if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the entry at `index`
+    pub fn get(&self, index: usize) -> Option<GistEntry<'mc>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        unsafe {
+            let entries = self.vector.vector.as_slice(self.len());
+            Some(GistEntry::from_raw(self.memory_context, &entries[index]))
+        }
+    }
+
+    /// Returns an iterator over all the entries
+    pub fn iter<'a>(&'a self) -> impl 'a + Iterator<Item = GistEntry<'mc>> {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+}
+
+/// The result of the picksplit support function, the indexes of the keys for each of the new pages
+///
+/// Each index must be on exactly one of the pages, otherwise an `ERROR` is raised.
+pub struct PickSplit {
+    /// Indexes into the keys for the left page
+    pub left: Vec<usize>,
+    /// Indexes into the keys for the right page
+    pub right: Vec<usize>,
+}
+
+/// The support functions of a GiST operator class.
+///
+/// Use `#[pg_gist]` from pg-extern-attr on the implementing type to generate the Postgres functions and the
+///  `CREATE OPERATOR CLASS ... USING gist` statement. The operators must be created separately, e.g. with
+///  `#[pg_operator]`.
+pub trait GistSupport {
+    /// The type being indexed, `FOR TYPE` of the operator class and the left argument of the operators
+    type Value: for<'s> TryFromPgDatum<'s> + PgTypeInfo;
+    /// The right argument of the operators
+    type Query: for<'s> TryFromPgDatum<'s> + PgTypeInfo;
+    /// The key stored in the index, `STORAGE` of the operator class
+    type Key: for<'s> TryFromPgDatum<'s> + Into<PgDatum<'static>> + PgTypeInfo;
+
+    /// The operators of the operator class and their strategy numbers, e.g. `&[(3, "&&")]`
+    const OPERATORS: &'static [(StrategyNumber, &'static str)];
+
+    /// Converts a value being added to the index into a key, the `compress` support function
+    fn compress(value: Self::Value) -> Self::Key;
+
+    /// Returns if the values under a key can match the query with the operator of `strategy`
+    ///
+    /// For leaf keys this must be an exact answer, or `Consistent::Maybe` to recheck the indexed value.
+    fn consistent(
+        key: &Self::Key,
+        query: &Self::Query,
+        strategy: StrategyNumber,
+        is_leaf: bool,
+    ) -> Consistent;
+
+    /// Returns a key that contains all of the keys, e.g. the bounding box
+    fn union(keys: &[Self::Key]) -> Self::Key;
+
+    /// The cost of inserting the `new` key under the `original` key, e.g. the growth in area
+    fn penalty(original: &Self::Key, new: &Self::Key) -> f32;
+
+    /// Divides the keys of a full page between two new pages
+    ///
+    /// If either page is left empty the keys are split in half instead, as Postgres does, so `union` is never
+    ///  called without keys.
+    fn picksplit(keys: &[Self::Key]) -> PickSplit;
+
+    /// Returns true if the keys are equal
+    fn same(left: &Self::Key, right: &Self::Key) -> bool;
+}
+
+/// Returns the size of the key, `datumGetSize`, e.g. the length of a varlena
+unsafe fn key_size<K: PgTypeInfo>(key: pg_sys::Datum) -> c_int {
+    let oid = crate::spi::type_oid(K::pg_type(), K::is_array()).expect("unsupported GiST key type");
+
+    let mut len: pg_sys::int16 = 0;
+    let mut by_val = pgbool!(false);
+    let mut align: c_char = 0;
+    crate::guard_pg(|| pg_sys::get_typlenbyvalalign(oid, &mut len, &mut by_val, &mut align));
+
+    crate::guard_pg(|| pg_sys::datumGetSize(key, by_val, c_int::from(len))) as c_int
+}
+
+/// Checks each of the indexes of the keys is on exactly one of the pages
+fn check_split(len: usize, left: &[usize], right: &[usize]) -> Result<(), String> {
+    let mut split = vec![false; len];
+
+    for &index in left.iter().chain(right) {
+        match split.get_mut(index) {
+            None => return Err(format!("index {} is out of bounds", index)),
+            Some(true) => return Err(format!("index {} is on a page more than once", index)),
+            Some(split) => *split = true,
+        }
+    }
+
+    match split.iter().position(|split| !split) {
+        Some(index) => Err(format!("index {} is on neither page", index)),
+        None => Ok(()),
+    }
+}
+
+/// Contains the GiST support functions called by Postgres. You should not interact with this directly,
+///   instead use `#[pg_gist]` from pg-extern-attr
+pub struct GistWrapper<T: GistSupport>(PhantomData<T>);
+
+impl<T: GistSupport> GistWrapper<T> {
+    /// `consistent(internal, query, int2, oid, internal) RETURNS bool`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn consistent(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let entry = GistEntry::from_raw(
+            &memory_context,
+            support_arg(func_call_info, 0) as *const pg_sys::GISTENTRY,
+        );
+        let query: T::Query = datum_to(&memory_context, support_arg(func_call_info, 1));
+        let strategy = support_arg(func_call_info, 2) as StrategyNumber;
+        let recheck = support_arg(func_call_info, 4) as *mut u8;
+
+        let key: T::Key = entry.key().expect("unsupported GiST key type");
+        let (matches, needs_recheck) =
+            match T::consistent(&key, &query, strategy, entry.is_leaf_page()) {
+                Consistent::No => (false, false),
+                Consistent::Yes => (true, false),
+                Consistent::Maybe => (true, true),
+            };
+
+        *recheck = needs_recheck as u8;
+        PgDatum::from(matches).into_datum()
+    }
+
+    /// `union(internal, internal) RETURNS storage`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn union(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let entries = GistEntryVector::from_raw(
+            &memory_context,
+            support_arg(func_call_info, 0) as *const pg_sys::GistEntryVector,
+        );
+        let size = support_arg(func_call_info, 1) as *mut c_int;
+
+        let keys = entries
+            .iter()
+            .map(|entry| entry.key().expect("unsupported GiST key type"))
+            .collect::<Vec<T::Key>>();
+
+        let union = key_datum(T::union(&keys));
+        *size = key_size::<T::Key>(union);
+        union
+    }
+
+    /// `compress(internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn compress(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let raw_entry = support_arg(func_call_info, 0) as *mut pg_sys::GISTENTRY;
+        let entry = GistEntry::from_raw(&memory_context, raw_entry);
+
+        // only new values need compressing, keys from the index are already compressed
+        if !entry.is_leaf() {
+            return raw_entry as pg_sys::Datum;
+        }
+
+        let value: T::Value = entry.key().expect("unsupported GiST value type");
+        let key = key_datum(T::compress(value));
+
+        let compressed =
+            pg_sys::palloc(size_of::<pg_sys::GISTENTRY>()) as *mut pg_sys::GISTENTRY;
+        *compressed = pg_sys::GISTENTRY {
+            key,
+            rel: (*raw_entry).rel,
+            page: (*raw_entry).page,
+            offset: (*raw_entry).offset,
+            leafkey: pgbool!(false),
+        };
+
+        compressed as pg_sys::Datum
+    }
+
+    /// `decompress(internal) RETURNS internal`, the keys are stored as is
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn decompress(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        support_arg(func_call_info, 0)
+    }
+
+    /// `penalty(internal, internal, internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn penalty(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let original = GistEntry::from_raw(
+            &memory_context,
+            support_arg(func_call_info, 0) as *const pg_sys::GISTENTRY,
+        );
+        let new = GistEntry::from_raw(
+            &memory_context,
+            support_arg(func_call_info, 1) as *const pg_sys::GISTENTRY,
+        );
+        let penalty = support_arg(func_call_info, 2) as *mut f32;
+
+        let original: T::Key = original.key().expect("unsupported GiST key type");
+        let new: T::Key = new.key().expect("unsupported GiST key type");
+
+        *penalty = T::penalty(&original, &new);
+        penalty as pg_sys::Datum
+    }
+
+    /// `picksplit(internal, internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn picksplit(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let entries = GistEntryVector::from_raw(
+            &memory_context,
+            support_arg(func_call_info, 0) as *const pg_sys::GistEntryVector,
+        );
+        let split = support_arg(func_call_info, 1) as *mut pg_sys::GIST_SPLITVEC;
+
+        // the entries to split start at FirstOffsetNumber, i.e. 1
+        let key = |offset: usize| -> T::Key {
+            entries
+                .get(offset)
+                .expect("picksplit index out of bounds")
+                .key()
+                .expect("unsupported GiST key type")
+        };
+        let keys = (1..entries.len()).map(key).collect::<Vec<T::Key>>();
+
+        let PickSplit {
+            mut left,
+            mut right,
+        } = T::picksplit(&keys);
+
+        // a missing or repeated index would lose or duplicate entries of the index
+        if let Err(err) = check_split(keys.len(), &left, &right) {
+            panic!("invalid picksplit of {} keys: {}", keys.len(), err);
+        }
+
+        if left.is_empty() || right.is_empty() {
+            left = (0..keys.len() / 2).collect();
+            right = (keys.len() / 2..keys.len()).collect();
+        }
+
+        let side = |indexes: &[usize]| -> (*mut pg_sys::OffsetNumber, c_int, pg_sys::Datum) {
+            let offsets = pg_sys::palloc(size_of::<pg_sys::OffsetNumber>() * indexes.len().max(1))
+                as *mut pg_sys::OffsetNumber;
+            for (i, index) in indexes.iter().enumerate() {
+                *offsets.add(i) = (index + 1) as pg_sys::OffsetNumber;
+            }
+
+            let keys = indexes.iter().map(|index| key(index + 1)).collect::<Vec<_>>();
+            let union = key_datum(T::union(&keys));
+
+            (offsets, indexes.len() as c_int, union)
+        };
+
+        let (spl_left, spl_nleft, spl_ldatum) = side(&left);
+        let (spl_right, spl_nright, spl_rdatum) = side(&right);
+
+        (*split).spl_left = spl_left;
+        (*split).spl_nleft = spl_nleft;
+        (*split).spl_ldatum = spl_ldatum;
+        (*split).spl_right = spl_right;
+        (*split).spl_nright = spl_nright;
+        (*split).spl_rdatum = spl_rdatum;
+
+        split as pg_sys::Datum
+    }
+
+    /// `same(storage, storage, internal) RETURNS internal`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn same(func_call_info: pg_sys::FunctionCallInfo) -> pg_sys::Datum {
+        let memory_context = PgAllocator::current_context();
+
+        let left: T::Key = datum_to(&memory_context, support_arg(func_call_info, 0));
+        let right: T::Key = datum_to(&memory_context, support_arg(func_call_info, 1));
+        let result = support_arg(func_call_info, 2) as *mut u8;
+
+        *result = T::same(&left, &right) as u8;
+        result as pg_sys::Datum
+    }
+
+    /// Returns the statements to create the support functions and the operator class `{name}_ops`
+    ///
+    /// The support functions are named `{name}_gist_{function}`, these are exported as `pg_{name}_gist_{function}`
+    ///  by `#[pg_gist]`.
+    pub fn create_stmt(name: &str, library_path: &str) -> String {
+        let value = sql_type::<T::Value>();
+        let query = sql_type::<T::Query>();
+        let key = sql_type::<T::Key>();
+
+        // (support number, function, arguments, return type)
+        let functions = [
+            (1, "consistent", format!("internal, {}, int2, oid, internal", query), "bool"),
+            (2, "union", "internal, internal".to_string(), key),
+            (3, "compress", "internal".to_string(), "internal"),
+            (4, "decompress", "internal".to_string(), "internal"),
+            (5, "penalty", "internal, internal, internal".to_string(), "internal"),
+            (6, "picksplit", "internal, internal".to_string(), "internal"),
+            (7, "same", format!("{0}, {0}, internal", key), "internal"),
+        ];
+
+        let mut stmts = Vec::new();
+        let mut items = sql_operators(T::OPERATORS, value, query);
+
+        for (support, function, args, ret) in functions.iter() {
+            stmts.push(format!(
+                "CREATE or REPLACE FUNCTION {name}_gist_{function}({args}) RETURNS {ret} AS '{library_path}', 'pg_{name}_gist_{function}' LANGUAGE C STRICT;",
+                name = name,
+                function = function,
+                args = args,
+                ret = ret,
+                library_path = library_path,
+            ));
+            items.push(format!(
+                "FUNCTION {} {}_gist_{}({})",
+                support, name, function, args
+            ));
+        }
+
+        items.push(format!("STORAGE {}", key));
+        // there is no `CREATE OR REPLACE OPERATOR CLASS`, an existing operator class is kept as indexes may use it
+        stmts.push(format!(
+            "DO $$ BEGIN CREATE OPERATOR CLASS {}_ops FOR TYPE {} USING gist AS {}; \
+             EXCEPTION WHEN duplicate_object THEN NULL; END $$;",
+            name,
+            value,
+            items.join(", ")
+        ));
+
+        stmts.join("\n")
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::hash::{Hash, Hasher};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a Hasher, for hash index support functions.
+///
+/// Unlike `std::collections::hash_map::DefaultHasher` the hasher itself does not change between releases of
+///  Rust, but the `Hash` implementations it is fed by may. Hash indexes are persisted by Postgres, so they must
+///  be rebuilt with `REINDEX` if the hash of a value changes, e.g. after upgrading Rust.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// The hash support function, `HASHSTANDARD_PROC`, for any `Hash` type.
+///
+/// The 64-bit hash is folded into the 32-bit value expected by Postgres. The hash only stays the same as long
+///  as the `Hash` implementation of the type does, see `FnvHasher`.
+///
+/// ```
+/// extern crate pg_extend;
+/// use pg_extend::index::hash_value;
+///
+/// assert_eq!(hash_value("hello"), hash_value("hello"));
+/// assert_ne!(hash_value("hello"), hash_value("world"));
+/// ```
+pub fn hash_value<T: Hash + ?Sized>(value: &T) -> i32 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+
+    let hash = hasher.finish();
+    (hash ^ (hash >> 32)) as i32
+}
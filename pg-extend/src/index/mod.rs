@@ -0,0 +1,105 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support functions for index operator classes.
+//!
+//! These are used by the functions generated with `#[pg_opclass]`, `#[pg_gist]` and `#[pg_gin]` from pg-extern-attr.
+//!
+//! SP-GiST operator classes are not supported, their support functions pass the inner tuples of the
+//!  partitioned search tree in structures, `spgConfigOut`, `spgChooseOut` and so on, which are not wrapped.
+
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::{PgDatum, TryFromPgDatum};
+use crate::pg_sys;
+
+mod btree;
+mod gin;
+mod gist;
+mod hash;
+
+pub use btree::btree_cmp;
+pub use gin::{GinQuery, GinSearchMode, GinSupport, GinWrapper};
+pub use gist::{GistEntry, GistEntryVector, GistSupport, GistWrapper, PickSplit};
+pub use hash::{hash_value, FnvHasher};
+
+/// The strategy number of an operator in an operator class, e.g. `3` for `&&` in the GiST and GIN operator classes
+///  of the built-in geometric and array types.
+pub type StrategyNumber = pg_sys::StrategyNumber;
+
+/// The result of a consistent support function, whether an index entry can match the query
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consistent {
+    /// The entry can not match the query
+    No,
+    /// The entry matches the query
+    Yes,
+    /// The entry might match the query, the indexed value will be rechecked with the operator
+    Maybe,
+}
+
+/// Returns the argument at `index` of a call to a support function, these are never NULL.
+unsafe fn support_arg(func_call_info: pg_sys::FunctionCallInfo, index: usize) -> pg_sys::Datum {
+    let func_info = func_call_info
+        .as_ref()
+        .expect("func_call_info was unexpectedly NULL");
+
+    crate::get_args(func_info)
+        .nth(index)
+        .and_then(|datum| datum)
+        .expect("support function argument was unexpectedly NULL")
+}
+
+/// Converts an argument of a support function
+unsafe fn datum_to<'mc, T>(memory_context: &'mc PgAllocator, datum: pg_sys::Datum) -> T
+where
+    T: TryFromPgDatum<'mc> + 'mc,
+{
+    T::try_from(
+        memory_context,
+        PgDatum::from_raw(memory_context, datum, false),
+    )
+    .expect("unsupported support function argument type")
+}
+
+/// Converts a key to be stored in the index
+unsafe fn key_datum<K: Into<PgDatum<'static>>>(key: K) -> pg_sys::Datum {
+    let datum: PgDatum = key.into();
+    datum.into_datum()
+}
+
+/// Calls a support function, any panic is raised as an `ERROR` in Postgres.
+///
+/// This is used by the functions generated with `#[pg_gist]` and `#[pg_gin]`.
+pub fn guard_support_function<F>(name: &str, f: F) -> pg_sys::Datum
+where
+    F: FnOnce() -> pg_sys::Datum,
+{
+    // the support functions only pass through pointers given to us by Postgres, an ERROR unwinds them before
+    //  its longjmp is continued by raise_panic
+    match unsafe { crate::catch_unwind_pg(f) } {
+        Ok(datum) => datum,
+        Err(err) => crate::raise_panic(name, err),
+    }
+}
+
+/// Returns the SQL name of the Rust type
+fn sql_type<T: crate::pg_type::PgTypeInfo>() -> &'static str {
+    crate::pg_type::PgType::from_rust::<T>().as_str(T::is_array())
+}
+
+/// Returns the `OPERATOR` items of a `CREATE OPERATOR CLASS` statement
+fn sql_operators(operators: &[(StrategyNumber, &str)], left: &str, right: &str) -> Vec<String> {
+    operators
+        .iter()
+        .map(|(strategy, symbol)| {
+            format!(
+                "OPERATOR {} {} ({}, {})",
+                strategy, symbol, left, right
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,232 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for handling interrupts, e.g. a query being cancelled, and waiting on the latch of the backend.
+//!
+//! Postgres does not interrupt a function when a cancel or termination is requested, rather it sets
+//!  `InterruptPending`, which must be checked with `check`, the equivalent of `CHECK_FOR_INTERRUPTS`. A long
+//!  running loop should check regularly, e.g. with `InterruptsExt::check_interrupts` for iterators.
+//!
+//! ```rust,ignore
+//! #[pg_extern]
+//! fn sum_to(n: i64) -> i64 {
+//!     (0..n).check_interrupts(1_000).sum()
+//! }
+//! ```
+
+use std::os::raw::{c_int, c_long};
+use std::ptr;
+use std::time::Duration;
+
+use crate::pg_sys;
+
+/// Processes a pending interrupt, `CHECK_FOR_INTERRUPTS`
+///
+/// A cancel, e.g. from `pg_cancel_backend` or `statement_timeout`, raises an `ERROR`. Within the functions
+///  generated by pg-extern-attr, e.g. `#[pg_extern]` and `#[pg_trigger]`, hooks, or anything else run in
+///  `catch_unwind_pg`, this unwinds the Rust code, dropping its values, before the longjmp back to Postgres is
+///  continued. A termination exits the backend with `FATAL`.
+pub fn check() {
+    unsafe {
+        if ptr::read_volatile(ptr::addr_of!(pg_sys::InterruptPending)) != 0 {
+            crate::guard_pg(|| pg_sys::ProcessInterrupts());
+        }
+    }
+}
+
+/// A wait on the latch of the backend, `WaitLatchOrSocket`, until it is set, e.g. by a signal, a timeout
+///  passes, or a socket is ready
+///
+/// ```rust,ignore
+/// let result = LatchWait::new()
+///     .timeout(Duration::from_secs(1))
+///     .socket_readable(stream.as_raw_fd())
+///     .wait();
+///
+/// if result.socket_readable() {
+///     // read from the socket
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LatchWait {
+    timeout: Option<Duration>,
+    socket: pg_sys::pgsocket,
+    socket_events: c_int,
+    #[cfg_attr(postgres9, allow(dead_code))]
+    wait_event: u32,
+}
+
+impl Default for LatchWait {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatchWait {
+    /// Waits for the latch to be set, without a timeout
+    pub fn new() -> Self {
+        Self {
+            timeout: None,
+            socket: pg_sys::PGINVALID_SOCKET as pg_sys::pgsocket,
+            socket_events: 0,
+            wait_event: pg_sys::PG_WAIT_EXTENSION,
+        }
+    }
+
+    /// Waits for at most the timeout, `WL_TIMEOUT`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Waits for the socket to be readable, `WL_SOCKET_READABLE`
+    pub fn socket_readable(mut self, socket: pg_sys::pgsocket) -> Self {
+        self.socket = socket;
+        self.socket_events |= pg_sys::WL_SOCKET_READABLE as c_int;
+        self
+    }
+
+    /// Waits for the socket to be writeable, `WL_SOCKET_WRITEABLE`
+    pub fn socket_writeable(mut self, socket: pg_sys::pgsocket) -> Self {
+        self.socket = socket;
+        self.socket_events |= pg_sys::WL_SOCKET_WRITEABLE as c_int;
+        self
+    }
+
+    /// The wait event the backend is shown to be waiting on in `pg_stat_activity`, `PG_WAIT_EXTENSION` by
+    ///  default, e.g. `PG_WAIT_EXTENSION | 1`
+    ///
+    /// Postgres does not support registering the names of custom wait events, so any event in the
+    ///  `PG_WAIT_EXTENSION` class is shown as `Extension`. The wait event is not reported before Postgres 10.
+    pub fn wait_event(mut self, wait_event: u32) -> Self {
+        self.wait_event = wait_event;
+        self
+    }
+
+    /// Waits for the latch, the timeout or the socket, then resets the latch and checks for interrupts
+    ///
+    /// A pending interrupt raises an `ERROR`, see `check`. If the postmaster has died the backend exits.
+    pub fn wait(self) -> WaitResult {
+        let mut events =
+            self.socket_events | (pg_sys::WL_LATCH_SET | pg_sys::WL_POSTMASTER_DEATH) as c_int;
+        let timeout = match self.timeout {
+            Some(timeout) => {
+                events |= pg_sys::WL_TIMEOUT as c_int;
+                timeout.as_millis().min(c_long::MAX as u128) as c_long
+            }
+            None => -1,
+        };
+
+        let events = unsafe {
+            #[cfg(postgres9)]
+            let rc = crate::guard_pg(|| {
+                pg_sys::WaitLatchOrSocket(pg_sys::MyLatch, events, self.socket, timeout)
+            });
+            #[cfg(not(postgres9))]
+            let rc = crate::guard_pg(|| {
+                pg_sys::WaitLatchOrSocket(
+                    pg_sys::MyLatch,
+                    events,
+                    self.socket,
+                    timeout,
+                    self.wait_event,
+                )
+            });
+
+            // there is no postmaster left to manage the backend
+            if rc & pg_sys::WL_POSTMASTER_DEATH as c_int != 0 {
+                pg_sys::proc_exit(1);
+            }
+
+            if rc & pg_sys::WL_LATCH_SET as c_int != 0 {
+                crate::guard_pg(|| pg_sys::ResetLatch(pg_sys::MyLatch));
+            }
+
+            rc
+        };
+
+        check();
+        WaitResult { events }
+    }
+}
+
+/// The events which ended a `LatchWait`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaitResult {
+    events: c_int,
+}
+
+impl WaitResult {
+    /// Returns true if the latch was set, `WL_LATCH_SET`
+    pub fn latch_set(self) -> bool {
+        self.events & pg_sys::WL_LATCH_SET as c_int != 0
+    }
+
+    /// Returns true if the timeout passed, `WL_TIMEOUT`
+    pub fn timed_out(self) -> bool {
+        self.events & pg_sys::WL_TIMEOUT as c_int != 0
+    }
+
+    /// Returns true if the socket is readable, `WL_SOCKET_READABLE`
+    pub fn socket_readable(self) -> bool {
+        self.events & pg_sys::WL_SOCKET_READABLE as c_int != 0
+    }
+
+    /// Returns true if the socket is writeable, `WL_SOCKET_WRITEABLE`
+    pub fn socket_writeable(self) -> bool {
+        self.events & pg_sys::WL_SOCKET_WRITEABLE as c_int != 0
+    }
+}
+
+/// An iterator which checks for interrupts every so many items, returned by `InterruptsExt::check_interrupts`
+pub struct CheckInterrupts<I> {
+    iter: I,
+    every: usize,
+    count: usize,
+}
+
+impl<I: Iterator> Iterator for CheckInterrupts<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.count += 1;
+        if self.count == self.every {
+            self.count = 0;
+            check();
+        }
+
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Adds `check_interrupts` to iterators
+pub trait InterruptsExt: Iterator + Sized {
+    /// Checks for interrupts before every so many items, see `check`, e.g. to allow a long running loop to be
+    ///  cancelled
+    ///
+    /// # Panics
+    ///
+    /// If `every` is zero.
+    fn check_interrupts(self, every: usize) -> CheckInterrupts<Self> {
+        assert!(
+            every > 0,
+            "interrupts must be checked every one or more items"
+        );
+
+        CheckInterrupts {
+            iter: self,
+            every,
+            count: 0,
+        }
+    }
+}
+
+impl<I: Iterator> InterruptsExt for I {}
@@ -11,7 +11,7 @@
 use std::mem;
 use std::os::raw::c_int;
 use std::sync::atomic::compiler_fence;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub mod pg_alloc;
 pub mod pg_sys;
@@ -22,21 +22,70 @@ pub mod pg_error;
 pub mod pg_fdw;
 pub mod pg_type;
 
+pub mod bgworker;
+pub mod dsm;
+pub mod event_trigger;
+pub mod guc;
+pub mod hooks;
+pub mod index;
+pub mod interrupts;
+pub mod lock;
 pub mod log;
 pub mod native;
+pub mod notify;
+pub mod row;
+pub mod shm_mq;
+pub mod shmem;
+pub mod spi;
+pub mod trigger;
+pub mod xact;
 
 /// A macro for marking a library compatible with the Postgres extension framework.
 ///
+/// This emits the `Pg_magic_func` Postgres checks the library was built for its version with, and the `_PG_init`
+///  Postgres calls once the library is loaded into a backend, which registers the panic handler.
+///
+/// An `init` function can be given, which is called from `_PG_init`, this is the place to define GUCs, request
+///  shared memory and install hooks. A `fini` function can also be given, which is called from `_PG_fini`,
+///  though Postgres does not currently unload libraries, so it is never called in practice. A panic in either
+///  fails the loading of the library with an `ERROR`.
+///
+/// ```rust,ignore
+/// pg_magic!(version: pg_sys::PG_VERSION_NUM, init: init);
+///
+/// fn init() {
+///     xact::on_xact_event(|event| notice!("transaction event: {:?}", event));
+/// }
+/// ```
+///
 /// This macro was initially inspired from the `pg_module` macro in https://github.com/thehydroimpulse/postgres-extension.rs
 #[macro_export]
 macro_rules! pg_magic {
     (version: $vers:expr) => {
+        $crate::pg_magic!(@magic $vers);
+        $crate::pg_magic!(@init);
+    };
+    (version: $vers:expr, init: $init:path) => {
+        $crate::pg_magic!(@magic $vers);
+        $crate::pg_magic!(@init $init);
+    };
+    (version: $vers:expr, init: $init:path, fini: $fini:path) => {
+        $crate::pg_magic!(@magic $vers);
+        $crate::pg_magic!(@init $init);
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn _PG_fini() {
+            unsafe { pg_extend::call_library_fn("_PG_fini", $fini) }
+        }
+    };
+    (@magic $vers:expr) => {
         #[no_mangle]
         #[allow(non_snake_case)]
         #[allow(unused)]
         #[link_name = "Pg_magic_func"]
         pub extern "C" fn Pg_magic_func() -> &'static pg_extend::pg_sys::Pg_magic_struct {
-            use pg_extend::{pg_sys, register_panic_handler};
+            use pg_extend::pg_sys;
             use std::mem::size_of;
             use std::os::raw::c_int;
 
@@ -50,14 +99,32 @@ macro_rules! pg_magic {
                 float8byval: pg_sys::USE_FLOAT8_BYVAL as c_int,
             };
 
-            // TODO: is this a good idea here?
-            // register panic_handler
-            register_panic_handler();
-
             // return the magic
             &my_magic
         }
     };
+    (@init $($init:path)?) => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn _PG_init() {
+            unsafe { pg_extend::call_library_fn("_PG_init", || { $($init();)? }) }
+        }
+    };
+}
+
+/// Calls the `_PG_init` or `_PG_fini` function of the library, this is used by the functions generated by
+///  `pg_magic!`.
+///
+/// The panic handler is registered first, a panic is then raised as an `ERROR`.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn call_library_fn<F: FnOnce()>(name: &str, library_fn: F) {
+    register_panic_handler();
+
+    // guard the Postgres process against the panic, an ERROR unwinds the function before its longjmp is
+    //  continued
+    if let Err(err) = catch_unwind_pg(library_fn) {
+        raise_panic(name, err);
+    }
 }
 
 #[cfg(postgres12)]
@@ -99,11 +166,98 @@ pub fn get_args<'a>(
     };
 }
 
+/// Raises the panic of a Rust function called by Postgres as an `ERROR`, this longjmps back to Postgres, it is
+///  used by the functions generated by `#[pg_extern]`.
+///
+/// An `ERROR` raised by Postgres is caught as a panic when it is within `catch_unwind_pg`, e.g. in a hook, its
+///  longjmp is then continued, keeping its message and SQLSTATE. A `PgError` panic, `panic_any(err)`, is
+///  raised as is.
+pub fn raise_panic(name: &str, err: Box<dyn std::any::Any + Send>) -> ! {
+    // within `catch_unwind_pg` the ERROR raised here is converted back into a panic by `guard_pg`, which must
+    //  not unwind into Postgres, its longjmp is continued below
+    let raised = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+            unsafe { jump_context.continue_longjmp() };
+        }
+
+        if let Some(pg_error) = err.downcast_ref::<pg_error::PgError>() {
+            unsafe { pg_error.clone().raise() };
+        }
+
+        // The Rust code paniced, we need to recover to Postgres via a longjump
+        //   A postgres logging error of Error will do this for us.
+        compiler_fence(Ordering::SeqCst);
+        if let Some(msg) = err.downcast_ref::<&'static str>() {
+            error!("panic executing Rust '{}': {}", name, msg);
+        }
+
+        if let Some(msg) = err.downcast_ref::<String>() {
+            error!("panic executing Rust '{}': {}", name, msg);
+        }
+
+        error!("panic executing Rust '{}'", name);
+    }));
+
+    if let Err(err) = raised {
+        if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+            unsafe { jump_context.continue_longjmp() };
+        }
+    }
+
+    // the above should have longjmped, this is a bug in pg-extend-rs, nothing may unwind into Postgres
+    std::process::abort();
+}
+
+/// The number of `catch_unwind_pg` calls being run, while there are any panics unwind to them rather than
+///  longjmping back to Postgres
+static CATCHING_PANICS: AtomicUsize = AtomicUsize::new(0);
+
+/// Calls the closure, catching a panic, including an `ERROR` raised by Postgres within it
+///
+/// An `ERROR` is caught as a `JumpContext` panic, the error state must then be cleaned up, e.g. by rolling back
+///  a subtransaction, or the longjmp continued with `JumpContext::continue_longjmp`. It is used by the
+///  functions generated by `#[pg_extern]`, which continue the longjmp with `raise_panic`, so that an `ERROR`
+///  unwinds their Rust code.
+///
+/// # Safety
+///
+/// A caught `ERROR` must be handled as above before returning to Postgres.
+pub unsafe fn catch_unwind_pg<R, F: FnOnce() -> R>(f: F) -> std::thread::Result<R> {
+    let exception_stack = pg_sys::PG_exception_stack;
+    let error_context_stack = pg_sys::error_context_stack;
+
+    CATCHING_PANICS.fetch_add(1, Ordering::SeqCst);
+    // any longjmp, even from Postgres functions called directly, is converted to a panic and caught here
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| guard_pg(f)));
+    CATCHING_PANICS.fetch_sub(1, Ordering::SeqCst);
+
+    // the unwinding skipped the restoration of these by guard_pg and the functions that longjmped
+    pg_sys::PG_exception_stack = exception_stack;
+    pg_sys::error_context_stack = error_context_stack;
+
+    result
+}
+
+/// Returns the message of the panic, if it has one
+pub(crate) fn panic_message(err: &(dyn std::any::Any + Send)) -> Option<&str> {
+    err.downcast_ref::<&'static str>()
+        .copied()
+        .or_else(|| err.downcast_ref::<String>().map(String::as_str))
+}
+
 /// Information for a longjmp
-struct JumpContext {
+pub(crate) struct JumpContext {
     jump_value: c_int,
 }
 
+impl JumpContext {
+    /// Continues the longjmp to the current `PG_exception_stack`, after the panic it was converted to is caught
+    pub(crate) unsafe fn continue_longjmp(&self) -> ! {
+        pg_sys_longjmp(pg_sys::PG_exception_stack as *mut _, self.jump_value);
+        unreachable!("longjmp should not return, this is a bug in pg-extend-rs");
+    }
+}
+
 /// This will replace the current panic_handler
 pub fn register_panic_handler() {
     use std::panic;
@@ -111,6 +265,11 @@ pub fn register_panic_handler() {
     // set (and replace the existing) panic handler, this will tell Postgres that the call failed
     //   a level of Fatal will force the DB connection to be killed.
     panic::set_hook(Box::new(|info| {
+        // within `catch_unwind_pg` the panic unwinds to it to be handled, e.g. by a subtransaction
+        if CATCHING_PANICS.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+
         // downcast info, check if it's the value we need.
         //   this must check if the panic was due to a longjmp
         //   the fence is to make sure the longjmp is not reodered.
@@ -122,10 +281,13 @@ pub fn register_panic_handler() {
 
             // the panic came from a pg longjmp... so unwrap it and rethrow
             unsafe {
-                pg_sys_longjmp(
-                    pg_sys::PG_exception_stack as *mut _,
-                    panic_context.jump_value,
-                );
+                panic_context.continue_longjmp();
+            }
+        } else if let Some(pg_error) = info.payload().downcast_ref::<pg_error::PgError>() {
+            // an error to raise with its SQLSTATE, e.g. one returned by SPI, `panic_any(err)`. A panic within
+            //  the panic hook aborts, so the longjmp of the ERROR must not be guarded
+            unsafe {
+                pg_error.clone().raise_unguarded();
             }
         } else {
             // error level will cause a longjmp in Postgres
@@ -0,0 +1,184 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for heavyweight locks, advisory locks for coordinating between sessions, and locks on relations.
+//!
+//! An `AdvisoryLock` is the same lock as `pg_advisory_lock` with an `int8` key takes, so it can be shared with
+//!  SQL, it is released when dropped.
+//!
+//! ```rust,ignore
+//! const JOB_LOCK: i64 = 42;
+//!
+//! #[pg_extern]
+//! fn run_job() -> bool {
+//!     let _lock = match AdvisoryLock::try_session(JOB_LOCK) {
+//!         Some(lock) => lock,
+//!         None => return false,
+//!     };
+//!
+//!     // only one session runs the job at a time
+//!     true
+//! }
+//! ```
+
+use std::mem;
+
+use crate::pg_sys;
+
+/// The modes of table-level locks, see the Postgres documentation on explicit locking for the conflicts between
+///  them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// Taken by `SELECT`, `AccessShareLock`
+    AccessShare,
+    /// Taken by `SELECT FOR UPDATE`, `RowShareLock`
+    RowShare,
+    /// Taken by `INSERT`, `UPDATE` and `DELETE`, `RowExclusiveLock`
+    RowExclusive,
+    /// Taken by `VACUUM` and `ANALYZE`, `ShareUpdateExclusiveLock`
+    ShareUpdateExclusive,
+    /// Taken by `CREATE INDEX`, `ShareLock`
+    Share,
+    /// Taken by `CREATE TRIGGER`, `ShareRowExclusiveLock`
+    ShareRowExclusive,
+    /// Taken by `REFRESH MATERIALIZED VIEW CONCURRENTLY`, `ExclusiveLock`
+    Exclusive,
+    /// Taken by `DROP TABLE` and `ALTER TABLE`, `AccessExclusiveLock`
+    AccessExclusive,
+}
+
+impl From<LockMode> for pg_sys::LOCKMODE {
+    fn from(mode: LockMode) -> Self {
+        let mode = match mode {
+            LockMode::AccessShare => pg_sys::AccessShareLock,
+            LockMode::RowShare => pg_sys::RowShareLock,
+            LockMode::RowExclusive => pg_sys::RowExclusiveLock,
+            LockMode::ShareUpdateExclusive => pg_sys::ShareUpdateExclusiveLock,
+            LockMode::Share => pg_sys::ShareLock,
+            LockMode::ShareRowExclusive => pg_sys::ShareRowExclusiveLock,
+            LockMode::Exclusive => pg_sys::ExclusiveLock,
+            LockMode::AccessExclusive => pg_sys::AccessExclusiveLock,
+        };
+
+        mode as pg_sys::LOCKMODE
+    }
+}
+
+/// Locks the relation, `LockRelationOid`, waiting for conflicting locks to be released
+///
+/// The lock is held until the end of the transaction, or until it is unlocked with `unlock_relation_oid`.
+pub fn lock_relation_oid(relation: pg_sys::Oid, mode: LockMode) {
+    unsafe { crate::guard_pg(|| pg_sys::LockRelationOid(relation, mode.into())) };
+}
+
+/// Locks the relation if no conflicting locks are held, `ConditionalLockRelationOid`, returning true if it was
+///  locked
+pub fn try_lock_relation_oid(relation: pg_sys::Oid, mode: LockMode) -> bool {
+    let locked =
+        unsafe { crate::guard_pg(|| pg_sys::ConditionalLockRelationOid(relation, mode.into())) };
+
+    pgbool!(locked)
+}
+
+/// Unlocks the relation, `UnlockRelationOid`, which must have been locked in the mode
+pub fn unlock_relation_oid(relation: pg_sys::Oid, mode: LockMode) {
+    unsafe { crate::guard_pg(|| pg_sys::UnlockRelationOid(relation, mode.into())) };
+}
+
+/// An exclusive advisory lock on an `int8` key in the current database, which is released when dropped
+///
+/// A session lock is held until it is dropped, a transaction lock until it is dropped or the transaction ends.
+///  Use `hold` to keep the lock past the guard, which is then the same as one taken with `pg_advisory_lock`
+///  or `pg_advisory_xact_lock`.
+pub struct AdvisoryLock {
+    tag: pg_sys::LOCKTAG,
+    session: bool,
+}
+
+impl AdvisoryLock {
+    /// Takes a session lock, `pg_advisory_lock`, waiting for it to be released by other sessions
+    pub fn session(key: i64) -> Self {
+        Self::acquire(key, true, false).expect("waiting for the lock always acquires it")
+    }
+
+    /// Takes a transaction lock, `pg_advisory_xact_lock`, waiting for it to be released by other sessions
+    pub fn transaction(key: i64) -> Self {
+        Self::acquire(key, false, false).expect("waiting for the lock always acquires it")
+    }
+
+    /// Takes a session lock if it is not held by another session, `pg_try_advisory_lock`
+    pub fn try_session(key: i64) -> Option<Self> {
+        Self::acquire(key, true, true)
+    }
+
+    /// Takes a transaction lock if it is not held by another session, `pg_try_advisory_xact_lock`
+    pub fn try_transaction(key: i64) -> Option<Self> {
+        Self::acquire(key, false, true)
+    }
+
+    fn acquire(key: i64, session: bool, dont_wait: bool) -> Option<Self> {
+        let tag = advisory_tag(key);
+
+        let result = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::LockAcquire(
+                    &tag,
+                    pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+                    pgbool!(session),
+                    pgbool!(dont_wait),
+                )
+            })
+        };
+
+        if result == pg_sys::LockAcquireResult_LOCKACQUIRE_NOT_AVAIL {
+            return None;
+        }
+
+        Some(Self { tag, session })
+    }
+
+    /// Keeps the lock after the guard, until it is unlocked with `pg_advisory_unlock` or the session ends for a
+    ///  session lock, or until the transaction ends for a transaction lock
+    pub fn hold(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        // the abort of the transaction releases a transaction lock after an ERROR, which may be why this is
+        //  unwinding, a session lock outlives the transaction so it is still released
+        if std::thread::panicking() && !self.session {
+            return;
+        }
+
+        unsafe {
+            crate::guard_pg(|| {
+                pg_sys::LockRelease(
+                    &self.tag,
+                    pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+                    pgbool!(self.session),
+                )
+            })
+        };
+    }
+}
+
+/// The tag of the advisory lock on the key, `SET_LOCKTAG_INT64`, which is a macro bindgen can not emit
+fn advisory_tag(key: i64) -> pg_sys::LOCKTAG {
+    let key = key as u64;
+
+    pg_sys::LOCKTAG {
+        locktag_field1: unsafe { pg_sys::MyDatabaseId },
+        locktag_field2: (key >> 32) as u32,
+        locktag_field3: key as u32,
+        // 1 for an int8 key, 2 for a pair of int4 keys
+        locktag_field4: 1,
+        locktag_type: pg_sys::LockTagType_LOCKTAG_ADVISORY as u8,
+        locktag_lockmethodid: pg_sys::USER_LOCKMETHOD as u8,
+    }
+}
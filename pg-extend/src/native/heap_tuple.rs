@@ -0,0 +1,151 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ffi::CString;
+use std::os::raw::c_int;
+
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::{PgDatum, TryFromPgDatum};
+use crate::pg_error::PgError;
+use crate::pg_sys;
+use crate::pg_type::PgTypeInfo;
+use crate::row::check_type;
+
+/// A row of a table, `HeapTuple`, with the descriptor of its columns
+pub struct HeapTuple<'mc> {
+    tuple: pg_sys::HeapTuple,
+    tupdesc: pg_sys::TupleDesc,
+    memory_context: &'mc PgAllocator,
+}
+
+impl<'mc> HeapTuple<'mc> {
+    /// Wraps a tuple allocated in the memory context
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw(
+        memory_context: &'mc PgAllocator,
+        tuple: pg_sys::HeapTuple,
+        tupdesc: pg_sys::TupleDesc,
+    ) -> Self {
+        assert!(!tuple.is_null(), "HeapTuple was unexpectedly NULL");
+        assert!(!tupdesc.is_null(), "TupleDesc was unexpectedly NULL");
+
+        HeapTuple {
+            tuple,
+            tupdesc,
+            memory_context,
+        }
+    }
+
+    /// The number of columns
+    pub fn len(&self) -> usize {
+        unsafe { (*self.tupdesc).natts as usize }
+    }
+
+    /// Returns true if there are no columns
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number, starting at 1, of the column with the name
+    pub fn column_number(&self, name: &str) -> Result<usize, PgError> {
+        let c_name = CString::new(name)
+            .map_err(|_| PgError::new(format!("invalid column name: {}", name)))?;
+
+        let number = unsafe { crate::guard_pg(|| pg_sys::SPI_fnumber(self.tupdesc, c_name.as_ptr())) };
+        if number <= 0 {
+            return Err(PgError::new(format!("column \"{}\" does not exist", name)).with_sqlstate("42703"));
+        }
+
+        Ok(number as usize)
+    }
+
+    /// Returns the Oid of the type of the column with the number, starting at 1
+    pub fn column_type(&self, number: usize) -> pg_sys::Oid {
+        unsafe { crate::guard_pg(|| pg_sys::SPI_gettypeid(self.tupdesc, number as c_int)) }
+    }
+
+    /// Returns the value of the column with the name
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    pub fn get<T>(&self, name: &str) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        let number = self.column_number(name)?;
+        self.get_checked(&format!("\"{}\"", name), number)
+    }
+
+    /// Returns the value of the column with the number, starting at 1
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    pub fn get_by_number<T>(&self, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        if number == 0 || number > self.len() {
+            return Err(PgError::new(format!("column number {} does not exist", number)).with_sqlstate("42703"));
+        }
+
+        self.get_checked(&format!("number {}", number), number)
+    }
+
+    /// Returns the value of the column, e.g. `"name"` or `number 2`, after checking its type is that of `T`
+    fn get_checked<T>(&self, column: &str, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        check_type::<T>(column, self.column_type(number))?;
+
+        let datum = unsafe {
+            let mut is_null = pgbool!(false);
+            let datum = crate::guard_pg(|| {
+                pg_sys::SPI_getbinval(self.tuple, self.tupdesc, number as c_int, &mut is_null)
+            });
+
+            PgDatum::from_raw(self.memory_context, datum, is_null)
+        };
+
+        T::try_from(self.memory_context, datum)
+            .map_err(|err| PgError::new(format!("column {}: {}", column, err)))
+    }
+
+    /// Returns a copy of the tuple with the value of the column replaced, e.g. to return from a `BEFORE` trigger
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    pub fn set<T>(&self, name: &str, value: T) -> Result<HeapTuple<'mc>, PgError>
+    where
+        T: Into<PgDatum<'mc>> + PgTypeInfo,
+    {
+        let number = self.column_number(name)?;
+        check_type::<T>(&format!("\"{}\"", name), self.column_type(number))?;
+
+        let mut number = number as c_int;
+        let value: PgDatum<'mc> = value.into();
+        let mut is_null = pgbool!(value.is_null());
+        let mut datum = unsafe { value.into_datum() };
+
+        unsafe {
+            let tuple = crate::guard_pg(|| {
+                pg_sys::heap_modify_tuple_by_cols(
+                    self.tuple,
+                    self.tupdesc,
+                    1,
+                    &mut number,
+                    &mut datum,
+                    &mut is_null,
+                )
+            });
+
+            Ok(HeapTuple::from_raw(self.memory_context, tuple, self.tupdesc))
+        }
+    }
+
+    /// Returns the raw tuple, e.g. to return it to Postgres
+    pub fn into_raw(self) -> pg_sys::HeapTuple {
+        self.tuple
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for sending notifications to sessions which have run `LISTEN` on a channel, the same as `NOTIFY`.
+//!
+//! ```rust,ignore
+//! #[pg_extern]
+//! fn job_finished(id: i64) {
+//!     notify::notify("jobs", &id.to_string()).expect("failed to notify");
+//! }
+//! ```
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+
+use crate::pg_sys;
+
+/// The maximum length of a payload in bytes, `NOTIFY_PAYLOAD_MAX_LENGTH`, which is a macro bindgen can not emit
+const NOTIFY_PAYLOAD_MAX_LENGTH: usize = (pg_sys::BLCKSZ - pg_sys::NAMEDATALEN - 128) as usize;
+
+/// The reason a notification could not be sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyError {
+    /// The channel name is empty
+    EmptyChannel,
+    /// The channel name is `NAMEDATALEN` or more bytes
+    ChannelTooLong,
+    /// The payload is `NOTIFY_PAYLOAD_MAX_LENGTH`, 8000 by default, or more bytes
+    PayloadTooLong,
+    /// The channel name or payload contains a NULL byte
+    NulByte,
+    /// Notifications are sent when a transaction commits, so one must be in progress, e.g. with
+    ///  `BackgroundWorker::transaction`
+    NoTransaction,
+    /// Notifications can not be sent from a parallel worker
+    ParallelWorker,
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotifyError::EmptyChannel => f.write_str("channel name cannot be empty"),
+            NotifyError::ChannelTooLong => f.write_str("channel name too long"),
+            NotifyError::PayloadTooLong => f.write_str("payload string too long"),
+            NotifyError::NulByte => f.write_str("channel name or payload contains a NULL byte"),
+            NotifyError::NoTransaction => f.write_str("notifications require a transaction"),
+            NotifyError::ParallelWorker => {
+                f.write_str("cannot send notifications from a parallel worker")
+            }
+        }
+    }
+}
+
+impl Error for NotifyError {}
+
+/// Sends the notification on the channel, `Async_Notify`, which is delivered to the listening sessions when the
+///  transaction commits
+///
+/// Notifications with the same channel and payload in a transaction are only delivered once.
+pub fn notify(channel: &str, payload: &str) -> Result<(), NotifyError> {
+    if channel.is_empty() {
+        return Err(NotifyError::EmptyChannel);
+    }
+
+    if channel.len() >= pg_sys::NAMEDATALEN as usize {
+        return Err(NotifyError::ChannelTooLong);
+    }
+
+    if payload.len() >= NOTIFY_PAYLOAD_MAX_LENGTH {
+        return Err(NotifyError::PayloadTooLong);
+    }
+
+    let channel = CString::new(channel).map_err(|_| NotifyError::NulByte)?;
+    let payload = CString::new(payload).map_err(|_| NotifyError::NulByte)?;
+
+    let in_transaction: bool = unsafe { pgbool!(crate::guard_pg(|| pg_sys::IsTransactionState())) };
+    if !in_transaction {
+        return Err(NotifyError::NoTransaction);
+    }
+
+    // IsParallelWorker
+    if unsafe { pg_sys::ParallelWorkerNumber } >= 0 {
+        return Err(NotifyError::ParallelWorker);
+    }
+
+    unsafe { crate::guard_pg(|| pg_sys::Async_Notify(channel.as_ptr(), payload.as_ptr())) };
+    Ok(())
+}
@@ -22,7 +22,7 @@ pub struct PgAllocator(NonNull<pg_sys::MemoryContextData>);
 
 impl PgAllocator {
     /// Instantiate a PgAllocator from the raw pointer.
-    unsafe fn from_raw(context: *mut pg_sys::MemoryContextData) -> Self {
+    pub(crate) unsafe fn from_raw(context: *mut pg_sys::MemoryContextData) -> Self {
         Self(NonNull::new_unchecked(context))
     }
 
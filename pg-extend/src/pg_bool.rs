@@ -7,6 +7,13 @@
 
 //! Support for Postgres boolean values
 
+/// The C `bool` of Postgres, which is a `char` before Postgres 11, e.g. for the arguments of hooks
+#[cfg(any(postgres9, postgres10))]
+pub(crate) type PgBool = std::os::raw::c_char;
+/// The C `bool` of Postgres, which is a `char` before Postgres 11, e.g. for the arguments of hooks
+#[cfg(not(any(postgres9, postgres10)))]
+pub(crate) type PgBool = bool;
+
 const TRUE_U8: u8 = 1;
 const FALSE_U8: u8 = 0;
 
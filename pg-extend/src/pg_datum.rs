@@ -86,6 +86,26 @@ pub trait TryFromPgDatum<'s>: Sized {
         'mc: 's;
 }
 
+impl<'s> TryFromPgDatum<'s> for bool {
+    fn try_from<'mc>(_: &'mc PgAllocator, datum: PgDatum<'mc>) -> Result<Self, &'static str>
+    where
+        Self: 's,
+        'mc: 's,
+    {
+        if let Some(datum) = datum.0 {
+            Ok(datum != 0)
+        } else {
+            Err("datum was NULL")
+        }
+    }
+}
+
+impl From<bool> for PgDatum<'_> {
+    fn from(value: bool) -> Self {
+        PgDatum(Some(value as Datum), PhantomData)
+    }
+}
+
 impl<'s> TryFromPgDatum<'s> for i16 {
     fn try_from<'mc>(_: &'mc PgAllocator, datum: PgDatum<'mc>) -> Result<Self, &'static str>
     where
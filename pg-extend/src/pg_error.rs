@@ -6,11 +6,15 @@
 // copied, modified, or distributed except according to those terms.
 
 //! Error reporting support for Postgres.
-//! This module is deprecated, use the logging macros in the [`pg_extend::log` module].
+//!
+//! `PgError` is returned by Rust functions to raise an `ERROR` in Postgres. The logging in this module is
+//! deprecated, use the logging macros in the [`pg_extend::log` module].
 //!
 //! [`pg_extend::log` module]: ../log/index.html
 #![allow(deprecated)]
 
+use std::ffi::{CStr, CString};
+use std::fmt;
 use std::os::raw::{c_char, c_int};
 
 use crate::{pg_bool, pg_sys};
@@ -104,3 +108,282 @@ where
         })
     }
 }
+
+/// An error to be raised in Postgres, i.e. `ereport(ERROR, ...)`, aborting the current transaction
+///
+/// ```
+/// extern crate pg_extend;
+/// use pg_extend::pg_error::PgError;
+///
+/// let err = PgError::new("value out of range")
+///     .with_detail("expected a value between 1 and 10")
+///     .with_sqlstate("22003");
+///
+/// assert_eq!(err.message(), "value out of range");
+/// assert_eq!(err.sqlstate(), Some("22003"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgError {
+    message: String,
+    detail: Option<String>,
+    hint: Option<String>,
+    sqlstate: Option<String>,
+}
+
+impl PgError {
+    /// A new error with the primary message
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        PgError {
+            message: message.into(),
+            detail: None,
+            hint: None,
+            sqlstate: None,
+        }
+    }
+
+    /// Adds the detail message, `errdetail`
+    pub fn with_detail<S: Into<String>>(mut self, detail: S) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Adds the hint message, `errhint`
+    pub fn with_hint<S: Into<String>>(mut self, hint: S) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Sets the five character SQLSTATE code, `errcode`, e.g. `22003` for `numeric_value_out_of_range`.
+    ///
+    /// The default is `XX000`, `internal_error`.
+    ///
+    /// # Panics
+    ///
+    /// If the code is not five digits or upper case letters
+    pub fn with_sqlstate<S: Into<String>>(mut self, sqlstate: S) -> Self {
+        let sqlstate = sqlstate.into();
+        assert!(
+            sqlstate.len() == 5
+                && sqlstate
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase()),
+            "invalid SQLSTATE: {}",
+            sqlstate
+        );
+
+        self.sqlstate = Some(sqlstate);
+        self
+    }
+
+    /// The primary message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The detail message
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    /// The hint message
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// The SQLSTATE code
+    pub fn sqlstate(&self) -> Option<&str> {
+        self.sqlstate.as_deref()
+    }
+
+    /// Raises the error in Postgres, this longjmps out of the current function.
+    ///
+    /// # Safety
+    ///
+    /// No Rust destructors are run for the frames being jumped over, this should only be called at the
+    ///  FFI boundary with Postgres, after any Rust values have been dropped.
+    pub unsafe fn raise(self) -> ! {
+        crate::guard_pg(|| self.raise_unguarded());
+        unreachable!("errfinish should have longjmped, this is a bug in pg-extend-rs");
+    }
+
+    /// Raises the error in Postgres without `guard_pg`, the longjmp is not converted to a panic, e.g. from the
+    ///  panic hook, where a second panic would abort the process
+    ///
+    /// # Safety
+    ///
+    /// See `raise`.
+    pub(crate) unsafe fn raise_unguarded(self) -> ! {
+        use std::sync::atomic::{compiler_fence, Ordering};
+
+        let to_cstring = |msg: String| {
+            CString::new(msg).unwrap_or_else(|_| {
+                CString::new("failed to convert error to a CString, check extension code for incompatible `CString` messages")
+                    .expect("this should not fail: msg")
+            })
+        };
+
+        let message = to_cstring(self.message);
+        let detail = self.detail.map(to_cstring);
+        let hint = self.hint.map(to_cstring);
+        let sqlstate = self.sqlstate.as_ref().map(|sqlstate| make_sqlstate(sqlstate));
+
+        // all messages are passed through a format string, they may contain a %
+        let format = b"%s\0".as_ptr() as *const c_char;
+        let file = concat!(file!(), "\0").as_ptr() as *const c_char;
+        let func_name = concat!(module_path!(), "\0").as_ptr() as *const c_char;
+
+        // an ERROR is never suppressed by errstart
+        pg_sys::errstart(
+            pg_sys::ERROR as c_int,
+            file,
+            line!() as c_int,
+            func_name,
+            ERR_DOMAIN.as_ptr() as *const c_char,
+        );
+
+        if let Some(sqlstate) = sqlstate {
+            pg_sys::errcode(sqlstate);
+        }
+
+        pg_sys::errmsg(format, message.as_ptr());
+
+        if let Some(ref detail) = detail {
+            pg_sys::errdetail(format, detail.as_ptr());
+        }
+
+        if let Some(ref hint) = hint {
+            pg_sys::errhint(format, hint.as_ptr());
+        }
+
+        // the messages were copied by Postgres, the longjmp of errfinish would leak them
+        drop((message, detail, hint));
+
+        compiler_fence(Ordering::SeqCst);
+        pg_sys::errfinish(0);
+
+        unreachable!("errfinish should have longjmped above, this is a bug in pg-extend-rs");
+    }
+}
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for PgError {}
+
+impl From<CaughtPgError> for PgError {
+    fn from(err: CaughtPgError) -> Self {
+        PgError {
+            message: err.message,
+            detail: err.detail,
+            hint: err.hint,
+            sqlstate: Some(err.sqlstate),
+        }
+    }
+}
+
+impl From<&str> for PgError {
+    fn from(message: &str) -> Self {
+        PgError::new(message)
+    }
+}
+
+impl From<String> for PgError {
+    fn from(message: String) -> Self {
+        PgError::new(message)
+    }
+}
+
+/// An `ERROR` raised in Postgres and caught, e.g. by `xact::subtransaction`
+///
+/// Converting it into a `PgError`, e.g. with `?`, raises it again when returned from the function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaughtPgError {
+    message: String,
+    detail: Option<String>,
+    hint: Option<String>,
+    context: Option<String>,
+    sqlstate: String,
+}
+
+impl CaughtPgError {
+    /// Takes the error being handled out of the error state, `CopyErrorData` and `FlushErrorState`
+    ///
+    /// # Safety
+    ///
+    /// This must only be called after an `ERROR` longjmped, and with `CurrentMemoryContext` switched out of
+    ///  `ErrorContext`.
+    pub(crate) unsafe fn take() -> Self {
+        let data = crate::guard_pg(|| pg_sys::CopyErrorData());
+        crate::guard_pg(|| pg_sys::FlushErrorState());
+
+        let to_string = |value: *mut c_char| {
+            value
+                .as_ref()
+                .map(|value| CStr::from_ptr(value).to_string_lossy().into_owned())
+        };
+
+        let err = CaughtPgError {
+            message: to_string((*data).message).unwrap_or_default(),
+            detail: to_string((*data).detail),
+            hint: to_string((*data).hint),
+            context: to_string((*data).context),
+            sqlstate: unpack_sqlstate((*data).sqlerrcode),
+        };
+
+        crate::guard_pg(|| pg_sys::FreeErrorData(data));
+        err
+    }
+
+    /// The primary message
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The detail message
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    /// The hint message
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// The context in which the error occurred, e.g. the SQL statement being executed
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    /// The SQLSTATE code, e.g. `23505` for `unique_violation`
+    pub fn sqlstate(&self) -> &str {
+        &self.sqlstate
+    }
+}
+
+impl fmt::Display for CaughtPgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CaughtPgError {}
+
+/// Encodes the SQLSTATE as Postgres does with `MAKE_SQLSTATE`
+fn make_sqlstate(sqlstate: &str) -> c_int {
+    sqlstate
+        .bytes()
+        .enumerate()
+        .fold(0, |code, (i, b)| {
+            code + (((c_int::from(b) - c_int::from(b'0')) & 0x3F) << (6 * i))
+        })
+}
+
+/// Decodes the SQLSTATE as Postgres does with `unpack_sql_state`
+pub(crate) fn unpack_sqlstate(code: c_int) -> String {
+    (0..5)
+        .map(|i| (((code >> (6 * i)) & 0x3F) as u8 + b'0') as char)
+        .collect()
+}
@@ -82,6 +82,8 @@ pub enum PgType {
     Void,
     /// xid  TransactionId  postgres.h
     TransactionId,
+    /// A type defined outside of the core Postgres types, e.g. by an extension, referenced by name
+    Custom(&'static str),
 }
 
 impl PgType {
@@ -167,6 +169,8 @@ impl PgType {
             PgType::Void => "void",
             // xid 	TransactionId 	postgres.h
             PgType::TransactionId => "xid",
+            // types created with CREATE TYPE
+            PgType::Custom(name) => name,
         }
     }
 
@@ -190,6 +194,12 @@ pub trait PgTypeInfo {
     }
 }
 
+impl PgTypeInfo for bool {
+    fn pg_type() -> PgType {
+        PgType::Boolean
+    }
+}
+
 impl PgTypeInfo for f32 {
     fn pg_type() -> PgType {
         PgType::Float4
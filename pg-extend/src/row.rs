@@ -0,0 +1,149 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Conversion of rows into Rust structs, see `#[derive(PgRow)]` in pg-extern-attr.
+//!
+//! The same struct can be read from the rows of an SPI query, the tuples passed to a trigger, and the rows
+//!  passed to a foreign data wrapper.
+//!
+//! ```rust,ignore
+//! #[derive(PgRow)]
+//! struct Word {
+//!     word: String,
+//!     #[pg_row(name = "length")]
+//!     len: i32,
+//! }
+//!
+//! let words = spi
+//!     .select("SELECT word, length(word) AS length FROM words", &[])?
+//!     .iter()
+//!     .map(|row| row.to_row::<Word>())
+//!     .collect::<Result<Vec<Word>, PgError>>()?;
+//! ```
+
+use std::ffi::CStr;
+
+use crate::native::HeapTuple;
+#[cfg(all(feature = "fdw", not(postgres12)))]
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::TryFromPgDatum;
+use crate::pg_error::PgError;
+#[cfg(all(feature = "fdw", not(postgres12)))]
+use crate::pg_fdw::Tuple;
+use crate::pg_sys;
+use crate::pg_type::PgTypeInfo;
+
+/// A type which can be read from the columns of a row, derive this with `#[derive(PgRow)]`
+pub trait PgRow<'mc>: Sized {
+    /// Reads the value from the columns of the row
+    fn from_columns<C: Columns<'mc>>(columns: &C) -> Result<Self, PgError>;
+}
+
+/// The columns of a row, which a `PgRow` is read from
+pub trait Columns<'mc> {
+    /// Returns the value of the column with the name
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    fn get<T>(&self, name: &str) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc;
+
+    /// Returns the value of the column with the number, starting at 1
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    fn get_by_number<T>(&self, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc;
+}
+
+impl<'mc> Columns<'mc> for HeapTuple<'mc> {
+    fn get<T>(&self, name: &str) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        HeapTuple::get(self, name)
+    }
+
+    fn get_by_number<T>(&self, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        HeapTuple::get_by_number(self, number)
+    }
+}
+
+/// The columns of a row passed to a foreign data wrapper, e.g. to `ForeignData::insert`
+///
+/// The row only has the names of its columns, so they can not be read by number, nor are their types checked.
+#[cfg(all(feature = "fdw", not(postgres12)))]
+pub struct ForeignTuple<'a, 'mc> {
+    memory_context: &'mc PgAllocator,
+    tuple: &'a Tuple<'mc>,
+}
+
+#[cfg(all(feature = "fdw", not(postgres12)))]
+impl<'a, 'mc> ForeignTuple<'a, 'mc> {
+    /// Wraps the row, the values of which are allocated in the memory context
+    pub fn new(memory_context: &'mc PgAllocator, tuple: &'a Tuple<'mc>) -> Self {
+        ForeignTuple {
+            memory_context,
+            tuple,
+        }
+    }
+}
+
+#[cfg(all(feature = "fdw", not(postgres12)))]
+impl<'a, 'mc> Columns<'mc> for ForeignTuple<'a, 'mc> {
+    fn get<T>(&self, name: &str) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        let datum = self.tuple.get(name).ok_or_else(|| {
+            PgError::new(format!("column \"{}\" does not exist", name)).with_sqlstate("42703")
+        })?;
+
+        T::try_from(self.memory_context, datum.clone())
+            .map_err(|err| PgError::new(format!("column \"{}\": {}", name, err)))
+    }
+
+    fn get_by_number<T>(&self, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'mc> + PgTypeInfo + 'mc,
+    {
+        Err(PgError::new(format!(
+            "column number {}: the columns of a foreign table row can only be read by name",
+            number
+        ))
+        .with_sqlstate("0A000"))
+    }
+}
+
+/// Checks the type of the column, e.g. `"name"` or `number 2`, is that of `T`
+///
+/// Reading a datum as the wrong type would misread it.
+pub(crate) fn check_type<T: PgTypeInfo>(column: &str, actual: pg_sys::Oid) -> Result<(), PgError> {
+    let expected = crate::spi::type_oid(T::pg_type(), T::is_array())?;
+    if expected == actual || (is_text(expected) && is_text(actual)) {
+        return Ok(());
+    }
+
+    let actual_name = unsafe { CStr::from_ptr(crate::guard_pg(|| pg_sys::format_type_be(actual))) };
+    Err(PgError::new(format!(
+        "column {} is of type {}, but {} was expected",
+        column,
+        actual_name.to_string_lossy(),
+        T::pg_type().as_str(T::is_array()),
+    ))
+    .with_sqlstate("42804"))
+}
+
+/// The types which share the representation of `text`
+fn is_text(oid: pg_sys::Oid) -> bool {
+    oid == pg_sys::TEXTOID as pg_sys::Oid
+        || oid == pg_sys::VARCHAROID as pg_sys::Oid
+        || oid == pg_sys::BPCHAROID as pg_sys::Oid
+}
@@ -0,0 +1,225 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for shared memory message queues, `shm_mq`, which send messages of bytes from one backend to another
+//!  through a `DsmSegment`, e.g. for a background worker to stream results back to the backend which launched it.
+//!
+//! The queue is created in the segment with `create`, and then attached to by one `ShmMqSender` and one
+//!  `ShmMqReceiver`. Once either side is dropped, the other side gets `ShmMqError::Detached`, after receiving
+//!  the messages which were already sent.
+//!
+//! ```rust,ignore
+//! let segment = DsmSegment::create(16 * 1024);
+//! shm_mq::create(&segment);
+//!
+//! let worker = BackgroundWorkerBuilder::new("squares", "myext", "pg_squares_worker")
+//!     .shared_memory_access()
+//!     .main_arg(segment.handle() as pg_sys::Datum)
+//!     .register_dynamic()?;
+//!
+//! let receiver = ShmMqReceiver::attach(&segment, Some(&worker));
+//! while let Ok(message) = receiver.receive() {
+//!     notice!("received: {:?}", message);
+//! }
+//! ```
+
+use std::error::Error;
+use std::ffi::c_void;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+
+use crate::bgworker::BackgroundWorkerHandle;
+use crate::dsm::DsmSegment;
+use crate::pg_sys;
+
+/// The error of sending or receiving a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShmMqError {
+    /// The other side has detached from the queue, or the worker set for it could not be started,
+    ///  `SHM_MQ_DETACHED`
+    Detached,
+    /// The message could not be sent or received without waiting, `SHM_MQ_WOULD_BLOCK`
+    WouldBlock,
+}
+
+impl fmt::Display for ShmMqError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShmMqError::Detached => f.write_str("the other side of the queue has detached"),
+            ShmMqError::WouldBlock => f.write_str("the queue operation would block"),
+        }
+    }
+}
+
+impl Error for ShmMqError {}
+
+impl ShmMqError {
+    fn from_pg(result: pg_sys::shm_mq_result) -> Result<(), Self> {
+        match result {
+            pg_sys::shm_mq_result_SHM_MQ_SUCCESS => Ok(()),
+            pg_sys::shm_mq_result_SHM_MQ_WOULD_BLOCK => Err(ShmMqError::WouldBlock),
+            _ => Err(ShmMqError::Detached),
+        }
+    }
+}
+
+/// Creates a queue filling the segment, `shm_mq_create`, this must be done once, before the sender and receiver
+///  attach to it
+pub fn create(segment: &DsmSegment) {
+    unsafe {
+        crate::guard_pg(|| pg_sys::shm_mq_create(segment.as_ptr() as *mut c_void, segment.size()))
+    };
+}
+
+/// An attachment to the queue in a segment, `shm_mq_handle`
+struct ShmMqHandle<'a> {
+    handle: *mut pg_sys::shm_mq_handle,
+    #[cfg(any(postgres9, postgres10))]
+    queue: *mut pg_sys::shm_mq,
+    _segment: PhantomData<&'a DsmSegment>,
+}
+
+impl<'a> ShmMqHandle<'a> {
+    /// Sets this backend as the sender or receiver, then attaches to the queue, `shm_mq_attach`
+    fn attach(
+        segment: &'a DsmSegment,
+        worker: Option<&'a BackgroundWorkerHandle>,
+        set_proc: unsafe extern "C" fn(*mut pg_sys::shm_mq, *mut pg_sys::PGPROC),
+    ) -> Self {
+        let queue = segment.as_ptr() as *mut pg_sys::shm_mq;
+        let worker = worker.map_or(ptr::null_mut(), BackgroundWorkerHandle::as_pg);
+
+        let handle = unsafe {
+            crate::guard_pg(|| {
+                set_proc(queue, pg_sys::MyProc);
+                pg_sys::shm_mq_attach(queue, segment.as_pg(), worker)
+            })
+        };
+
+        Self {
+            handle,
+            #[cfg(any(postgres9, postgres10))]
+            queue,
+            _segment: PhantomData,
+        }
+    }
+}
+
+impl<'a> Drop for ShmMqHandle<'a> {
+    fn drop(&mut self) {
+        // the queue is detached along with its segment by the abort of the transaction after an ERROR, which may
+        //  be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        #[cfg(any(postgres9, postgres10))]
+        unsafe {
+            crate::guard_pg(|| pg_sys::shm_mq_detach(self.queue))
+        };
+
+        #[cfg(not(any(postgres9, postgres10)))]
+        unsafe {
+            crate::guard_pg(|| pg_sys::shm_mq_detach(self.handle))
+        };
+    }
+}
+
+/// The sending side of a queue, which detaches from it when dropped
+pub struct ShmMqSender<'a> {
+    mq: ShmMqHandle<'a>,
+}
+
+impl<'a> ShmMqSender<'a> {
+    /// Attaches to the queue in the segment as its sender, `shm_mq_set_sender`
+    ///
+    /// If the receiver is a background worker, its handle should be given, so that sending fails with
+    ///  `ShmMqError::Detached` if it could not be started, rather than waiting for it forever.
+    pub fn attach(segment: &'a DsmSegment, worker: Option<&'a BackgroundWorkerHandle>) -> Self {
+        Self {
+            mq: ShmMqHandle::attach(segment, worker, pg_sys::shm_mq_set_sender),
+        }
+    }
+
+    /// Sends the message, waiting for the receiver to attach and for space in the queue, `shm_mq_send`
+    ///
+    /// The wait can be interrupted by a cancel, which raises an `ERROR`.
+    pub fn send(&self, message: &[u8]) -> Result<(), ShmMqError> {
+        self.send_message(message, false)
+    }
+
+    /// Sends the message if there is space in the queue, otherwise returns `ShmMqError::WouldBlock`, in which case
+    ///  the message is partially sent, and the same message must be sent again
+    pub fn try_send(&self, message: &[u8]) -> Result<(), ShmMqError> {
+        self.send_message(message, true)
+    }
+
+    fn send_message(&self, message: &[u8], nowait: bool) -> Result<(), ShmMqError> {
+        let result = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::shm_mq_send(
+                    self.mq.handle,
+                    message.len(),
+                    message.as_ptr() as *const c_void,
+                    pgbool!(nowait),
+                )
+            })
+        };
+
+        ShmMqError::from_pg(result)
+    }
+}
+
+/// The receiving side of a queue, which detaches from it when dropped
+pub struct ShmMqReceiver<'a> {
+    mq: ShmMqHandle<'a>,
+}
+
+impl<'a> ShmMqReceiver<'a> {
+    /// Attaches to the queue in the segment as its receiver, `shm_mq_set_receiver`
+    ///
+    /// If the sender is a background worker, its handle should be given, so that receiving fails with
+    ///  `ShmMqError::Detached` if it could not be started, rather than waiting for it forever.
+    pub fn attach(segment: &'a DsmSegment, worker: Option<&'a BackgroundWorkerHandle>) -> Self {
+        Self {
+            mq: ShmMqHandle::attach(segment, worker, pg_sys::shm_mq_set_receiver),
+        }
+    }
+
+    /// Receives the next message, waiting for the sender to attach and send it, `shm_mq_receive`
+    ///
+    /// The wait can be interrupted by a cancel, which raises an `ERROR`.
+    pub fn receive(&self) -> Result<Vec<u8>, ShmMqError> {
+        self.receive_message(false)
+    }
+
+    /// Receives the next message if it has been sent, otherwise returns `ShmMqError::WouldBlock`
+    pub fn try_receive(&self) -> Result<Vec<u8>, ShmMqError> {
+        self.receive_message(true)
+    }
+
+    fn receive_message(&self, nowait: bool) -> Result<Vec<u8>, ShmMqError> {
+        let mut len = 0;
+        let mut data: *mut c_void = ptr::null_mut();
+
+        let result = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::shm_mq_receive(self.mq.handle, &mut len, &mut data, pgbool!(nowait))
+            })
+        };
+        ShmMqError::from_pg(result)?;
+
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        // the data is only valid until the next receive, so it is copied
+        let message = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+        Ok(message.to_vec())
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for shared memory, which is shared by all the backends of the server, unlike `static`s which each
+//!  backend has its own copy of.
+//!
+//! Shared memory is allocated when the server starts, so it is declared in `static`s which are requested from
+//!  the `init` function of `pg_magic!`, and the library must be in `shared_preload_libraries`. The space is
+//!  requested with `RequestAddinShmemSpace`, and the value is then initialized in the `shmem_startup_hook`,
+//!  with `ShmemInitStruct`.
+//!
+//! ```rust,ignore
+//! #[repr(C)]
+//! #[derive(Clone, Copy, Default)]
+//! struct Stats {
+//!     inserts: i64,
+//!     deletes: i64,
+//! }
+//!
+//! unsafe impl ShmemData for Stats {}
+//!
+//! static STATS: PgLwLock<Stats> = PgLwLock::new("myext.stats");
+//! static CALLS: PgAtomicU64 = PgAtomicU64::new("myext.calls");
+//!
+//! fn init() {
+//!     STATS.request();
+//!     CALLS.request();
+//! }
+//!
+//! #[pg_extern]
+//! fn record_insert() -> i64 {
+//!     CALLS.fetch_add(1);
+//!
+//!     let mut stats = STATS.exclusive();
+//!     stats.inserts += 1;
+//!     stats.inserts
+//! }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::pg_sys;
+use crate::JumpContext;
+
+/// The index of `AddinShmemInitLock` in `MainLWLockArray`, which bindgen can not emit the macro of
+const ADDIN_SHMEM_INIT_LOCK: usize = 21;
+
+thread_local! {
+    /// The initialization of the requested values, run by the `shmem_startup_hook`
+    static STARTUP: RefCell<Vec<Box<dyn Fn()>>> = RefCell::new(Vec::new());
+}
+
+/// The `shmem_startup_hook` which was installed before ours, which is called first
+static mut PREV_SHMEM_STARTUP_HOOK: pg_sys::shmem_startup_hook_type = None;
+
+/// Data which can be stored in shared memory
+///
+/// # Safety
+///
+/// The type must be `#[repr(C)]`, or a primitive, and must not contain references or pointers to memory of a
+///  backend, e.g. a `Box` or `String`, as only the value itself is shared, and shared memory can be mapped at a
+///  different address in each backend.
+pub unsafe trait ShmemData: Copy + Default + 'static {}
+
+unsafe impl ShmemData for bool {}
+unsafe impl ShmemData for i8 {}
+unsafe impl ShmemData for i16 {}
+unsafe impl ShmemData for i32 {}
+unsafe impl ShmemData for i64 {}
+unsafe impl ShmemData for u8 {}
+unsafe impl ShmemData for u16 {}
+unsafe impl ShmemData for u32 {}
+unsafe impl ShmemData for u64 {}
+unsafe impl ShmemData for f32 {}
+unsafe impl ShmemData for f64 {}
+
+/// A value in shared memory, which is initialized to its `Default`
+///
+/// This is the allocation of the value, which is not synchronized, use `PgLwLock` or `PgAtomicU64` to access
+///  it from backends.
+pub struct PgShmem<T> {
+    name: &'static str,
+    ptr: Cell<*mut T>,
+}
+
+// the pointer is only set by the shmem_startup_hook, before the backends using it are started
+unsafe impl<T: ShmemData> Sync for PgShmem<T> {}
+
+impl<T> PgShmem<T> {
+    /// Declares the value, the name identifies it in shared memory and must be unique on the server, e.g.
+    ///  prefixed with the name of the extension
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            ptr: Cell::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl<T: ShmemData> PgShmem<T> {
+    /// Requests the space for the value, `RequestAddinShmemSpace`, this must be called from the `init` function
+    ///  of `pg_magic!`, while the library is loaded from `shared_preload_libraries`
+    ///
+    /// # Panics
+    ///
+    /// If the library is not being loaded from `shared_preload_libraries`.
+    pub fn request(&'static self) {
+        request(self.name, mem::size_of::<T>(), move || unsafe {
+            self.init()
+        });
+    }
+
+    /// Finds or allocates the value in shared memory, `ShmemInitStruct`
+    unsafe fn init(&self) {
+        let name = CString::new(self.name).expect("names do not contain NULL bytes");
+        // bool is a char before Postgres 11, which has the same representation
+        let mut found = false;
+
+        let ptr = crate::guard_pg(|| {
+            pg_sys::ShmemInitStruct(
+                name.as_ptr(),
+                mem::size_of::<T>(),
+                &mut found as *mut bool as *mut _,
+            )
+        }) as *mut T;
+
+        // the first backend to find it initializes it, this is the postmaster, unless it is an EXEC_BACKEND build
+        if !found {
+            ptr::write(ptr, T::default());
+        }
+
+        self.ptr.set(ptr);
+    }
+
+    /// Returns true if the value has been allocated in shared memory, i.e. it was requested while the library
+    ///  was loaded from `shared_preload_libraries`
+    pub fn is_initialized(&self) -> bool {
+        !self.ptr.get().is_null()
+    }
+
+    /// Returns a pointer to the value in shared memory, access to it must be synchronized between backends
+    ///
+    /// # Panics
+    ///
+    /// If the value has not been allocated, see `is_initialized`.
+    pub fn as_ptr(&self) -> *mut T {
+        let ptr = self.ptr.get();
+        assert!(
+            !ptr.is_null(),
+            "shared memory {} is not initialized, the library must be in shared_preload_libraries",
+            self.name
+        );
+
+        ptr
+    }
+}
+
+/// A value in shared memory, which is protected by an `LWLock`
+///
+/// The lock is held by the guards returned from `share` and `exclusive`, until they are dropped. A lock which is
+///  still held when an `ERROR` is raised is released by Postgres.
+pub struct PgLwLock<T> {
+    shmem: PgShmem<T>,
+    lock: Cell<*mut pg_sys::LWLock>,
+}
+
+// the pointers are only set by the shmem_startup_hook, before the backends using it are started
+unsafe impl<T: ShmemData> Sync for PgLwLock<T> {}
+
+impl<T> PgLwLock<T> {
+    /// Declares the value, the name identifies it and its lock tranche and must be unique on the server, e.g.
+    ///  prefixed with the name of the extension
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            shmem: PgShmem::new(name),
+            lock: Cell::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl<T: ShmemData> PgLwLock<T> {
+    /// Requests the space for the value and its lock, `RequestAddinShmemSpace` and `RequestNamedLWLockTranche`,
+    ///  this must be called from the `init` function of `pg_magic!`, while the library is loaded from
+    ///  `shared_preload_libraries`
+    ///
+    /// # Panics
+    ///
+    /// If the library is not being loaded from `shared_preload_libraries`.
+    pub fn request(&'static self) {
+        let name = self.shmem.name;
+        request(name, mem::size_of::<T>(), move || unsafe { self.init() });
+
+        let tranche = CString::new(name).expect("names do not contain NULL bytes");
+        unsafe { crate::guard_pg(|| pg_sys::RequestNamedLWLockTranche(tranche.as_ptr(), 1)) };
+    }
+
+    /// Finds or allocates the value, and looks up its lock, `GetNamedLWLockTranche`
+    unsafe fn init(&self) {
+        self.shmem.init();
+
+        let tranche = CString::new(self.shmem.name).expect("names do not contain NULL bytes");
+        let locks = crate::guard_pg(|| pg_sys::GetNamedLWLockTranche(tranche.as_ptr()));
+        self.lock.set(&mut (*locks).lock);
+    }
+
+    /// Returns true if the value has been allocated in shared memory, i.e. it was requested while the library
+    ///  was loaded from `shared_preload_libraries`
+    pub fn is_initialized(&self) -> bool {
+        self.shmem.is_initialized()
+    }
+
+    /// Acquires the lock in shared mode, for reading the value, waiting for an exclusive lock to be released
+    ///
+    /// # Panics
+    ///
+    /// If the value has not been allocated, see `is_initialized`.
+    pub fn share(&self) -> PgLwLockShareGuard<'_, T> {
+        let data = self.shmem.as_ptr();
+        let lock = self.acquire(pg_sys::LWLockMode_LW_SHARED);
+
+        PgLwLockShareGuard {
+            data: unsafe { &*data },
+            lock,
+        }
+    }
+
+    /// Acquires the lock in exclusive mode, for modifying the value, waiting for all other locks to be released
+    ///
+    /// # Panics
+    ///
+    /// If the value has not been allocated, see `is_initialized`.
+    pub fn exclusive(&self) -> PgLwLockExclusiveGuard<'_, T> {
+        let data = self.shmem.as_ptr();
+        let lock = self.acquire(pg_sys::LWLockMode_LW_EXCLUSIVE);
+
+        PgLwLockExclusiveGuard {
+            data: unsafe { &mut *data },
+            lock,
+        }
+    }
+
+    fn acquire(&self, mode: pg_sys::LWLockMode) -> LwLockHeld {
+        let lock = self.lock.get();
+        unsafe { crate::guard_pg(|| pg_sys::LWLockAcquire(lock, mode)) };
+
+        LwLockHeld(lock)
+    }
+}
+
+/// A held `LWLock`, which is released when dropped
+struct LwLockHeld(*mut pg_sys::LWLock);
+
+impl Drop for LwLockHeld {
+    fn drop(&mut self) {
+        // the abort of the transaction releases all LWLocks after an ERROR, which may be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe { crate::guard_pg(|| pg_sys::LWLockRelease(self.0)) };
+    }
+}
+
+/// A shared lock of a `PgLwLock`, which is released when dropped
+pub struct PgLwLockShareGuard<'a, T> {
+    data: &'a T,
+    #[allow(unused)]
+    lock: LwLockHeld,
+}
+
+impl<'a, T> Deref for PgLwLockShareGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+/// An exclusive lock of a `PgLwLock`, which is released when dropped
+pub struct PgLwLockExclusiveGuard<'a, T> {
+    data: &'a mut T,
+    #[allow(unused)]
+    lock: LwLockHeld,
+}
+
+impl<'a, T> Deref for PgLwLockExclusiveGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T> DerefMut for PgLwLockExclusiveGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+/// A `u64` counter in shared memory, which is updated atomically without a lock
+///
+/// All operations are sequentially consistent, as are the atomic operations of Postgres.
+pub struct PgAtomicU64 {
+    shmem: PgShmem<u64>,
+}
+
+// the pointer is only set by the shmem_startup_hook, before the backends using it are started
+unsafe impl Sync for PgAtomicU64 {}
+
+impl PgAtomicU64 {
+    /// Declares the counter, the name identifies it in shared memory and must be unique on the server, e.g.
+    ///  prefixed with the name of the extension
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            shmem: PgShmem::new(name),
+        }
+    }
+
+    /// Requests the space for the counter, which starts at zero, see `PgShmem::request`
+    pub fn request(&'static self) {
+        self.shmem.request();
+    }
+
+    /// Returns true if the counter has been allocated in shared memory, i.e. it was requested while the library
+    ///  was loaded from `shared_preload_libraries`
+    pub fn is_initialized(&self) -> bool {
+        self.shmem.is_initialized()
+    }
+
+    fn atomic(&self) -> &AtomicU64 {
+        // AtomicU64 has the same in-memory representation as u64
+        unsafe { &*(self.shmem.as_ptr() as *const AtomicU64) }
+    }
+
+    /// Returns the value of the counter
+    pub fn load(&self) -> u64 {
+        self.atomic().load(Ordering::SeqCst)
+    }
+
+    /// Sets the value of the counter
+    pub fn store(&self, value: u64) {
+        self.atomic().store(value, Ordering::SeqCst)
+    }
+
+    /// Sets the value of the counter, returning the previous value
+    pub fn swap(&self, value: u64) -> u64 {
+        self.atomic().swap(value, Ordering::SeqCst)
+    }
+
+    /// Adds to the counter, wrapping around on overflow, returning the previous value
+    pub fn fetch_add(&self, value: u64) -> u64 {
+        self.atomic().fetch_add(value, Ordering::SeqCst)
+    }
+
+    /// Subtracts from the counter, wrapping around on overflow, returning the previous value
+    pub fn fetch_sub(&self, value: u64) -> u64 {
+        self.atomic().fetch_sub(value, Ordering::SeqCst)
+    }
+
+    /// Sets the counter to `new` if it is `current`, returning the previous value, as `Ok` if it was set
+    pub fn compare_exchange(&self, current: u64, new: u64) -> Result<u64, u64> {
+        self.atomic()
+            .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+    }
+}
+
+/// Requests the space, and registers the initialization for the `shmem_startup_hook`, which is installed on the
+///  first request
+fn request<F: Fn() + 'static>(name: &str, size: usize, startup: F) {
+    assert!(
+        crate::bgworker::loading_shared_preload_libraries(),
+        "shared memory {} can only be requested while loading shared_preload_libraries",
+        name
+    );
+
+    unsafe { crate::guard_pg(|| pg_sys::RequestAddinShmemSpace(size)) };
+
+    STARTUP.with(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+
+        if hooks.is_empty() {
+            unsafe {
+                PREV_SHMEM_STARTUP_HOOK = pg_sys::shmem_startup_hook;
+                pg_sys::shmem_startup_hook = Some(shmem_startup);
+            }
+        }
+
+        hooks.push(Box::new(startup));
+    });
+}
+
+/// The `shmem_startup_hook`, initializes all the requested values, while holding `AddinShmemInitLock`
+unsafe extern "C" fn shmem_startup() {
+    if let Some(prev_hook) = PREV_SHMEM_STARTUP_HOOK {
+        prev_hook();
+    }
+
+    let result = crate::catch_unwind_pg(|| {
+        let lock = &mut (*pg_sys::MainLWLockArray.add(ADDIN_SHMEM_INIT_LOCK)).lock as *mut _;
+        pg_sys::LWLockAcquire(lock, pg_sys::LWLockMode_LW_EXCLUSIVE);
+        let _held = LwLockHeld(lock);
+
+        STARTUP.with(|hooks| {
+            for startup in hooks.borrow().iter() {
+                startup();
+            }
+        });
+    });
+
+    let err = match result {
+        Ok(()) => return,
+        Err(err) => err,
+    };
+
+    if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+        jump_context.continue_longjmp();
+    }
+
+    crate::raise_panic("shmem_startup_hook", err);
+}
@@ -0,0 +1,106 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use std::os::raw::c_long;
+use std::vec;
+
+use crate::pg_sys;
+use crate::spi::row::TupleTable;
+use crate::spi::{Spi, SpiRow};
+
+/// The number of rows fetched at a time by default
+const DEFAULT_BATCH_SIZE: c_long = 1000;
+
+/// A cursor opened with `Spi::open_cursor`, which fetches the rows of the query in batches
+///
+/// Each batch is allocated in its own memory context, which is freed once all of the rows of the batch are
+///  dropped. Rows that are dropped as they are iterated over are freed before the next batch is fetched, so
+///  only one batch is held in memory at a time.
+pub struct Cursor<'spi> {
+    portal: pg_sys::Portal,
+    batch_size: c_long,
+    batch: vec::IntoIter<SpiRow<'spi>>,
+    done: bool,
+    spi: PhantomData<&'spi Spi>,
+}
+
+impl<'spi> Cursor<'spi> {
+    pub(crate) unsafe fn from_raw(portal: pg_sys::Portal) -> Self {
+        assert!(!portal.is_null(), "Portal was unexpectedly NULL");
+
+        Cursor {
+            portal,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch: Vec::new().into_iter(),
+            done: false,
+            spi: PhantomData,
+        }
+    }
+
+    /// Sets the number of rows fetched at a time, the default is 1000
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch_size must be greater than 0");
+
+        self.batch_size = batch_size as c_long;
+        self
+    }
+
+    /// Fetches the next batch of rows, `SPI_cursor_fetch`
+    fn fetch(&mut self) {
+        // release this iterator's hold on the previous batch before allocating the next
+        self.batch = Vec::new().into_iter();
+
+        unsafe {
+            crate::guard_pg(|| pg_sys::SPI_cursor_fetch(self.portal, pgbool!(true), self.batch_size));
+
+            let processed = pg_sys::SPI_processed as usize;
+            let table = pg_sys::SPI_tuptable;
+
+            if processed < self.batch_size as usize {
+                self.done = true;
+            }
+
+            if processed == 0 {
+                TupleTable::free(table);
+                return;
+            }
+
+            self.batch = TupleTable::from_raw(table).into_rows(processed).into_iter();
+        }
+    }
+}
+
+impl<'spi> Iterator for Cursor<'spi> {
+    type Item = SpiRow<'spi>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row) = self.batch.next() {
+            return Some(row);
+        }
+
+        if self.done {
+            return None;
+        }
+
+        self.fetch();
+        self.batch.next()
+    }
+}
+
+impl<'spi> Drop for Cursor<'spi> {
+    fn drop(&mut self) {
+        // the abort of the transaction closes the portal after an ERROR, which may be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe {
+            crate::guard_pg(|| pg_sys::SPI_cursor_close(self.portal));
+        }
+    }
+}
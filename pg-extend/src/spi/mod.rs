@@ -0,0 +1,417 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Executing SQL from within a function, through the Server Programming Interface (SPI).
+//!
+//! ```rust,ignore
+//! let spi = Spi::connect()?;
+//! spi.execute("INSERT INTO words VALUES ($1)", &["ferris".to_string().into()])?;
+//!
+//! for row in spi.select("SELECT word FROM words WHERE length(word) > $1", &[3.into()])? {
+//!     let word: String = row.get("word")?;
+//! }
+//! ```
+//!
+//! Statements executed repeatedly can be prepared once with `Spi::prepare`, and kept across calls of a function
+//!  with `PreparedPlan::keep` or a `PlanCache`. Queries returning many rows can be iterated over in batches with
+//!  `Spi::open_cursor`.
+//!
+//! Errors reported by SPI are returned as a `PgError`. An `ERROR` raised by Postgres, e.g. a syntax error or a
+//!  constraint violation, aborts the function the same as any other, keeping its SQLSTATE. To handle it instead,
+//!  run the statements with `Spi::catch_errors`, which returns it as a `PgError`.
+
+mod cursor;
+mod plan;
+mod row;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_long};
+
+use crate::pg_datum::PgDatum;
+use crate::pg_error::PgError;
+use crate::pg_sys;
+use crate::pg_type::{PgType, PgTypeInfo};
+use crate::xact;
+
+pub use self::cursor::Cursor;
+pub use self::plan::{KeptPlan, PlanCache, PreparedPlan};
+pub use self::row::SpiRow;
+use self::row::TupleTable;
+
+/// A parameter of a statement, `$1`, `$2`, ...
+#[derive(Clone)]
+pub struct SpiArg<'a> {
+    pg_type: PgType,
+    is_array: bool,
+    datum: PgDatum<'a>,
+}
+
+impl<'a> SpiArg<'a> {
+    /// A parameter with the value, the Postgres type is that of the Rust type
+    pub fn new<T>(value: T) -> Self
+    where
+        T: PgTypeInfo + Into<PgDatum<'a>>,
+    {
+        SpiArg {
+            pg_type: T::pg_type(),
+            is_array: T::is_array(),
+            datum: value.into(),
+        }
+    }
+
+    /// A `NULL` parameter of the Postgres type
+    pub fn null(pg_type: PgType) -> Self {
+        SpiArg {
+            pg_type,
+            is_array: false,
+            datum: PgDatum::from(()),
+        }
+    }
+}
+
+impl<'a, T> From<T> for SpiArg<'a>
+where
+    T: PgTypeInfo + Into<PgDatum<'a>>,
+{
+    fn from(value: T) -> Self {
+        SpiArg::new(value)
+    }
+}
+
+/// A connection to the SPI manager, `SPI_connect`, which is closed with `SPI_finish` when dropped.
+///
+/// While connected, the current memory context is that of SPI, which is freed when the connection is closed.
+///  Rows returned by the connection can not outlive it.
+pub struct Spi {
+    read_only: bool,
+}
+
+impl Spi {
+    /// Connects to the SPI manager
+    pub fn connect() -> Result<Self, PgError> {
+        let connected = unsafe { crate::guard_pg(|| pg_sys::SPI_connect()) };
+        check_result(connected, "SPI_connect")?;
+
+        Ok(Spi { read_only: false })
+    }
+
+    /// Connects to the SPI manager in read-only mode.
+    ///
+    /// Only statements that do not modify the database may be executed, any other raises an `ERROR`. This
+    ///  is required for functions declared `STABLE` or `IMMUTABLE`.
+    pub fn connect_read_only() -> Result<Self, PgError> {
+        let mut spi = Self::connect()?;
+        spi.read_only = true;
+        Ok(spi)
+    }
+
+    /// Returns true if the connection is read-only
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Runs the statements of the closure in a subtransaction, see `xact::subtransaction`, returning an `ERROR`
+    ///  raised by Postgres, e.g. a syntax error or a constraint violation, as a `PgError` with its SQLSTATE.
+    ///
+    /// The changes of the closure are rolled back on an `ERROR`, the rest of the transaction continues. Each
+    ///  subtransaction which writes is assigned a transaction id, so this should only wrap the statements whose
+    ///  errors are handled. Subtransactions can not be started in parallel mode.
+    ///
+    /// ```rust,ignore
+    /// match spi.catch_errors(|| spi.execute("INSERT INTO words VALUES ($1)", &[word.into()])) {
+    ///     Ok(_) => (),
+    ///     Err(err) if err.sqlstate() == Some("23505") => notice!("{} was already inserted", word),
+    ///     Err(err) => panic::panic_any(err),
+    /// }
+    /// ```
+    pub fn catch_errors<R, F>(&self, f: F) -> Result<R, PgError>
+    where
+        F: FnOnce() -> Result<R, PgError>,
+    {
+        let result = xact::subtransaction(f);
+
+        // before Postgres 10 the connection must be restored after the subtransaction, see plpython
+        #[cfg(postgres9)]
+        unsafe {
+            crate::guard_pg(|| pg_sys::SPI_restore_connection());
+        }
+
+        result.map_err(PgError::from)?
+    }
+
+    /// Executes the statement, returning the number of rows processed, `SPI_processed`
+    pub fn execute(&self, sql: &str, args: &[SpiArg]) -> Result<u64, PgError> {
+        self.execute_with_args(sql, args, 0)?;
+
+        unsafe {
+            let processed = pg_sys::SPI_processed;
+
+            // any rows returned, e.g. with RETURNING, are not used
+            TupleTable::free(pg_sys::SPI_tuptable);
+            Ok(processed)
+        }
+    }
+
+    /// Executes the query, returning all of the rows
+    pub fn select(&self, sql: &str, args: &[SpiArg]) -> Result<Vec<SpiRow<'_>>, PgError> {
+        self.execute_with_args(sql, args, 0)?;
+        self.take_rows(sql)
+    }
+
+    /// Executes the query, returning the first row if there is one
+    pub fn select_one(&self, sql: &str, args: &[SpiArg]) -> Result<Option<SpiRow<'_>>, PgError> {
+        self.execute_with_args(sql, args, 1)?;
+        Ok(self.take_rows(sql)?.into_iter().next())
+    }
+
+    /// Prepares the statement, `SPI_prepare`, with the types of its parameters, `$1`, `$2`, ...
+    pub fn prepare(&self, sql: &str, types: &[PgType]) -> Result<PreparedPlan<'_>, PgError> {
+        let c_sql = CString::new(sql).map_err(|_| PgError::new("SQL contained a NULL byte"))?;
+        let mut types = types
+            .iter()
+            .map(|pg_type| type_oid(*pg_type, false))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        unsafe {
+            let plan = crate::guard_pg(|| {
+                pg_sys::SPI_prepare(c_sql.as_ptr(), types.len() as c_int, types.as_mut_ptr())
+            });
+
+            if plan.is_null() {
+                check_result(pg_sys::SPI_result, "SPI_prepare")?;
+                return Err(PgError::new(format!("SPI_prepare failed: {}", sql)));
+            }
+
+            Ok(PreparedPlan::from_raw(plan))
+        }
+    }
+
+    /// Executes the prepared statement, returning the number of rows processed, `SPI_processed`
+    pub fn execute_plan(&self, plan: &PreparedPlan, args: &[SpiArg]) -> Result<u64, PgError> {
+        self.execute_plan_with_args(plan, args, 0)?;
+
+        unsafe {
+            let processed = pg_sys::SPI_processed;
+
+            // any rows returned, e.g. with RETURNING, are not used
+            TupleTable::free(pg_sys::SPI_tuptable);
+            Ok(processed)
+        }
+    }
+
+    /// Executes the prepared query, returning all of the rows
+    pub fn select_plan(
+        &self,
+        plan: &PreparedPlan,
+        args: &[SpiArg],
+    ) -> Result<Vec<SpiRow<'_>>, PgError> {
+        self.execute_plan_with_args(plan, args, 0)?;
+        self.take_rows("prepared plan")
+    }
+
+    /// Opens a cursor for the prepared query, `SPI_cursor_open`, which fetches the rows in batches as it is
+    ///  iterated over
+    pub fn open_cursor(&self, plan: &PreparedPlan, args: &[SpiArg]) -> Result<Cursor<'_>, PgError> {
+        check_plan_args(plan, args)?;
+        let (mut values, nulls) = datums(args);
+
+        unsafe {
+            let portal = crate::guard_pg(|| {
+                pg_sys::SPI_cursor_open(
+                    std::ptr::null(),
+                    plan.as_ptr(),
+                    values.as_mut_ptr(),
+                    nulls.as_ptr(),
+                    pgbool!(self.read_only),
+                )
+            });
+
+            if portal.is_null() {
+                check_result(pg_sys::SPI_result, "SPI_cursor_open")?;
+                return Err(PgError::new("SPI_cursor_open failed"));
+            }
+
+            Ok(Cursor::from_raw(portal))
+        }
+    }
+
+    fn execute_with_args(&self, sql: &str, args: &[SpiArg], count: c_long) -> Result<(), PgError> {
+        let sql = CString::new(sql).map_err(|_| PgError::new("SQL contained a NULL byte"))?;
+
+        let mut types = args
+            .iter()
+            .map(|arg| type_oid(arg.pg_type, arg.is_array))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (mut values, nulls) = datums(args);
+
+        let executed = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::SPI_execute_with_args(
+                    sql.as_ptr(),
+                    args.len() as c_int,
+                    types.as_mut_ptr(),
+                    values.as_mut_ptr(),
+                    nulls.as_ptr(),
+                    pgbool!(self.read_only),
+                    count,
+                )
+            })
+        };
+
+        check_result(executed, "SPI_execute_with_args")
+    }
+
+    fn execute_plan_with_args(
+        &self,
+        plan: &PreparedPlan,
+        args: &[SpiArg],
+        count: c_long,
+    ) -> Result<(), PgError> {
+        check_plan_args(plan, args)?;
+        let (mut values, nulls) = datums(args);
+
+        let executed = unsafe {
+            crate::guard_pg(|| {
+                pg_sys::SPI_execute_plan(
+                    plan.as_ptr(),
+                    values.as_mut_ptr(),
+                    nulls.as_ptr(),
+                    pgbool!(self.read_only),
+                    count,
+                )
+            })
+        };
+
+        check_result(executed, "SPI_execute_plan")
+    }
+
+    /// Takes ownership of the rows of the last statement, `SPI_tuptable`
+    fn take_rows(&self, sql: &str) -> Result<Vec<SpiRow<'_>>, PgError> {
+        unsafe {
+            let processed = pg_sys::SPI_processed as usize;
+            let table = pg_sys::SPI_tuptable;
+            if table.is_null() {
+                return Err(PgError::new(format!("statement did not return rows: {}", sql)));
+            }
+
+            Ok(TupleTable::from_raw(table).into_rows(processed))
+        }
+    }
+}
+
+impl Drop for Spi {
+    fn drop(&mut self) {
+        // the abort of the transaction cleans up SPI after an ERROR, which may be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe {
+            crate::guard_pg(|| pg_sys::SPI_finish());
+        }
+    }
+}
+
+/// Returns the Oid of the type, looked up by name in the catalog if it is not a common built-in type
+pub(crate) fn type_oid(pg_type: PgType, is_array: bool) -> Result<pg_sys::Oid, PgError> {
+    if !is_array {
+        let oid = match pg_type {
+            PgType::Boolean => Some(pg_sys::BOOLOID),
+            PgType::SmallInt | PgType::Int2 => Some(pg_sys::INT2OID),
+            PgType::Integer | PgType::Int4 => Some(pg_sys::INT4OID),
+            PgType::BigInt | PgType::Int8 => Some(pg_sys::INT8OID),
+            PgType::Real | PgType::Float4 => Some(pg_sys::FLOAT4OID),
+            PgType::DoublePrecision | PgType::Float8 => Some(pg_sys::FLOAT8OID),
+            PgType::Oid => Some(pg_sys::OIDOID),
+            PgType::Text => Some(pg_sys::TEXTOID),
+            PgType::VarChar => Some(pg_sys::VARCHAROID),
+            _ => None,
+        };
+
+        if let Some(oid) = oid {
+            return Ok(oid as pg_sys::Oid);
+        }
+    }
+
+    let name = pg_type.as_str(is_array);
+    let c_name = CString::new(name).expect("type names do not contain NULL bytes");
+
+    let mut oid: pg_sys::Oid = 0; // InvalidOid
+    let mut typmod: pg_sys::int32 = 0;
+    unsafe {
+        crate::guard_pg(|| {
+            pg_sys::parseTypeString(c_name.as_ptr(), &mut oid, &mut typmod, pgbool!(true))
+        });
+    }
+
+    if oid == 0 {
+        return Err(PgError::new(format!("type \"{}\" does not exist", name)).with_sqlstate("42704"));
+    }
+
+    Ok(oid)
+}
+
+/// Checks the arguments are those of the prepared statement, a mismatched type would be misread by Postgres
+fn check_plan_args(plan: &PreparedPlan, args: &[SpiArg]) -> Result<(), PgError> {
+    let arg_count = plan.arg_count();
+    if arg_count != args.len() {
+        return Err(PgError::new(format!(
+            "prepared statement takes {} parameters, {} were given",
+            arg_count,
+            args.len()
+        ))
+        .with_sqlstate("08P01"));
+    }
+
+    for (index, arg) in args.iter().enumerate() {
+        let expected = plan.arg_type(index);
+        if type_oid(arg.pg_type, arg.is_array)? != expected {
+            return Err(PgError::new(format!(
+                "parameter ${} of prepared statement is not of type {}",
+                index + 1,
+                arg.pg_type.as_str(arg.is_array)
+            ))
+            .with_sqlstate("42804"));
+        }
+    }
+
+    Ok(())
+}
+
+/// The `Values` and `Nulls` arguments of SPI
+fn datums(args: &[SpiArg]) -> (Vec<pg_sys::Datum>, Vec<c_char>) {
+    let values = args
+        .iter()
+        .map(|arg| unsafe { arg.datum.clone().into_datum() })
+        .collect();
+    let nulls = args.iter().map(|arg| null_flag(&arg.datum)).collect();
+
+    (values, nulls)
+}
+
+/// The `Nulls` argument of SPI, `n` for NULL and space for not
+pub(crate) fn null_flag(datum: &PgDatum) -> c_char {
+    if datum.is_null() {
+        b'n' as c_char
+    } else {
+        b' ' as c_char
+    }
+}
+
+/// Converts a negative SPI result code into an error
+pub(crate) fn check_result(code: c_int, function: &str) -> Result<(), PgError> {
+    if code >= 0 {
+        return Ok(());
+    }
+
+    let name = unsafe {
+        let name = pg_sys::SPI_result_code_string(code);
+        CStr::from_ptr(name).to_string_lossy().into_owned()
+    };
+
+    Err(PgError::new(format!("{} failed: {}", function, name)))
+}
@@ -0,0 +1,199 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::os::raw::c_int;
+
+use crate::pg_error::PgError;
+use crate::pg_sys;
+use crate::pg_type::PgType;
+use crate::spi::{check_result, Spi};
+
+/// A statement prepared with `Spi::prepare`, `SPI_prepare`
+///
+/// The plan is freed along with the `Spi` connection it was prepared with, unless it is kept with `keep`.
+pub struct PreparedPlan<'a> {
+    plan: pg_sys::SPIPlanPtr,
+    plan_lifetime: PhantomData<&'a Spi>,
+}
+
+impl<'a> PreparedPlan<'a> {
+    pub(crate) unsafe fn from_raw(plan: pg_sys::SPIPlanPtr) -> Self {
+        assert!(!plan.is_null(), "SPIPlanPtr was unexpectedly NULL");
+
+        PreparedPlan {
+            plan,
+            plan_lifetime: PhantomData,
+        }
+    }
+
+    /// Moves the plan into a long lived memory context, `SPI_keepplan`, so that it can be used after the
+    ///  `Spi` connection is closed, e.g. in later calls of the function.
+    ///
+    /// The kept plan is freed when it is dropped.
+    pub fn keep(self) -> Result<KeptPlan, PgError> {
+        let kept = unsafe { crate::guard_pg(|| pg_sys::SPI_keepplan(self.plan)) };
+        check_result(kept, "SPI_keepplan")?;
+
+        Ok(KeptPlan {
+            plan: PreparedPlan {
+                plan: self.plan,
+                plan_lifetime: PhantomData,
+            },
+        })
+    }
+
+    /// Frees the plan, `SPI_freeplan`
+    pub fn free(self) -> Result<(), PgError> {
+        let freed = unsafe { crate::guard_pg(|| pg_sys::SPI_freeplan(self.plan)) };
+        check_result(freed, "SPI_freeplan")
+    }
+
+    /// The number of parameters of the statement
+    pub fn arg_count(&self) -> usize {
+        unsafe { crate::guard_pg(|| pg_sys::SPI_getargcount(self.plan)) as usize }
+    }
+
+    /// The Oid of the type of the parameter, starting at 0 for `$1`
+    pub(crate) fn arg_type(&self, index: usize) -> pg_sys::Oid {
+        unsafe { crate::guard_pg(|| pg_sys::SPI_getargtypeid(self.plan, index as c_int)) }
+    }
+
+    pub(crate) fn as_ptr(&self) -> pg_sys::SPIPlanPtr {
+        self.plan
+    }
+}
+
+/// A plan kept with `PreparedPlan::keep`, it can be used with any `Spi` connection and is freed when dropped,
+///  `SPI_freeplan`
+pub struct KeptPlan {
+    plan: PreparedPlan<'static>,
+}
+
+impl Deref for KeptPlan {
+    type Target = PreparedPlan<'static>;
+
+    fn deref(&self) -> &PreparedPlan<'static> {
+        &self.plan
+    }
+}
+
+impl Drop for KeptPlan {
+    fn drop(&mut self) {
+        // a kept plan is not freed by the abort of the transaction, but freeing it while unwinding from an ERROR
+        //  could raise another, so it is leaked instead
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe {
+            crate::guard_pg(|| pg_sys::SPI_freeplan(self.plan.as_ptr()));
+        }
+    }
+}
+
+/// Prepared plans of a function, kept across calls of the function for as long as Postgres keeps its
+///  `FmgrInfo`, e.g. for the duration of a query.
+///
+/// Take `&PlanCache` as the first argument of a `#[pg_extern]` function, after the `&PgAllocator` if there is
+///  one, and it will be passed in.
+///
+/// ```rust,ignore
+/// #[pg_extern]
+/// fn count_words(plan_cache: &PlanCache, min_length: i32) -> i64 {
+///     let spi = Spi::connect().expect("failed to connect to SPI");
+///     let plan = plan_cache
+///         .get(&spi, "SELECT count(*) FROM words WHERE length(word) >= $1", &[PgType::Int4])
+///         .expect("failed to prepare");
+///     ...
+/// }
+/// ```
+pub struct PlanCache {
+    // the plans are boxed so that references to them stay valid as more are added, none are removed until drop
+    plans: RefCell<HashMap<String, Box<KeptPlan>>>,
+}
+
+impl PlanCache {
+    /// Returns the plan cache of the function, stored in `flinfo->fn_extra`, creating it on the first call
+    ///
+    /// The cache is dropped, and the plans freed, when the memory context of the `FmgrInfo` is reset.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_func_call_info<'a>(func_call_info: pg_sys::FunctionCallInfo) -> &'a PlanCache {
+        let flinfo = func_call_info
+            .as_ref()
+            .expect("func_call_info was unexpectedly NULL")
+            .flinfo
+            .as_mut()
+            .expect("flinfo was unexpectedly NULL");
+
+        if flinfo.fn_extra.is_null() {
+            let cache = Box::into_raw(Box::new(PlanCache {
+                plans: RefCell::new(HashMap::new()),
+            }));
+
+            // the callback must live as long as the memory context
+            crate::guard_pg(|| {
+                let callback = pg_sys::MemoryContextAllocZero(
+                    flinfo.fn_mcxt,
+                    size_of::<pg_sys::MemoryContextCallback>(),
+                ) as *mut pg_sys::MemoryContextCallback;
+
+                (*callback).func = Some(drop_plan_cache);
+                (*callback).arg = cache as *mut c_void;
+                pg_sys::MemoryContextRegisterResetCallback(flinfo.fn_mcxt, callback);
+            });
+
+            flinfo.fn_extra = cache as *mut c_void;
+        }
+
+        &*(flinfo.fn_extra as *const PlanCache)
+    }
+
+    /// Returns the plan for the SQL, preparing and keeping it on first use
+    ///
+    /// Plans are cached by the SQL text alone, the types are only used when the plan is prepared.
+    pub fn get<'c>(
+        &'c self,
+        spi: &Spi,
+        sql: &str,
+        types: &[PgType],
+    ) -> Result<&'c PreparedPlan<'static>, PgError> {
+        if let Some(plan) = self.plans.borrow().get(sql) {
+            let plan: *const PreparedPlan<'static> = &plan.plan;
+            // the box is only dropped with the cache
+            return Ok(unsafe { &*plan });
+        }
+
+        let plan = Box::new(spi.prepare(sql, types)?.keep()?);
+        let plan_ptr: *const PreparedPlan<'static> = &plan.plan;
+        self.plans.borrow_mut().insert(sql.to_string(), plan);
+
+        // the box is only dropped with the cache
+        Ok(unsafe { &*plan_ptr })
+    }
+
+    /// The number of cached plans
+    pub fn len(&self) -> usize {
+        self.plans.borrow().len()
+    }
+
+    /// Returns true if no plans are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The reset callback of the memory context of the `FmgrInfo`, this must not panic
+unsafe extern "C" fn drop_plan_cache(arg: *mut c_void) {
+    let cache = Box::from_raw(arg as *mut PlanCache);
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || drop(cache)));
+}
@@ -0,0 +1,126 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::native::HeapTuple;
+use crate::pg_alloc::PgAllocator;
+use crate::pg_datum::TryFromPgDatum;
+use crate::pg_error::PgError;
+use crate::pg_sys;
+use crate::pg_type::PgTypeInfo;
+use crate::row::{Columns, PgRow};
+use crate::spi::Spi;
+
+/// The rows returned by a statement, `SPITupleTable`, these are freed once all of the rows are dropped
+pub(crate) struct TupleTable {
+    table: *mut pg_sys::SPITupleTable,
+    memory_context: PgAllocator,
+}
+
+impl TupleTable {
+    /// Takes ownership of the table returned by SPI, e.g. `SPI_tuptable`
+    pub(crate) unsafe fn from_raw(table: *mut pg_sys::SPITupleTable) -> Self {
+        assert!(!table.is_null(), "SPITupleTable was unexpectedly NULL");
+
+        TupleTable {
+            table,
+            memory_context: PgAllocator::from_raw((*table).tuptabcxt),
+        }
+    }
+
+    /// Frees the table, and the rows in it, `SPI_freetuptable`
+    pub(crate) unsafe fn free(table: *mut pg_sys::SPITupleTable) {
+        if !table.is_null() {
+            crate::guard_pg(|| pg_sys::SPI_freetuptable(table));
+        }
+    }
+
+    /// Returns the first `len` rows of the table
+    pub(crate) fn into_rows<'spi>(self, len: usize) -> Vec<SpiRow<'spi>> {
+        let table = Rc::new(self);
+
+        (0..len)
+            .map(|index| SpiRow {
+                table: Rc::clone(&table),
+                index,
+                spi: PhantomData,
+            })
+            .collect()
+    }
+}
+
+impl Drop for TupleTable {
+    fn drop(&mut self) {
+        // the abort of the transaction frees the table after an ERROR, which may be why this is unwinding
+        if std::thread::panicking() {
+            return;
+        }
+
+        unsafe { Self::free(self.table) }
+    }
+}
+
+/// A row returned by a statement executed through `Spi`
+pub struct SpiRow<'spi> {
+    table: Rc<TupleTable>,
+    index: usize,
+    spi: PhantomData<&'spi Spi>,
+}
+
+impl<'spi> SpiRow<'spi> {
+    /// The row as a tuple, whose values are valid for as long as the row
+    pub fn tuple(&self) -> HeapTuple<'_> {
+        unsafe {
+            let table = &*self.table.table;
+            HeapTuple::from_raw(
+                &self.table.memory_context,
+                *table.vals.add(self.index),
+                table.tupdesc,
+            )
+        }
+    }
+
+    /// The number of columns
+    pub fn len(&self) -> usize {
+        self.tuple().len()
+    }
+
+    /// Returns true if there are no columns
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value of the column with the name
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    pub fn get<'r, T>(&'r self, name: &str) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'r> + PgTypeInfo + 'r,
+    {
+        Columns::get(&self.tuple(), name)
+    }
+
+    /// Returns the value of the column with the number, starting at 1
+    ///
+    /// An error is returned if the column does not exist, or if its type is not that of `T`.
+    pub fn get_by_number<'r, T>(&'r self, number: usize) -> Result<T, PgError>
+    where
+        T: TryFromPgDatum<'r> + PgTypeInfo + 'r,
+    {
+        Columns::get_by_number(&self.tuple(), number)
+    }
+
+    /// Reads the row into a `PgRow`, e.g. a struct with `#[derive(PgRow)]`
+    pub fn to_row<'r, T>(&'r self) -> Result<T, PgError>
+    where
+        T: PgRow<'r>,
+    {
+        T::from_columns(&self.tuple())
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for trigger functions, see `#[pg_trigger]` in pg-extern-attr.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::native::HeapTuple;
+use crate::pg_alloc::PgAllocator;
+use crate::pg_error::PgError;
+use crate::pg_sys;
+
+/// When the trigger fires, relative to the event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerWhen {
+    /// `BEFORE`
+    Before,
+    /// `AFTER`
+    After,
+    /// `INSTEAD OF`, only on views
+    InsteadOf,
+}
+
+/// The event which fired the trigger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerEvent {
+    /// `INSERT`
+    Insert,
+    /// `UPDATE`
+    Update,
+    /// `DELETE`
+    Delete,
+    /// `TRUNCATE`
+    Truncate,
+}
+
+/// Whether the trigger fires for each row, or once for the statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerLevel {
+    /// `FOR EACH ROW`
+    Row,
+    /// `FOR EACH STATEMENT`
+    Statement,
+}
+
+/// The data passed to a trigger function, wraps `TriggerData`
+pub struct Trigger<'mc> {
+    data: &'mc pg_sys::TriggerData,
+    memory_context: &'mc PgAllocator,
+}
+
+impl<'mc> Trigger<'mc> {
+    /// Returns the trigger data of the function call, or an error if the function was not called as a trigger,
+    ///  i.e. `CALLED_AS_TRIGGER`
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_func_call_info(
+        memory_context: &'mc PgAllocator,
+        func_call_info: pg_sys::FunctionCallInfo,
+    ) -> Result<Self, PgError> {
+        let context = func_call_info
+            .as_ref()
+            .expect("func_call_info was unexpectedly NULL")
+            .context;
+
+        if context.is_null() || (*context).type_ != pg_sys::NodeTag_T_TriggerData {
+            return Err(PgError::new("trigger function called, but not by a trigger")
+                .with_sqlstate("39P01"));
+        }
+
+        Ok(Trigger {
+            data: &*(context as *const pg_sys::TriggerData),
+            memory_context,
+        })
+    }
+
+    /// When the trigger fired, `BEFORE`, `AFTER` or `INSTEAD OF`
+    pub fn when(&self) -> TriggerWhen {
+        match self.data.tg_event & pg_sys::TRIGGER_EVENT_TIMINGMASK {
+            pg_sys::TRIGGER_EVENT_BEFORE => TriggerWhen::Before,
+            pg_sys::TRIGGER_EVENT_INSTEAD => TriggerWhen::InsteadOf,
+            _ => TriggerWhen::After,
+        }
+    }
+
+    /// The event that fired the trigger
+    pub fn event(&self) -> TriggerEvent {
+        match self.data.tg_event & pg_sys::TRIGGER_EVENT_OPMASK {
+            pg_sys::TRIGGER_EVENT_INSERT => TriggerEvent::Insert,
+            pg_sys::TRIGGER_EVENT_UPDATE => TriggerEvent::Update,
+            pg_sys::TRIGGER_EVENT_DELETE => TriggerEvent::Delete,
+            _ => TriggerEvent::Truncate,
+        }
+    }
+
+    /// `FOR EACH ROW` or `FOR EACH STATEMENT`
+    pub fn level(&self) -> TriggerLevel {
+        if self.data.tg_event & pg_sys::TRIGGER_EVENT_ROW != 0 {
+            TriggerLevel::Row
+        } else {
+            TriggerLevel::Statement
+        }
+    }
+
+    /// The name of the trigger
+    pub fn name(&self) -> String {
+        unsafe { to_string((*self.data.tg_trigger).tgname) }
+    }
+
+    /// The name of the table of the trigger
+    pub fn table_name(&self) -> String {
+        unsafe {
+            let relation = (*self.data.tg_relation).rd_rel;
+            to_string((*relation).relname.data.as_ptr())
+        }
+    }
+
+    /// The name of the schema of the table of the trigger
+    pub fn table_schema(&self) -> String {
+        unsafe {
+            let namespace = (*(*self.data.tg_relation).rd_rel).relnamespace;
+            let schema = crate::guard_pg(|| pg_sys::get_namespace_name(namespace));
+            to_string(schema)
+        }
+    }
+
+    /// The arguments of `CREATE TRIGGER ... EXECUTE PROCEDURE function(arguments)`
+    pub fn args(&self) -> Vec<String> {
+        unsafe {
+            let trigger = &*self.data.tg_trigger;
+            if trigger.tgargs.is_null() {
+                return Vec::new();
+            }
+
+            (0..trigger.tgnargs as usize)
+                .map(|i| to_string(*trigger.tgargs.add(i)))
+                .collect()
+        }
+    }
+
+    /// The new row of an `INSERT` or `UPDATE` row level trigger
+    ///
+    /// A `BEFORE` trigger returns this, or a modified copy, for it to be inserted or updated.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&self) -> Option<HeapTuple<'mc>> {
+        if self.level() != TriggerLevel::Row {
+            return None;
+        }
+
+        match self.event() {
+            TriggerEvent::Insert => self.tuple(self.data.tg_trigtuple),
+            TriggerEvent::Update => self.tuple(self.data.tg_newtuple),
+            _ => None,
+        }
+    }
+
+    /// The old row of an `UPDATE` or `DELETE` row level trigger
+    ///
+    /// A `BEFORE DELETE` trigger returns this for it to be deleted.
+    pub fn old(&self) -> Option<HeapTuple<'mc>> {
+        if self.level() != TriggerLevel::Row {
+            return None;
+        }
+
+        match self.event() {
+            TriggerEvent::Update | TriggerEvent::Delete => self.tuple(self.data.tg_trigtuple),
+            _ => None,
+        }
+    }
+
+    fn tuple(&self, tuple: pg_sys::HeapTuple) -> Option<HeapTuple<'mc>> {
+        if tuple.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let tupdesc = (*self.data.tg_relation).rd_att;
+            Some(HeapTuple::from_raw(self.memory_context, tuple, tupdesc))
+        }
+    }
+}
+
+/// Calls the trigger function, this is used by the functions generated with `#[pg_trigger]`.
+///
+/// A returned row is passed back to Postgres, `None` skips the operation for the row in a `BEFORE` trigger.
+///  An error is raised as an `ERROR`, as is any panic.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn call_trigger<F>(
+    name: &str,
+    func_call_info: pg_sys::FunctionCallInfo,
+    trigger_fn: F,
+) -> pg_sys::Datum
+where
+    F: for<'mc> FnOnce(&Trigger<'mc>) -> Result<Option<HeapTuple<'mc>>, PgError>,
+{
+    // All params will be in the "current" memory context at the call-site
+    let memory_context = PgAllocator::current_context();
+
+    // guard the Postgres process against the panic, and give us an oportunity to cleanup, an ERROR unwinds
+    //  the trigger function before its longjmp is continued by raise_panic
+    let result = crate::catch_unwind_pg(|| -> Result<pg_sys::Datum, PgError> {
+        let trigger = Trigger::from_func_call_info(&memory_context, func_call_info)?;
+
+        let tuple = trigger_fn(&trigger)?;
+        Ok(tuple.map_or(0, |tuple| tuple.into_raw() as pg_sys::Datum))
+    });
+
+    // the error is raised through raise_panic, which is safe within an outer catch_unwind_pg, e.g. when the
+    //  trigger is fired by a statement executed through SPI from a `#[pg_extern]` function
+    match result {
+        Ok(Ok(datum)) => datum,
+        Ok(Err(err)) => crate::raise_panic(name, Box::new(err)),
+        Err(err) => crate::raise_panic(name, err),
+    }
+}
+
+unsafe fn to_string(name: *const c_char) -> String {
+    if name.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(name).to_string_lossy().into_owned()
+}
@@ -0,0 +1,336 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for transactions, e.g. running part of a function in a subtransaction which can be rolled back, or
+//!  being called back on the commit or abort of the transaction.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::panic;
+use std::ptr;
+
+use crate::pg_error::CaughtPgError;
+use crate::pg_sys;
+use crate::JumpContext;
+
+/// Runs the closure in a subtransaction, `BeginInternalSubTransaction`, which is rolled back if it raises an
+///  `ERROR`, e.g. to try an insert and continue if it fails.
+///
+/// The subtransaction is committed, `ReleaseCurrentSubTransaction`, if the closure returns. If an `ERROR` is
+///  raised it is rolled back, `RollbackAndReleaseCurrentSubTransaction`, and the error is returned, the
+///  changes of the closure are undone but the rest of the transaction continues. A panic also rolls back the
+///  subtransaction, and then continues to unwind.
+///
+/// The closure runs in the memory context of the caller, and the memory context and resource owner of the
+///  caller are restored after the subtransaction either way.
+///
+/// Statements executed through `Spi` can be run in a subtransaction with `Spi::catch_errors`.
+///
+/// ```rust,ignore
+/// let locked = subtransaction(|| lock_relation_oid(relation, LockMode::AccessExclusive));
+///
+/// match locked {
+///     Ok(()) => notice!("locked"),
+///     // with lock_timeout set, waiting too long for the lock raises an ERROR
+///     Err(err) if err.sqlstate() == "55P03" => notice!("the table is busy"),
+///     Err(err) => return Err(err.into()),
+/// }
+/// ```
+pub fn subtransaction<R, F>(f: F) -> Result<R, CaughtPgError>
+where
+    F: FnOnce() -> R,
+{
+    unsafe {
+        let memory_context = pg_sys::CurrentMemoryContext;
+        let resource_owner = pg_sys::CurrentResourceOwner;
+        crate::guard_pg(|| pg_sys::BeginInternalSubTransaction(ptr::null_mut()));
+
+        // BeginInternalSubTransaction switches to the memory context of the subtransaction, which is reset
+        //  along with it, values returned from the closure must outlive it
+        pg_sys::CurrentMemoryContext = memory_context;
+
+        match crate::catch_unwind_pg(f) {
+            Ok(value) => {
+                crate::guard_pg(|| pg_sys::ReleaseCurrentSubTransaction());
+                pg_sys::CurrentMemoryContext = memory_context;
+                pg_sys::CurrentResourceOwner = resource_owner;
+
+                Ok(value)
+            }
+            Err(err) => {
+                // the error must be copied out of ErrorContext before the subtransaction is rolled back
+                pg_sys::CurrentMemoryContext = memory_context;
+                let caught = if err.is::<JumpContext>() {
+                    Some(CaughtPgError::take())
+                } else {
+                    None
+                };
+
+                crate::guard_pg(|| pg_sys::RollbackAndReleaseCurrentSubTransaction());
+                pg_sys::CurrentMemoryContext = memory_context;
+                pg_sys::CurrentResourceOwner = resource_owner;
+
+                match caught {
+                    Some(caught) => Err(caught),
+                    None => panic::resume_unwind(err),
+                }
+            }
+        }
+    }
+}
+
+/// The id of the current subtransaction, `GetCurrentSubTransactionId`, as passed to `on_subxact_event` callbacks
+pub fn current_subtransaction_id() -> pg_sys::SubTransactionId {
+    unsafe { crate::guard_pg(|| pg_sys::GetCurrentSubTransactionId()) }
+}
+
+/// An event in the life of a transaction, `XactEvent`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XactEvent {
+    /// The transaction committed, `XACT_EVENT_COMMIT`
+    Commit,
+    /// A parallel worker's transaction committed, `XACT_EVENT_PARALLEL_COMMIT`
+    ParallelCommit,
+    /// The transaction aborted, `XACT_EVENT_ABORT`
+    Abort,
+    /// A parallel worker's transaction aborted, `XACT_EVENT_PARALLEL_ABORT`
+    ParallelAbort,
+    /// The transaction was prepared for two phase commit, `XACT_EVENT_PREPARE`
+    Prepare,
+    /// The transaction is about to commit, `XACT_EVENT_PRE_COMMIT`, a panic here aborts the transaction
+    PreCommit,
+    /// A parallel worker's transaction is about to commit, `XACT_EVENT_PARALLEL_PRE_COMMIT`, a panic here aborts
+    ///  the transaction
+    ParallelPreCommit,
+    /// The transaction is about to be prepared, `XACT_EVENT_PRE_PREPARE`, a panic here aborts the transaction
+    PrePrepare,
+    /// An event not known to this library
+    Other(pg_sys::XactEvent),
+}
+
+impl XactEvent {
+    /// Returns true if the transaction can still be aborted, by raising an `ERROR`, during the event
+    pub fn can_abort(self) -> bool {
+        matches!(
+            self,
+            XactEvent::PreCommit | XactEvent::ParallelPreCommit | XactEvent::PrePrepare
+        )
+    }
+}
+
+impl From<pg_sys::XactEvent> for XactEvent {
+    fn from(event: pg_sys::XactEvent) -> Self {
+        match event {
+            pg_sys::XactEvent_XACT_EVENT_COMMIT => XactEvent::Commit,
+            pg_sys::XactEvent_XACT_EVENT_PARALLEL_COMMIT => XactEvent::ParallelCommit,
+            pg_sys::XactEvent_XACT_EVENT_ABORT => XactEvent::Abort,
+            pg_sys::XactEvent_XACT_EVENT_PARALLEL_ABORT => XactEvent::ParallelAbort,
+            pg_sys::XactEvent_XACT_EVENT_PREPARE => XactEvent::Prepare,
+            pg_sys::XactEvent_XACT_EVENT_PRE_COMMIT => XactEvent::PreCommit,
+            pg_sys::XactEvent_XACT_EVENT_PARALLEL_PRE_COMMIT => XactEvent::ParallelPreCommit,
+            pg_sys::XactEvent_XACT_EVENT_PRE_PREPARE => XactEvent::PrePrepare,
+            _ => XactEvent::Other(event),
+        }
+    }
+}
+
+/// An event in the life of a subtransaction, `SubXactEvent`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubXactEvent {
+    /// The subtransaction started, `SUBXACT_EVENT_START_SUB`
+    StartSub,
+    /// The subtransaction committed into its parent, `SUBXACT_EVENT_COMMIT_SUB`
+    CommitSub,
+    /// The subtransaction aborted, `SUBXACT_EVENT_ABORT_SUB`
+    AbortSub,
+    /// The subtransaction is about to commit, `SUBXACT_EVENT_PRE_COMMIT_SUB`, a panic here aborts the
+    ///  subtransaction
+    PreCommitSub,
+    /// An event not known to this library
+    Other(pg_sys::SubXactEvent),
+}
+
+impl SubXactEvent {
+    /// Returns true if the subtransaction can still be aborted, by raising an `ERROR`, during the event
+    pub fn can_abort(self) -> bool {
+        self == SubXactEvent::PreCommitSub
+    }
+}
+
+impl From<pg_sys::SubXactEvent> for SubXactEvent {
+    fn from(event: pg_sys::SubXactEvent) -> Self {
+        match event {
+            pg_sys::SubXactEvent_SUBXACT_EVENT_START_SUB => SubXactEvent::StartSub,
+            pg_sys::SubXactEvent_SUBXACT_EVENT_COMMIT_SUB => SubXactEvent::CommitSub,
+            pg_sys::SubXactEvent_SUBXACT_EVENT_ABORT_SUB => SubXactEvent::AbortSub,
+            pg_sys::SubXactEvent_SUBXACT_EVENT_PRE_COMMIT_SUB => SubXactEvent::PreCommitSub,
+            _ => SubXactEvent::Other(event),
+        }
+    }
+}
+
+/// A registered callback, the `arg` passed to Postgres
+struct Callback<F: ?Sized> {
+    /// Set while the callback is being called, so that it is not called recursively, nor unregistered
+    running: Cell<bool>,
+    callback: RefCell<Box<F>>,
+}
+
+type XactFn = dyn FnMut(XactEvent);
+type SubXactFn = dyn FnMut(SubXactEvent, pg_sys::SubTransactionId, pg_sys::SubTransactionId);
+
+/// Registers the closure to be called on each event of every transaction, `RegisterXactCallback`, e.g. to
+///  flush buffered writes at `PreCommit` or discard them at `Abort`
+///
+/// The closure stays registered for the life of the backend, unless unregistered with the returned
+///  `XactCallback`. A panic in the closure during `PreCommit`, `ParallelPreCommit` or `PrePrepare` aborts the
+///  transaction, it is too late to abort during the other events so a panic is only logged as a `WARNING`.
+///
+/// ```rust,ignore
+/// on_xact_event(|event| match event {
+///     XactEvent::PreCommit => flush_writes(),
+///     XactEvent::Abort => discard_writes(),
+///     _ => (),
+/// });
+/// ```
+pub fn on_xact_event<F>(callback: F) -> XactCallback
+where
+    F: FnMut(XactEvent) + 'static,
+{
+    let callback: Box<Callback<XactFn>> = Box::new(Callback {
+        running: Cell::new(false),
+        callback: RefCell::new(Box::new(callback)),
+    });
+    let arg = Box::into_raw(callback) as *mut c_void;
+
+    unsafe { crate::guard_pg(|| pg_sys::RegisterXactCallback(Some(call_xact_callback), arg)) };
+    XactCallback { arg }
+}
+
+/// Registers the closure to be called on each event of every subtransaction, `RegisterSubXactCallback`
+///
+/// The closure is passed the event, the id of the subtransaction and the id of its parent. It stays registered
+///  for the life of the backend, unless unregistered with the returned `SubXactCallback`. A panic in the
+///  closure during `PreCommitSub` aborts the subtransaction, during the other events it is only logged as a
+///  `WARNING`.
+pub fn on_subxact_event<F>(callback: F) -> SubXactCallback
+where
+    F: FnMut(SubXactEvent, pg_sys::SubTransactionId, pg_sys::SubTransactionId) + 'static,
+{
+    let callback: Box<Callback<SubXactFn>> = Box::new(Callback {
+        running: Cell::new(false),
+        callback: RefCell::new(Box::new(callback)),
+    });
+    let arg = Box::into_raw(callback) as *mut c_void;
+
+    unsafe { crate::guard_pg(|| pg_sys::RegisterSubXactCallback(Some(call_subxact_callback), arg)) };
+    SubXactCallback { arg }
+}
+
+/// A closure registered with `on_xact_event`, dropping this leaves the closure registered
+pub struct XactCallback {
+    arg: *mut c_void,
+}
+
+impl XactCallback {
+    /// Unregisters and drops the closure, `UnregisterXactCallback`
+    ///
+    /// # Panics
+    ///
+    /// If called from within the closure
+    pub fn unregister(self) {
+        let callback = self.arg as *mut Callback<XactFn>;
+        assert!(!unsafe { (*callback).running.get() }, "a callback can not unregister itself");
+
+        unsafe {
+            // the closure is only dropped once Postgres no longer has it
+            crate::guard_pg(|| pg_sys::UnregisterXactCallback(Some(call_xact_callback), self.arg));
+            drop(Box::from_raw(callback));
+        }
+    }
+}
+
+/// A closure registered with `on_subxact_event`, dropping this leaves the closure registered
+pub struct SubXactCallback {
+    arg: *mut c_void,
+}
+
+impl SubXactCallback {
+    /// Unregisters and drops the closure, `UnregisterSubXactCallback`
+    ///
+    /// # Panics
+    ///
+    /// If called from within the closure
+    pub fn unregister(self) {
+        let callback = self.arg as *mut Callback<SubXactFn>;
+        assert!(!unsafe { (*callback).running.get() }, "a callback can not unregister itself");
+
+        unsafe {
+            // the closure is only dropped once Postgres no longer has it
+            crate::guard_pg(|| pg_sys::UnregisterSubXactCallback(Some(call_subxact_callback), self.arg));
+            drop(Box::from_raw(callback));
+        }
+    }
+}
+
+unsafe extern "C" fn call_xact_callback(event: pg_sys::XactEvent, arg: *mut c_void) {
+    let event = XactEvent::from(event);
+    let callback = &*(arg as *const Callback<XactFn>);
+
+    call_callback("on_xact_event", event.can_abort(), callback, |callback| callback(event));
+}
+
+unsafe extern "C" fn call_subxact_callback(
+    event: pg_sys::SubXactEvent,
+    my_subid: pg_sys::SubTransactionId,
+    parent_subid: pg_sys::SubTransactionId,
+    arg: *mut c_void,
+) {
+    let event = SubXactEvent::from(event);
+    let callback = &*(arg as *const Callback<SubXactFn>);
+
+    call_callback("on_subxact_event", event.can_abort(), callback, |callback| {
+        callback(event, my_subid, parent_subid)
+    });
+}
+
+/// Calls the closure of the callback, this must not unwind into Postgres
+///
+/// An `ERROR` raised by Postgres continues to longjmp. A panic is raised as an `ERROR` if the transaction can be
+///  aborted, otherwise it is logged as a `WARNING`.
+unsafe fn call_callback<F, C>(name: &str, can_abort: bool, callback: &Callback<F>, call: C)
+where
+    F: ?Sized,
+    C: FnOnce(&mut F),
+{
+    // a callback which causes the same event, e.g. by starting a subtransaction, is not called again
+    if callback.running.replace(true) {
+        return;
+    }
+
+    let result = crate::catch_unwind_pg(|| call(&mut **callback.callback.borrow_mut()));
+    callback.running.set(false);
+
+    let err = match result {
+        Ok(()) => return,
+        Err(err) => err,
+    };
+
+    if let Some(jump_context) = err.downcast_ref::<JumpContext>() {
+        jump_context.continue_longjmp();
+    }
+
+    if can_abort {
+        crate::raise_panic(name, err);
+    }
+
+    if let Some(msg) = crate::panic_message(&*err) {
+        crate::warn!("panic executing Rust '{}': {}", name, msg);
+    } else {
+        crate::warn!("panic executing Rust '{}'", name);
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[pg_bgworker]`, which exports a Rust function as the main function of a background worker
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+
+pub(crate) fn impl_info_for_bgworker(item: &syn::Item) -> TokenStream {
+    let func = if let syn::Item::Fn(func) = item {
+        &func.sig
+    } else {
+        panic!("pg_bgworker only supported on functions");
+    };
+
+    let func_name = &func.ident;
+
+    if func.inputs.len() != 1 {
+        panic!(
+            "pg_bgworker functions must take only the worker, e.g. fn {}(worker: &BackgroundWorker)",
+            func_name
+        );
+    }
+
+    let mut expanded = item.clone().into_token_stream();
+
+    // the postmaster looks the function up by name in the library, it is not called from SQL
+    let func_wrapper_name = syn::Ident::new(&format!("pg_{}", func_name), Span::call_site());
+    expanded.extend(quote!(
+        #[no_mangle]
+        pub extern "C" fn #func_wrapper_name (main_arg: pg_extend::pg_sys::Datum) {
+            unsafe { pg_extend::bgworker::call_worker_main(stringify!(#func_name), main_arg, #func_name) }
+        }
+    ));
+
+    expanded
+}
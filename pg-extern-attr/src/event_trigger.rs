@@ -0,0 +1,167 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[pg_event_trigger]`, which exports a Rust function as a Postgres event trigger function
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::{AttributeArgs, Lit, Meta, NestedMeta};
+
+/// The events an event trigger can be created on, see `CREATE EVENT TRIGGER`
+const EVENTS: &[&str] = &[
+    "ddl_command_start",
+    "ddl_command_end",
+    "sql_drop",
+    "table_rewrite",
+];
+
+/// Options for the `CREATE EVENT TRIGGER` statement
+#[derive(Default)]
+struct EventTriggerOptions {
+    event: Option<String>,
+    tags: Vec<String>,
+}
+
+impl EventTriggerOptions {
+    /// Parse the options from the attribute arguments, e.g. `event = "ddl_command_end", tags = "CREATE TABLE"`
+    fn from_args(args: AttributeArgs) -> Self {
+        let mut options = EventTriggerOptions::default();
+
+        for arg in args {
+            let name_value = match arg {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+                _ => panic!("unsupported pg_event_trigger option, expected `name = \"value\"`"),
+            };
+
+            let value = match name_value.lit {
+                Lit::Str(ref s) => s.value(),
+                _ => panic!("pg_event_trigger options must be string literals"),
+            };
+
+            let name = name_value
+                .path
+                .get_ident()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+
+            match name.as_str() {
+                "event" => {
+                    if !EVENTS.contains(&value.as_str()) {
+                        panic!(
+                            "unknown pg_event_trigger event: {}, expected one of {:?}",
+                            value, EVENTS
+                        );
+                    }
+
+                    options.event = Some(value)
+                }
+                "tags" => options.tags = value.split(',').map(validate_tag).collect(),
+                _ => panic!("unknown pg_event_trigger option: {}", name),
+            }
+        }
+
+        if options.event.is_none() && !options.tags.is_empty() {
+            panic!("pg_event_trigger tags require an event, e.g. event = \"ddl_command_end\"");
+        }
+
+        options
+    }
+
+    /// Returns the `CREATE EVENT TRIGGER` statement, if an event was specified
+    ///
+    /// There is no `CREATE OR REPLACE EVENT TRIGGER`, so any existing event trigger is dropped first.
+    fn create_stmt(&self, func_name: &syn::Ident) -> Option<String> {
+        let event = self.event.as_ref()?;
+
+        let when = if self.tags.is_empty() {
+            String::new()
+        } else {
+            let tags = self
+                .tags
+                .iter()
+                .map(|tag| format!("'{}'", tag))
+                .collect::<Vec<_>>();
+
+            format!(" WHEN TAG IN ({})", tags.join(", "))
+        };
+
+        Some(format!(
+            "DROP EVENT TRIGGER IF EXISTS {name};\nCREATE EVENT TRIGGER {name} ON {event}{when} EXECUTE PROCEDURE {name}();",
+            name = func_name,
+            event = event,
+            when = when,
+        ))
+    }
+}
+
+/// Command tags are words of uppercase letters, e.g. `CREATE TABLE`
+fn validate_tag(tag: &str) -> String {
+    let tag = tag.trim();
+
+    if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_uppercase() || c == ' ') {
+        panic!("invalid pg_event_trigger tag: {:?}", tag);
+    }
+
+    tag.to_string()
+}
+
+pub(crate) fn impl_info_for_event_trigger(args: AttributeArgs, item: &syn::Item) -> TokenStream {
+    let func = if let syn::Item::Fn(func) = item {
+        &func.sig
+    } else {
+        panic!("pg_event_trigger only supported on functions");
+    };
+
+    let func_name = &func.ident;
+    let options = EventTriggerOptions::from_args(args);
+
+    if func.inputs.len() != 1 {
+        panic!(
+            "pg_event_trigger functions must take only the event trigger, e.g. fn {}(event_trigger: &EventTrigger)",
+            func_name
+        );
+    }
+
+    let mut expanded = item.clone().into_token_stream();
+
+    let func_wrapper_name = syn::Ident::new(&format!("pg_{}", func_name), Span::call_site());
+    expanded.extend(crate::get_info_fn(&func_wrapper_name));
+
+    // the event trigger data is validated and errors raised by call_event_trigger
+    expanded.extend(quote!(
+        #[no_mangle]
+        pub extern "C" fn #func_wrapper_name (func_call_info: pg_extend::pg_sys::FunctionCallInfo) -> pg_extend::pg_sys::Datum {
+            unsafe { pg_extend::event_trigger::call_event_trigger(stringify!(#func_name), func_call_info, #func_name) }
+        }
+    ));
+
+    let create_sql_name =
+        syn::Ident::new(&format!("{}_pg_create_stmt", func_name), Span::call_site());
+
+    let mut sql_stmt = format!(
+        "CREATE or REPLACE FUNCTION {}() RETURNS event_trigger AS '{{library_path}}', '{}' LANGUAGE C;",
+        func_name, func_wrapper_name,
+    );
+
+    if let Some(event_trigger_stmt) = options.create_stmt(func_name) {
+        sql_stmt.push('\n');
+        sql_stmt.push_str(&event_trigger_stmt);
+    }
+
+    // declare a function that can be used to output a create statement for the event trigger function
+    expanded.extend(quote!(
+        #[allow(unused)]
+        pub fn #create_sql_name(library_path: &str) -> String {
+            format!(
+                #sql_stmt,
+                library_path = library_path
+            )
+        }
+    ));
+
+    expanded
+}
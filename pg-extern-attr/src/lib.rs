@@ -21,18 +21,59 @@ use syn::spanned::Spanned;
 use syn::token::Comma;
 use syn::Type;
 
+mod bgworker;
+mod event_trigger;
 mod lifetime;
+mod opclass;
+mod operator;
+mod row;
+mod trigger;
+
+use operator::Operator;
+
+/// Arguments of the Rust function which are passed in by the wrapper, rather than from SQL, these must be
+///  the first arguments.
+#[derive(Clone, Copy, PartialEq)]
+enum RustArg {
+    /// `&PgAllocator`, the memory context of the call
+    PgAllocator,
+    /// `&PlanCache`, the prepared plans of the function
+    PlanCache,
+}
 
-/// A type that represents that PgAllocator is an argument to the Rust function.
-type HasPgAllocatorArg = bool;
+impl RustArg {
+    /// Returns the Rust argument the type is, if any
+    fn from_type(ty: &Type) -> Option<Self> {
+        if check_for_type_ref(ty, stringify!(PgAllocator)) {
+            Some(RustArg::PgAllocator)
+        } else if check_for_type_ref(ty, stringify!(PlanCache)) {
+            Some(RustArg::PlanCache)
+        } else {
+            None
+        }
+    }
+}
 
-fn create_function_params(num_args: usize, has_pg_allocator: HasPgAllocatorArg) -> TokenStream {
+/// Returns the leading Rust arguments of the function
+fn get_rust_args(arg_types: &[Type]) -> Vec<RustArg> {
+    arg_types
+        .iter()
+        .map(RustArg::from_type)
+        .take_while(Option::is_some)
+        .flatten()
+        .collect()
+}
+
+fn create_function_params(num_args: usize, rust_args: &[RustArg]) -> TokenStream {
     let mut tokens = TokenStream::new();
 
-    // if the allocator is the first arg we want to start at 1
-    if has_pg_allocator {
-        tokens.extend(quote!(&memory_context,));
-    };
+    // the Rust args come first, followed by the SQL args
+    for rust_arg in rust_args {
+        match rust_arg {
+            RustArg::PgAllocator => tokens.extend(quote!(&memory_context,)),
+            RustArg::PlanCache => tokens.extend(quote!(plan_cache,)),
+        }
+    }
 
     for i in 0..num_args {
         let arg_name = Ident::new(&format!("arg_{}", i), Span::call_site());
@@ -64,8 +105,16 @@ fn get_arg_types(inputs: &Punctuated<syn::FnArg, Comma>) -> Vec<syn::Type> {
     types
 }
 
-/// Check if the argument is the PgAllocator (aka MemoryContext)
-fn check_for_pg_allocator(ty: &Type) -> bool {
+/// Returns true if the attribute is the named attribute macro, e.g. `pg_extern` or `pg_extern_attr::pg_extern`
+fn is_attribute(attr: &syn::Attribute, name: &str) -> bool {
+    match attr.path.segments.last() {
+        Some(segment) => segment.ident == name,
+        None => false,
+    }
+}
+
+/// Check if the argument is a reference to the named type, e.g. `&PgAllocator` (aka MemoryContext)
+fn check_for_type_ref(ty: &Type, name: &str) -> bool {
     // we only accept references, i.e. &PgAllocator
     let type_ref = match ty {
         Type::Reference(type_ref) => type_ref,
@@ -79,7 +128,7 @@ fn check_for_pg_allocator(ty: &Type) -> bool {
             .segments
             .iter()
             .last()
-            .map_or(false, |p| p.ident == stringify!(PgAllocator)),
+            .is_some_and(|p| p.ident == name),
         _ => false,
     }
 }
@@ -89,17 +138,14 @@ fn check_for_pg_allocator(ty: &Type) -> bool {
 ///
 /// # Return
 ///
-/// The TokenStream of all the args, and a boolean if the first arg is the PgAllocator
-fn extract_arg_data(arg_types: &[Type]) -> (TokenStream, HasPgAllocatorArg) {
+/// The TokenStream of all the args, and the Rust args which are skipped
+fn extract_arg_data(arg_types: &[Type]) -> (TokenStream, Vec<RustArg>) {
     let mut get_args_stream = TokenStream::new();
 
-    // 1 to skip first 0, to use first arg.
-    let first_param_pg_allocator = arg_types
-        .first()
-        .map_or(false, |ty| check_for_pg_allocator(ty));
-    let skip_first = if first_param_pg_allocator { 1 } else { 0 };
+    // skip the Rust args, to use the first SQL arg.
+    let rust_args = get_rust_args(arg_types);
 
-    for (i, arg_type) in arg_types.iter().skip(skip_first).enumerate() {
+    for (i, arg_type) in arg_types.iter().skip(rust_args.len()).enumerate() {
         let arg_name = Ident::new(&format!("arg_{}", i), i.span());
         let arg_error = format!("unsupported function argument type for {}", arg_name);
 
@@ -117,7 +163,7 @@ fn extract_arg_data(arg_types: &[Type]) -> (TokenStream, HasPgAllocatorArg) {
         get_args_stream.extend(get_arg);
     }
 
-    (get_args_stream, first_param_pg_allocator)
+    (get_args_stream, rust_args)
 }
 
 fn sql_param_list(num_args: usize) -> String {
@@ -143,20 +189,12 @@ fn sql_param_list(num_args: usize) -> String {
 ///
 /// # Return
 ///
-/// The TokenStream of all the args, and a boolean if the first arg is the PgAllocator
-fn sql_param_types(arg_types: &[Type]) -> (TokenStream, bool) {
+/// The TokenStream of all the args
+fn sql_param_types(arg_types: &[Type]) -> TokenStream {
     let mut tokens = TokenStream::new();
 
-    // 1 to skip first 0, to use first arg.
-    let first_param_pg_allocator = arg_types
-        .first()
-        .map_or(false, |ty| check_for_pg_allocator(ty));
-
-    let arg_types = if first_param_pg_allocator {
-        &arg_types[1..]
-    } else {
-        arg_types
-    };
+    // skip the Rust args, to use the first SQL arg.
+    let arg_types = &arg_types[get_rust_args(arg_types).len()..];
 
     for (i, arg_type) in arg_types.iter().enumerate() {
         let sql_name = Ident::new(&format!("sql_{}", i), arg_type.span());
@@ -168,7 +206,7 @@ fn sql_param_types(arg_types: &[Type]) -> (TokenStream, bool) {
         tokens.extend(sql_param);
     }
 
-    (tokens, first_param_pg_allocator)
+    tokens
 }
 
 fn sql_return_type(outputs: &syn::ReturnType) -> TokenStream {
@@ -193,15 +231,7 @@ fn sql_function_options(arg_types: &[Type]) -> TokenStream {
         return quote!("",);
     }
 
-    let first_param_pg_allocator = arg_types
-        .first()
-        .map_or(false, |ty| check_for_pg_allocator(ty));
-
-    let arg_types = if first_param_pg_allocator {
-        &arg_types[1..]
-    } else {
-        arg_types
-    };
+    let arg_types = &arg_types[get_rust_args(arg_types).len()..];
 
     // if it's empty param list, return empty param list
     if arg_types.is_empty() {
@@ -288,7 +318,7 @@ fn get_info_fn(func_name: &syn::Ident) -> TokenStream {
     )
 }
 
-fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
+fn impl_info_for_fn(item: &syn::Item, operator: Option<&Operator>) -> TokenStream {
     let func = if let syn::Item::Fn(func) = item {
         &func.sig
     } else {
@@ -313,22 +343,26 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
     function.extend(func_info);
 
     let arg_types = get_arg_types(inputs);
-    let (get_args_from_datums, has_pg_allocator) = extract_arg_data(&arg_types);
+    let (get_args_from_datums, rust_args) = extract_arg_data(&arg_types);
     // remove the optional Rust arguments from the sql argument count
-    let num_sql_args = if has_pg_allocator {
-        arg_types.len() - 1
+    let num_sql_args = arg_types.len() - rust_args.len();
+
+    let func_params = create_function_params(num_sql_args, &rust_args);
+
+    // the plan cache is kept in the FmgrInfo across calls
+    let get_plan_cache = if rust_args.contains(&RustArg::PlanCache) {
+        quote!(
+            let plan_cache = unsafe { pg_extend::spi::PlanCache::from_func_call_info(func_call_info) };
+        )
     } else {
-        arg_types.len()
+        TokenStream::new()
     };
 
-    let func_params = create_function_params(num_sql_args, has_pg_allocator);
-
     // wrap the original function in a pg_wrapper function
     let func_wrapper = quote_spanned!( func_name.span() =>
         #[no_mangle]
         #[allow(unused_variables, unused_mut, clippy::suspicious_else_formatting, clippy::unit_arg, clippy::let_unit_value)]
         pub extern "C" fn #func_wrapper_name (func_call_info: pg_extend::pg_sys::FunctionCallInfo) -> pg_extend::pg_sys::Datum {
-            use std::panic;
             use pg_extend::pg_alloc::PgAllocator;
 
             // All params will be in the "current" memory context at the call-site
@@ -341,7 +375,9 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
             };
 
             // guard the Postgres process against the panic, and give us an oportunity to cleanup
-            let panic_result = panic::catch_unwind(|| {
+            let func_body = || {
+                #get_plan_cache
+
                 // extract the argument list
                 let mut args = pg_extend::get_args(func_info);
 
@@ -354,7 +390,10 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
 
                 // arbitrary Rust code could panic, so this is guarded
                 pg_extend::pg_datum::PgDatum::from(result)
-            });
+            };
+
+            // an ERROR raised by Postgres within the function also unwinds it, dropping its values
+            let panic_result = unsafe { pg_extend::catch_unwind_pg(func_body) };
 
             // see if we caught a panic
             match panic_result {
@@ -369,27 +408,12 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
                     }
                 }
                 Err(err) => {
-                    use std::sync::atomic::compiler_fence;
-                    use std::sync::atomic::Ordering;
-                    use pg_extend::error;
-
                     // ensure the return value is null
                     func_info.isnull = pg_extend::pg_bool::Bool::from(true).into();
 
-                    // The Rust code paniced, we need to recover to Postgres via a longjump
-                    //   A postgres logging error of Error will do this for us.
-                    compiler_fence(Ordering::SeqCst);
-                    if let Some(msg) = err.downcast_ref::<&'static str>() {
-                        error!("panic executing Rust '{}': {}", stringify!(#func_name), msg);
-                    }
-
-                    if let Some(msg) = err.downcast_ref::<String>() {
-                        error!("panic executing Rust '{}': {}", stringify!(#func_name), msg);
-                    }
-
-                    error!("panic executing Rust '{}'", stringify!(#func_name));
-
-                    unreachable!("log should have longjmped above, this is a bug in pg-extend-rs");
+                    // The Rust code paniced, we need to recover to Postgres via a longjump, an ERROR raised
+                    //   by Postgres continues its longjmp, otherwise the panic is raised as an ERROR
+                    pg_extend::raise_panic(stringify!(#func_name), err)
                 }
             }
         }
@@ -398,17 +422,22 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
     let create_sql_name =
         syn::Ident::new(&format!("{}_pg_create_stmt", func_name), Span::call_site());
 
-    let (sql_param_types, _has_pg_allocator) = sql_param_types(&arg_types);
+    let sql_param_types = sql_param_types(&arg_types);
     let sql_params = sql_param_list(num_sql_args);
     let sql_options = sql_function_options(&arg_types);
     let sql_return = sql_return_type(output);
 
     // ret and library_path are replacements at runtime
-    let sql_stmt = format!(
+    let mut sql_stmt = format!(
         "CREATE or REPLACE FUNCTION {}({}) {{ret}} AS '{{library_path}}', '{}' LANGUAGE C{{opts}};",
         func_name, sql_params, func_wrapper_name,
     );
 
+    // operators are declared after the function, sharing the same argument types
+    if let Some(operator) = operator {
+        sql_stmt.push_str(&operator.create_stmt(func_name, num_sql_args));
+    }
+
     // declare a function that can be used to output a create statement for the externed function
     //   all create statements will be put into a common module for access
     let create_sql_def = quote!(
@@ -480,6 +509,12 @@ fn impl_info_for_fn(item: &syn::Item) -> TokenStream {
 /// # }
 /// ```
 ///
+/// # Arguments
+///
+/// The first arguments of the function may be `&PgAllocator`, the memory context of the call, and
+///  `&PlanCache`, the prepared plans of the function. These are passed in by the wrapper, and are not
+///  arguments of the SQL function.
+///
 #[proc_macro_attribute]
 #[allow(clippy::needless_pass_by_value)]
 pub fn pg_extern(
@@ -487,13 +522,87 @@ pub fn pg_extern(
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     // get a usable token stream
-    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+    let mut ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // an operator may be declared along with the function, see `pg_operator`
+    let operator = match ast {
+        syn::Item::Fn(ref mut func) => operator::take_operator(&mut func.attrs),
+        _ => None,
+    };
 
     // output the original function definition.
     let mut expanded: TokenStream = ast.clone().into_token_stream();
 
     // Build the impl
-    expanded.extend(impl_info_for_fn(&ast));
+    expanded.extend(impl_info_for_fn(&ast, operator.as_ref()));
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for declaring a `#[pg_extern]` function as a Postgres operator.
+///
+/// This must be used along with `#[pg_extern]`, the `CREATE OPERATOR` statement is appended to the
+///  create statement for the function. The left and right argument types of the operator are the
+///  Postgres types of the function's arguments, a function with a single argument is a prefix operator.
+///
+/// The options mirror those of `CREATE OPERATOR`:
+///
+/// - `symbol = "<->"`, required, the name of the operator
+/// - `commutator = "<->"`, the commutator of this operator
+/// - `negator = "<>"`, the negator of this operator
+/// - `restrict = "eqsel"`, the restriction selectivity estimator function
+/// - `join = "eqjoinsel"`, the join selectivity estimator function
+/// - `hashes`, the operator can support a hash join
+/// - `merges`, the operator can support a merge join
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate pg_extend;
+/// extern crate pg_extern_attr;
+///
+/// use pg_extern_attr::{pg_extern, pg_operator};
+///
+/// #[pg_extern]
+/// #[pg_operator(symbol = "<->", commutator = "<->")]
+/// fn distance(left: f64, right: f64) -> f64 {
+///     (left - right).abs()
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_operator(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(attr as syn::AttributeArgs);
+    let operator = Operator::from_args(args);
+
+    // get a usable token stream
+    let mut ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // this attribute was placed above `#[pg_extern]`, so expand the function here
+    let func = match ast {
+        syn::Item::Fn(ref mut func) => func,
+        _ => panic!("pg_operator only supported on functions"),
+    };
+
+    let attr_count = func.attrs.len();
+    func.attrs.retain(|attr| !is_attribute(attr, "pg_extern"));
+    if func.attrs.len() == attr_count {
+        panic!("pg_operator must be used with pg_extern");
+    }
+
+    if operator::take_operator(&mut func.attrs).is_some() {
+        panic!("only one pg_operator attribute is allowed per function");
+    }
+
+    // output the original function definition.
+    let mut expanded: TokenStream = ast.clone().into_token_stream();
+
+    // Build the impl
+    expanded.extend(impl_info_for_fn(&ast, Some(&operator)));
 
     // Return the generated impl
     proc_macro::TokenStream::from(expanded)
@@ -516,3 +625,292 @@ pub fn pg_foreignwrapper(
     // Return the generated impl
     proc_macro::TokenStream::from(expanded)
 }
+
+/// An attribute macro for generating the operators and operator classes needed to index a type.
+///
+/// The type must implement `Ord`, and `Hash` for hash indexes. It must also be usable as an argument
+///  of a `#[pg_extern]` function, i.e. implement `TryFromPgDatum` and `PgTypeInfo`. The `PgTypeInfo`
+///  must be a `PgType::Custom`, the type must have been created with `CREATE TYPE` for the extension.
+///
+/// Each of the following functions is generated as if it were declared with `#[pg_extern]` and
+///  `#[pg_operator]`, named with the snake case of the type, e.g. `my_point_eq` for `MyPoint`:
+///
+/// - `btree`: the `=`, `<>`, `<`, `<=`, `>` and `>=` operators, a `_cmp` comparison support function,
+///   and `CREATE OPERATOR CLASS ... DEFAULT FOR TYPE ... USING btree`
+/// - `hash`: the `=` and `<>` operators, a `_hash` support function, and
+///   `CREATE OPERATOR CLASS ... DEFAULT FOR TYPE ... USING hash`
+/// - `brin`: requires `btree`, `CREATE OPERATOR CLASS ... DEFAULT FOR TYPE ... USING brin` with the minmax
+///   support functions of Postgres
+///
+/// A `{type}_pg_create_stmt` function, e.g. `my_point_pg_create_stmt`, outputs the create statements of all
+///  of them.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_opclass(btree, hash)]
+/// #[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// struct MyPoint {
+///     x: i32,
+///     y: i32,
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_opclass(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(attr as syn::AttributeArgs);
+
+    // get a usable token stream
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = opclass::impl_info_for_opclass(args, &ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// A derive for generating the btree operators and operator class of a type, the same as
+///  `#[pg_opclass(btree)]`, see `pg_opclass`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(PartialEq, Eq, PartialOrd, Ord, PgOrd)]
+/// struct Version(i64);
+/// ```
+#[proc_macro_derive(PgOrd)]
+pub fn pg_ord(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    // Build the impl
+    let expanded: TokenStream = opclass::impl_pg_ord(&ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for generating the GiST support functions of a type implementing
+///  `pg_extend::index::GistSupport`.
+///
+/// The support functions are exported as `pg_{type}_gist_{function}`, named with the snake case of the type,
+///  and a `{type}_pg_create_stmt` function outputs the create statements for them and the
+///  `CREATE OPERATOR CLASS {type}_ops FOR TYPE ... USING gist`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_gist]
+/// struct RangeOps;
+///
+/// impl GistSupport for RangeOps {
+///     type Value = Range;
+///     type Query = Range;
+///     type Key = Range;
+///     ...
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_gist(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = opclass::impl_info_for_support("gist", &ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for generating the GIN support functions of a type implementing
+///  `pg_extend::index::GinSupport`.
+///
+/// The support functions are exported as `pg_{type}_gin_{function}`, named with the snake case of the type,
+///  and a `{type}_pg_create_stmt` function outputs the create statements for them and the
+///  `CREATE OPERATOR CLASS {type}_ops FOR TYPE ... USING gin`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_gin]
+/// struct WordOps;
+///
+/// impl GinSupport for WordOps {
+///     type Value = String;
+///     type Query = String;
+///     type Key = String;
+///     ...
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_gin(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = opclass::impl_info_for_support("gin", &ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for exporting a Rust function as a Postgres trigger function, `RETURNS trigger`.
+///
+/// The function takes the `pg_extend::trigger::Trigger` and returns the row for Postgres to use, which is
+///  ignored for `AFTER` and statement level triggers. Returning `None` from a `BEFORE` row level trigger skips
+///  the operation for the row. An `Err` is raised as an `ERROR`, as is calling the function other than from a
+///  trigger.
+///
+/// A `{name}_pg_create_stmt` function outputs the `CREATE FUNCTION` statement, the `CREATE TRIGGER` is left
+///  to the user.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_trigger]
+/// fn uppercase_name<'mc>(trigger: &Trigger<'mc>) -> Result<Option<HeapTuple<'mc>>, PgError> {
+///     let new = trigger.new().ok_or("uppercase_name must be a row level INSERT or UPDATE trigger")?;
+///     let name: String = new.get("name")?;
+///     new.set("name", name.to_uppercase()).map(Some)
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_trigger(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = trigger::impl_info_for_trigger(&ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for exporting a Rust function as a Postgres event trigger function, `RETURNS event_trigger`.
+///
+/// The function takes the `pg_extend::event_trigger::EventTrigger` and returns `Result<(), PgError>`, an `Err`
+///  is raised as an `ERROR`, which aborts the statement.
+///
+/// A `{name}_pg_create_stmt` function outputs the `CREATE FUNCTION` statement. With the options it also
+///  outputs the `CREATE EVENT TRIGGER`, named the same as the function:
+///
+/// - `event = "ddl_command_end"`, the event to fire on, `ddl_command_start`, `ddl_command_end`, `sql_drop` or
+///   `table_rewrite`
+/// - `tags = "CREATE TABLE, ALTER TABLE"`, optional, the command tags to fire for, `WHEN TAG IN (...)`
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_event_trigger(event = "ddl_command_end", tags = "CREATE TABLE")]
+/// fn audit_tables(event_trigger: &EventTrigger) -> Result<(), PgError> {
+///     for command in event_trigger.ddl_commands()? {
+///         notice!("created {}", command.object_identity);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_event_trigger(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let args = parse_macro_input!(attr as syn::AttributeArgs);
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = event_trigger::impl_info_for_event_trigger(args, &ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// An attribute macro for exporting a Rust function as the main function of a background worker.
+///
+/// The function takes the `pg_extend::bgworker::BackgroundWorker`, and the worker exits when it returns. It is
+///  exported as `pg_{name}`, which is the function to register the worker with in a
+///  `pg_extend::bgworker::BackgroundWorkerBuilder`. A panic is raised as an `ERROR`, which exits the worker.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[pg_bgworker]
+/// fn vacuum_scheduler(worker: &BackgroundWorker) {
+///     worker.connect("postgres", None);
+///
+///     while worker.wait(Duration::from_secs(60)) {
+///         worker.transaction(|| run_scheduled_vacuums());
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pg_bgworker(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast: syn::Item = parse_macro_input!(item as syn::Item);
+
+    // Build the impl
+    let expanded: TokenStream = bgworker::impl_info_for_bgworker(&ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// A derive macro for reading a struct from the columns of a row, implements `pg_extend::row::PgRow`.
+///
+/// Each field is read with `TryFromPgDatum`, from the column with the name of the field, or for tuple structs
+///  the column at the position of the field. This can be overridden on a field:
+///
+/// - `#[pg_row(name = "column")]`, read from the column with the name
+/// - `#[pg_row(position = 1)]`, read from the column at the position, starting at 1
+///
+/// A missing column, or a column whose type is not that of the field, is an error. The row can be read from an
+///  SPI row with `SpiRow::to_row`, or from anything implementing `pg_extend::row::Columns`, e.g. the `HeapTuple`
+///  of a trigger, or the `ForeignTuple` of a foreign data wrapper.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(PgRow)]
+/// struct Word {
+///     word: String,
+///     #[pg_row(name = "length")]
+///     len: i32,
+/// }
+///
+/// for row in spi.select("SELECT word, length(word) AS length FROM words", &[])? {
+///     let word: Word = row.to_row()?;
+/// }
+/// ```
+#[proc_macro_derive(PgRow, attributes(pg_row))]
+pub fn pg_row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    // get a usable token stream
+    let ast = parse_macro_input!(input as syn::DeriveInput);
+
+    // Build the impl
+    let expanded: TokenStream = row::impl_pg_row(&ast);
+
+    // Return the generated impl
+    proc_macro::TokenStream::from(expanded)
+}
@@ -41,8 +41,8 @@ fn sl_generic_argument(args: &mut syn::GenericArgument) {
         Type(ref mut ty) => strip_type(ty),
         Binding(ref mut binding) => strip_type(&mut binding.ty),
         Constraint(ref mut constraint) => {
-            for mut bound in &mut constraint.bounds {
-                sl_type_param_bound(&mut bound);
+            for bound in &mut constraint.bounds {
+                sl_type_param_bound(bound);
             }
         }
         Const(expr) => unimplemented!("Const not supported by pg-extern: {:?}", expr),
@@ -102,8 +102,8 @@ pub(crate) fn strip_type(ty: &mut syn::Type) {
         }
         Never(_type_never) => (),
         Tuple(type_tuple) => {
-            for mut i in &mut type_tuple.elems {
-                strip_type(&mut i);
+            for i in &mut type_tuple.elems {
+                strip_type(i);
             }
         }
         Path(ref mut type_path) => sl_type_path(type_path),
@@ -0,0 +1,397 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[pg_opclass]`, which generates the operators and operator classes for indexing a type
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::{AttributeArgs, Meta, NestedMeta};
+
+use crate::operator;
+
+/// The index access methods for which operator classes can be generated
+#[derive(Default)]
+struct IndexMethods {
+    btree: bool,
+    hash: bool,
+    brin: bool,
+}
+
+impl IndexMethods {
+    fn from_args(args: AttributeArgs) -> Self {
+        let mut methods = IndexMethods::default();
+
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("btree") => {
+                    methods.btree = true
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("hash") => {
+                    methods.hash = true
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("brin") => {
+                    methods.brin = true
+                }
+                _ => panic!("unsupported pg_opclass option, expected `btree`, `hash` and/or `brin`"),
+            }
+        }
+
+        if !methods.btree && !methods.hash && !methods.brin {
+            panic!("pg_opclass requires an index method, e.g. #[pg_opclass(btree)]");
+        }
+
+        // the brin minmax support functions use the btree operators
+        if methods.brin && !methods.btree {
+            panic!("pg_opclass(brin) requires btree, e.g. #[pg_opclass(btree, brin)]");
+        }
+
+        methods
+    }
+}
+
+/// Converts a type name to snake case for the SQL names, e.g. `MyPoint` to `my_point`
+///
+/// A run of capitals is one word, e.g. `IPAddr` to `ip_addr`.
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+
+    for (i, &ch) in chars.iter().enumerate() {
+        if ch.is_uppercase() {
+            // a word starts after a lowercase letter or digit, or at the last capital of a run
+            let prev_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_numeric());
+            let ends_run = i > 0
+                && chars[i - 1].is_uppercase()
+                && chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+
+            if prev_lower || ends_run {
+                snake.push('_');
+            }
+            snake.extend(ch.to_lowercase());
+        } else {
+            snake.push(ch);
+        }
+    }
+
+    snake
+}
+
+/// A comparison operator, the name of the generated function and the attribute declaring the operator
+fn comparison(
+    prefix: &str,
+    name: &str,
+    operator: TokenStream,
+    ty: &syn::Ident,
+    body: TokenStream,
+) -> syn::ItemFn {
+    let func_name = syn::Ident::new(&format!("{}_{}", prefix, name), Span::call_site());
+
+    parse_quote!(
+        #[pg_operator(#operator)]
+        #[allow(clippy::needless_pass_by_value)]
+        fn #func_name(left: #ty, right: #ty) -> bool {
+            let ordering = std::cmp::Ord::cmp(&left, &right);
+            #body
+        }
+    )
+}
+
+/// The support functions wrapped by `GistWrapper`, see `GistSupport` in pg-extend
+const GIST_FUNCTIONS: &[&str] = &[
+    "consistent",
+    "union",
+    "compress",
+    "decompress",
+    "penalty",
+    "picksplit",
+    "same",
+];
+
+/// The support functions wrapped by `GinWrapper`, see `GinSupport` in pg-extend
+const GIN_FUNCTIONS: &[&str] = &[
+    "compare",
+    "extract_value",
+    "extract_query",
+    "consistent",
+    "tri_consistent",
+];
+
+pub(crate) fn impl_info_for_opclass(args: AttributeArgs, item: &syn::Item) -> TokenStream {
+    let methods = IndexMethods::from_args(args);
+
+    let (ty, generics) = match item {
+        syn::Item::Struct(item) => (&item.ident, &item.generics),
+        syn::Item::Enum(item) => (&item.ident, &item.generics),
+        _ => panic!("pg_opclass only supported on structs and enums"),
+    };
+
+    let mut expanded = item.clone().into_token_stream();
+    expanded.extend(impl_opclass(&methods, "pg_opclass", ty, generics));
+    expanded
+}
+
+/// `#[derive(PgOrd)]`, the same as `#[pg_opclass(btree)]`
+pub(crate) fn impl_pg_ord(ast: &syn::DeriveInput) -> TokenStream {
+    let methods = IndexMethods {
+        btree: true,
+        ..IndexMethods::default()
+    };
+
+    impl_opclass(&methods, "PgOrd", &ast.ident, &ast.generics)
+}
+
+/// Generates the operators and operator classes of the type, `macro_name` is used in the error messages
+fn impl_opclass(
+    methods: &IndexMethods,
+    macro_name: &str,
+    ty: &syn::Ident,
+    generics: &syn::Generics,
+) -> TokenStream {
+    if !generics.params.is_empty() {
+        panic!(
+            "{} is not supported on types with generic parameters or lifetimes",
+            macro_name
+        );
+    }
+
+    let prefix = to_snake_case(&ty.to_string());
+    let opclass_name = format!("{}_ops", prefix);
+
+    // the equality operator can take part in merge joins with btree, and hash joins with hash
+    let mut eq_options = quote!(
+        symbol = "=",
+        commutator = "=",
+        negator = "<>",
+        restrict = "eqsel",
+        join = "eqjoinsel"
+    );
+    if methods.btree {
+        eq_options.extend(quote!(, merges));
+    }
+    if methods.hash {
+        eq_options.extend(quote!(, hashes));
+    }
+
+    let mut functions = vec![
+        comparison(&prefix, "eq", eq_options, ty, quote!(ordering == std::cmp::Ordering::Equal)),
+        comparison(
+            &prefix,
+            "ne",
+            quote!(symbol = "<>", commutator = "<>", negator = "=", restrict = "neqsel", join = "neqjoinsel"),
+            ty,
+            quote!(ordering != std::cmp::Ordering::Equal),
+        ),
+    ];
+
+    // the CREATE OPERATOR CLASS statements, with the SQL type left as `{ty}`
+    let mut opclasses = Vec::new();
+
+    if methods.btree {
+        functions.push(comparison(
+            &prefix,
+            "lt",
+            quote!(symbol = "<", commutator = ">", negator = ">=", restrict = "scalarltsel", join = "scalarltjoinsel"),
+            ty,
+            quote!(ordering == std::cmp::Ordering::Less),
+        ));
+        functions.push(comparison(
+            &prefix,
+            "le",
+            quote!(symbol = "<=", commutator = ">=", negator = ">", restrict = "scalarltsel", join = "scalarltjoinsel"),
+            ty,
+            quote!(ordering != std::cmp::Ordering::Greater),
+        ));
+        functions.push(comparison(
+            &prefix,
+            "gt",
+            quote!(symbol = ">", commutator = "<", negator = "<=", restrict = "scalargtsel", join = "scalargtjoinsel"),
+            ty,
+            quote!(ordering == std::cmp::Ordering::Greater),
+        ));
+        functions.push(comparison(
+            &prefix,
+            "ge",
+            quote!(symbol = ">=", commutator = "<=", negator = "<", restrict = "scalargtsel", join = "scalargtjoinsel"),
+            ty,
+            quote!(ordering != std::cmp::Ordering::Less),
+        ));
+
+        let cmp_name = syn::Ident::new(&format!("{}_cmp", prefix), Span::call_site());
+        functions.push(parse_quote!(
+            #[allow(clippy::needless_pass_by_value)]
+            fn #cmp_name(left: #ty, right: #ty) -> i32 {
+                pg_extend::index::btree_cmp(&left, &right)
+            }
+        ));
+
+        opclasses.push(format!(
+            "CREATE OPERATOR CLASS {name} DEFAULT FOR TYPE {{ty}} USING btree AS \
+             OPERATOR 1 <, OPERATOR 2 <=, OPERATOR 3 =, OPERATOR 4 >=, OPERATOR 5 >, \
+             FUNCTION 1 {cmp}({{ty}}, {{ty}});",
+            name = opclass_name,
+            cmp = cmp_name,
+        ));
+    }
+
+    if methods.brin {
+        // the minmax support functions of the built-in types work for any type with the btree operators
+        opclasses.push(format!(
+            "CREATE OPERATOR CLASS {name} DEFAULT FOR TYPE {{ty}} USING brin AS \
+             OPERATOR 1 <, OPERATOR 2 <=, OPERATOR 3 =, OPERATOR 4 >=, OPERATOR 5 >, \
+             FUNCTION 1 brin_minmax_opcinfo(internal), \
+             FUNCTION 2 brin_minmax_add_value(internal, internal, internal, internal), \
+             FUNCTION 3 brin_minmax_consistent(internal, internal, internal), \
+             FUNCTION 4 brin_minmax_union(internal, internal, internal);",
+            name = opclass_name,
+        ));
+    }
+
+    if methods.hash {
+        let hash_name = syn::Ident::new(&format!("{}_hash", prefix), Span::call_site());
+        functions.push(parse_quote!(
+            #[allow(clippy::needless_pass_by_value)]
+            fn #hash_name(value: #ty) -> i32 {
+                pg_extend::index::hash_value(&value)
+            }
+        ));
+
+        opclasses.push(format!(
+            "CREATE OPERATOR CLASS {name} DEFAULT FOR TYPE {{ty}} USING hash AS \
+             OPERATOR 1 =, FUNCTION 1 {hash}({{ty}});",
+            name = opclass_name,
+            hash = hash_name,
+        ));
+    }
+
+    let mut expanded = TokenStream::new();
+    let mut create_stmt_names = Vec::new();
+
+    // each function is expanded as if it were annotated with `#[pg_extern]`
+    for mut function in functions {
+        let operator = operator::take_operator(&mut function.attrs);
+
+        create_stmt_names.push(syn::Ident::new(
+            &format!("{}_pg_create_stmt", function.sig.ident),
+            Span::call_site(),
+        ));
+
+        let function = syn::Item::Fn(function);
+        expanded.extend(function.to_token_stream());
+        expanded.extend(crate::impl_info_for_fn(&function, operator.as_ref()));
+    }
+
+    let create_sql_name =
+        syn::Ident::new(&format!("{}_pg_create_stmt", prefix), Span::call_site());
+    // there is no `CREATE OR REPLACE OPERATOR CLASS`, an existing operator class is kept as indexes may use it
+    let opclass_stmt = opclasses
+        .iter()
+        .map(|opclass| {
+            format!(
+                "DO $$ BEGIN {} EXCEPTION WHEN duplicate_object THEN NULL; END $$;",
+                opclass
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let custom_type_msg = format!("{} requires a PgType::Custom type: {{}}", macro_name);
+
+    // declare a function that can be used to output the create statements for all the functions,
+    //   operators and operator classes
+    let create_sql_def = quote!(
+        #[allow(unused)]
+        pub fn #create_sql_name(library_path: &str) -> String {
+            use pg_extend::pg_type::PgTypeInfo;
+
+            // the operators would replace those of the built-in type
+            let ty = match pg_extend::pg_type::PgType::from_rust::<#ty>() {
+                pg_extend::pg_type::PgType::Custom(ty) => ty,
+                _ => panic!(#custom_type_msg, stringify!(#ty)),
+            };
+
+            let mut stmts: Vec<String> = Vec::new();
+            #( stmts.push(#create_stmt_names(library_path)); )*
+            stmts.push(format!(#opclass_stmt, ty = ty));
+
+            stmts.join("\n")
+        }
+    );
+
+    expanded.extend(create_sql_def);
+    expanded
+}
+
+/// Generates the Postgres functions for an implementation of `GistSupport` or `GinSupport`, `method` is `gist` or `gin`
+pub(crate) fn impl_info_for_support(method: &str, item: &syn::Item) -> TokenStream {
+    let (ty, generics) = match item {
+        syn::Item::Struct(item) => (&item.ident, &item.generics),
+        syn::Item::Enum(item) => (&item.ident, &item.generics),
+        _ => panic!("pg_{} only supported on structs and enums", method),
+    };
+
+    if !generics.params.is_empty() {
+        panic!(
+            "pg_{} is not supported on types with generic parameters or lifetimes",
+            method
+        );
+    }
+
+    let (wrapper, functions) = match method {
+        "gist" => (quote!(pg_extend::index::GistWrapper), GIST_FUNCTIONS),
+        "gin" => (quote!(pg_extend::index::GinWrapper), GIN_FUNCTIONS),
+        _ => unreachable!("unsupported index method: {}", method),
+    };
+
+    let prefix = to_snake_case(&ty.to_string());
+    let mut expanded = item.clone().into_token_stream();
+
+    for function in functions {
+        let func_name = syn::Ident::new(
+            &format!("pg_{}_{}_{}", prefix, method, function),
+            Span::call_site(),
+        );
+        let wrapper_fn = syn::Ident::new(function, Span::call_site());
+
+        expanded.extend(crate::get_info_fn(&func_name));
+        expanded.extend(quote!(
+            #[no_mangle]
+            pub extern "C" fn #func_name (func_call_info: pg_extend::pg_sys::FunctionCallInfo) -> pg_extend::pg_sys::Datum {
+                pg_extend::index::guard_support_function(stringify!(#func_name), || unsafe {
+                    #wrapper::<#ty>::#wrapper_fn(func_call_info)
+                })
+            }
+        ));
+    }
+
+    let create_sql_name =
+        syn::Ident::new(&format!("{}_pg_create_stmt", prefix), Span::call_site());
+
+    // declare a function that can be used to output the create statements for the support functions
+    //   and operator class
+    expanded.extend(quote!(
+        #[allow(unused)]
+        pub fn #create_sql_name(library_path: &str) -> String {
+            #wrapper::<#ty>::create_stmt(#prefix, library_path)
+        }
+    ));
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("MyPoint"), "my_point");
+        assert_eq!(to_snake_case("IPAddr"), "ip_addr");
+        assert_eq!(to_snake_case("Int8Signature"), "int8_signature");
+        assert_eq!(to_snake_case("HTTP"), "http");
+        assert_eq!(to_snake_case("Version2Id"), "version2_id");
+        assert_eq!(to_snake_case("point"), "point");
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[pg_operator]`, which adds a `CREATE OPERATOR` to the create statement of a `#[pg_extern]` function
+
+use syn::{Attribute, AttributeArgs, Lit, Meta, NestedMeta};
+
+use crate::is_attribute;
+
+/// The characters Postgres allows in an operator name, see `CREATE OPERATOR`
+const OPERATOR_CHARS: &str = "+-*/<>=~!@#%^&|`?";
+
+/// The characters which allow a multiple character operator name to end in `+` or `-`
+const SUFFIX_SIGN_CHARS: &str = "~!@#%^&|`?";
+
+/// The maximum length of an operator name, `NAMEDATALEN - 1`
+const MAX_SYMBOL_LEN: usize = 63;
+
+/// Options for the `CREATE OPERATOR` statement
+#[derive(Default)]
+pub(crate) struct Operator {
+    symbol: String,
+    commutator: Option<String>,
+    negator: Option<String>,
+    restrict: Option<String>,
+    join: Option<String>,
+    hashes: bool,
+    merges: bool,
+}
+
+impl Operator {
+    /// Parse the options from the attribute arguments, e.g. `symbol = "<->", hashes`
+    pub(crate) fn from_args(args: AttributeArgs) -> Self {
+        let mut operator = Operator::default();
+
+        for arg in args {
+            match arg {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                    let value = match name_value.lit {
+                        Lit::Str(ref s) => s.value(),
+                        _ => panic!("pg_operator options must be string literals"),
+                    };
+
+                    let name = name_value
+                        .path
+                        .get_ident()
+                        .map(ToString::to_string)
+                        .unwrap_or_default();
+
+                    match name.as_str() {
+                        "symbol" => operator.symbol = validate_symbol(value),
+                        "commutator" => operator.commutator = Some(validate_symbol(value)),
+                        "negator" => operator.negator = Some(validate_symbol(value)),
+                        "restrict" => operator.restrict = Some(value),
+                        "join" => operator.join = Some(value),
+                        _ => panic!("unknown pg_operator option: {}", name),
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("hashes") => {
+                    operator.hashes = true
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("merges") => {
+                    operator.merges = true
+                }
+                _ => panic!("unsupported pg_operator option, expected `name = \"value\"`, `hashes` or `merges`"),
+            }
+        }
+
+        if operator.symbol.is_empty() {
+            panic!("pg_operator requires a symbol, e.g. #[pg_operator(symbol = \"<->\")]");
+        }
+
+        operator
+    }
+
+    /// Parse the options from an attribute on the function, i.e. `#[pg_operator(symbol = "<->")]`
+    pub(crate) fn from_attribute(attr: &Attribute) -> Self {
+        let args = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested.into_iter().collect(),
+            Ok(_) => AttributeArgs::new(),
+            Err(err) => panic!("failed to parse pg_operator attribute: {}", err),
+        };
+
+        Self::from_args(args)
+    }
+
+    /// Returns the `CREATE OPERATOR` statement for the function
+    ///
+    /// The argument types are left as `{sql_N}` replacements, the same as the `CREATE FUNCTION` statement.
+    ///  There is no `CREATE OR REPLACE OPERATOR`, so an existing operator is kept, e.g. when the statements are
+    ///  run again, as it may be used by operator classes and indexes.
+    pub(crate) fn create_stmt(&self, func_name: &syn::Ident, num_args: usize) -> String {
+        let mut options = vec![format!("PROCEDURE = {}", func_name)];
+
+        // a single argument is a prefix operator, which has no left argument
+        match num_args {
+            1 => options.push("RIGHTARG = {sql_0}".to_string()),
+            2 => {
+                options.push("LEFTARG = {sql_0}".to_string());
+                options.push("RIGHTARG = {sql_1}".to_string());
+            }
+            _ => panic!(
+                "pg_operator functions must take one or two arguments, {} takes {}",
+                func_name, num_args
+            ),
+        };
+
+        if let Some(ref commutator) = self.commutator {
+            options.push(format!("COMMUTATOR = {}", commutator));
+        }
+        if let Some(ref negator) = self.negator {
+            options.push(format!("NEGATOR = {}", negator));
+        }
+        if let Some(ref restrict) = self.restrict {
+            options.push(format!("RESTRICT = {}", restrict));
+        }
+        if let Some(ref join) = self.join {
+            options.push(format!("JOIN = {}", join));
+        }
+        if self.hashes {
+            options.push("HASHES".to_string());
+        }
+        if self.merges {
+            options.push("MERGES".to_string());
+        }
+
+        format!(
+            "\nDO $$ BEGIN CREATE OPERATOR {} ({}); \
+             EXCEPTION WHEN duplicate_function OR duplicate_object THEN NULL; END $$;",
+            self.symbol,
+            options.join(", ")
+        )
+    }
+}
+
+/// Checks the symbol is a valid operator name, panicking if not, see `CREATE OPERATOR`
+fn validate_symbol(symbol: String) -> String {
+    if let Err(err) = check_symbol(&symbol) {
+        panic!("invalid operator symbol {:?}, {}", symbol, err);
+    }
+
+    symbol
+}
+
+/// The rules of Postgres for operator names, which are otherwise rejected or lexed as something else
+fn check_symbol(symbol: &str) -> Result<(), String> {
+    if symbol.is_empty() {
+        return Err("it must not be empty".to_string());
+    }
+
+    if symbol.len() > MAX_SYMBOL_LEN {
+        return Err(format!("it may be at most {} characters", MAX_SYMBOL_LEN));
+    }
+
+    if !symbol.chars().all(|c| OPERATOR_CHARS.contains(c)) {
+        return Err(format!("it may only contain: {}", OPERATOR_CHARS));
+    }
+
+    // these start comments
+    if symbol.contains("--") || symbol.contains("/*") {
+        return Err("it must not contain -- or /*".to_string());
+    }
+
+    // e.g. `@-` is allowed, but `*-` would be lexed as `*` followed by a unary minus
+    if symbol.len() > 1
+        && (symbol.ends_with('+') || symbol.ends_with('-'))
+        && !symbol.chars().any(|c| SUFFIX_SIGN_CHARS.contains(c))
+    {
+        return Err(format!(
+            "it may only end in + or - if it contains one of: {}",
+            SUFFIX_SIGN_CHARS
+        ));
+    }
+
+    Ok(())
+}
+
+/// Removes any `#[pg_operator]` attributes from the function, returning the parsed options
+pub(crate) fn take_operator(attrs: &mut Vec<Attribute>) -> Option<Operator> {
+    let mut operator = None;
+
+    attrs.retain(|attr| {
+        if is_attribute(attr, "pg_operator") {
+            if operator.is_some() {
+                panic!("only one pg_operator attribute is allowed per function");
+            }
+
+            operator = Some(Operator::from_attribute(attr));
+            false
+        } else {
+            true
+        }
+    });
+
+    operator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_symbol() {
+        for symbol in &["+", "-", "<->", "&&", "@-", "~+", "|/", "!=", "*"] {
+            assert!(check_symbol(symbol).is_ok(), "{} should be valid", symbol);
+        }
+    }
+
+    #[test]
+    fn test_check_symbol_invalid_chars() {
+        assert!(check_symbol("").is_err());
+        assert!(check_symbol("a").is_err());
+        assert!(check_symbol("<$>").is_err());
+        assert!(check_symbol(&"<".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_check_symbol_comments() {
+        assert!(check_symbol("--").is_err());
+        assert!(check_symbol("<--").is_err());
+        assert!(check_symbol("/*").is_err());
+        assert!(check_symbol("@/*").is_err());
+    }
+
+    #[test]
+    fn test_check_symbol_sign_suffix() {
+        assert!(check_symbol("*-").is_err());
+        assert!(check_symbol("<+").is_err());
+        assert!(check_symbol("=+").is_err());
+        assert!(check_symbol("@+").is_ok());
+        assert!(check_symbol("#-").is_ok());
+    }
+}
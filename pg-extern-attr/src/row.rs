@@ -0,0 +1,144 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[derive(PgRow)]`, which reads a struct from the columns of a row
+
+use proc_macro2::{Span, TokenStream};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Fields, GenericParam, Lit, Meta, NestedMeta};
+
+/// The column a field is read from
+enum Column {
+    Name(String),
+    Number(usize),
+}
+
+impl Column {
+    /// Parse the column from the `#[pg_row(...)]` attribute of the field, if there is one
+    fn from_attrs(attrs: &[syn::Attribute]) -> Option<Self> {
+        let mut column = None;
+
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("pg_row")) {
+            let list = match attr.parse_meta() {
+                Ok(Meta::List(list)) => list,
+                _ => panic!("expected #[pg_row(name = \"column\")] or #[pg_row(position = 1)]"),
+            };
+
+            for nested in list.nested {
+                let name_value = match nested {
+                    NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+                    _ => panic!("unsupported pg_row option, expected `name = \"column\"` or `position = 1`"),
+                };
+
+                let option = name_value
+                    .path
+                    .get_ident()
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+
+                column = match (option.as_str(), name_value.lit) {
+                    ("name", Lit::Str(name)) => Some(Column::Name(name.value())),
+                    ("position", Lit::Int(position)) => {
+                        let position = position
+                            .base10_parse::<usize>()
+                            .expect("pg_row position must be an integer");
+                        if position == 0 {
+                            panic!("pg_row positions start at 1");
+                        }
+
+                        Some(Column::Number(position))
+                    }
+                    ("name", _) => panic!("pg_row name must be a string literal"),
+                    ("position", _) => panic!("pg_row position must be an integer literal"),
+                    _ => panic!("unknown pg_row option: {}", option),
+                };
+            }
+        }
+
+        column
+    }
+
+    /// Returns the expression reading the column from `columns`
+    fn get_column(&self) -> TokenStream {
+        match self {
+            Column::Name(name) => quote!( columns.get(#name)? ),
+            Column::Number(number) => quote!( columns.get_by_number(#number)? ),
+        }
+    }
+}
+
+/// Returns the lifetime of the struct, the memory context the values are allocated in, or `'mc` if the struct
+///  has none
+fn memory_context_lifetime(ast: &DeriveInput) -> (syn::Lifetime, bool) {
+    let mut lifetime = None;
+
+    for param in &ast.generics.params {
+        match param {
+            GenericParam::Lifetime(param) if lifetime.is_none() => lifetime = Some(param.lifetime.clone()),
+            _ => panic!("PgRow can only be derived for structs with at most one lifetime, and no type parameters"),
+        }
+    }
+
+    match lifetime {
+        Some(lifetime) => (lifetime, true),
+        None => (syn::Lifetime::new("'mc", Span::call_site()), false),
+    }
+}
+
+pub(crate) fn impl_pg_row(ast: &DeriveInput) -> TokenStream {
+    let struct_name = &ast.ident;
+
+    let data = match ast.data {
+        Data::Struct(ref data) => data,
+        _ => panic!("PgRow can only be derived for structs"),
+    };
+
+    let body = match data.fields {
+        Fields::Named(ref fields) => {
+            let fields = fields.named.iter().map(|field| {
+                let field_name = field.ident.as_ref().expect("named fields have names");
+                let column = Column::from_attrs(&field.attrs)
+                    .unwrap_or_else(|| Column::Name(field_name.to_string()))
+                    .get_column();
+
+                quote_spanned!( field.span()=> #field_name: #column )
+            });
+
+            quote!( #struct_name { #(#fields,)* } )
+        }
+        Fields::Unnamed(ref fields) => {
+            let fields = fields.unnamed.iter().enumerate().map(|(index, field)| {
+                let column = Column::from_attrs(&field.attrs)
+                    .unwrap_or(Column::Number(index + 1))
+                    .get_column();
+
+                quote_spanned!( field.span()=> #column )
+            });
+
+            quote!( #struct_name ( #(#fields,)* ) )
+        }
+        Fields::Unit => panic!("PgRow can not be derived for unit structs"),
+    };
+
+    let (lifetime, has_lifetime) = memory_context_lifetime(ast);
+    let struct_type = if has_lifetime {
+        quote!( #struct_name<#lifetime> )
+    } else {
+        quote!(#struct_name)
+    };
+
+    quote! {
+        impl<#lifetime> pg_extend::row::PgRow<#lifetime> for #struct_type {
+            fn from_columns<C>(columns: &C) -> Result<Self, pg_extend::pg_error::PgError>
+            where
+                C: pg_extend::row::Columns<#lifetime>,
+            {
+                Ok(#body)
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2020 Benjamin Fry <benjaminfry@me.com>
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for `#[pg_trigger]`, which exports a Rust function as a Postgres trigger function
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+
+pub(crate) fn impl_info_for_trigger(item: &syn::Item) -> TokenStream {
+    let func = if let syn::Item::Fn(func) = item {
+        &func.sig
+    } else {
+        panic!("pg_trigger only supported on functions");
+    };
+
+    let func_name = &func.ident;
+
+    if func.inputs.len() != 1 {
+        panic!(
+            "pg_trigger functions must take only the trigger, e.g. fn {}<'mc>(trigger: &Trigger<'mc>)",
+            func_name
+        );
+    }
+
+    let mut expanded = item.clone().into_token_stream();
+
+    let func_wrapper_name = syn::Ident::new(&format!("pg_{}", func_name), Span::call_site());
+    expanded.extend(crate::get_info_fn(&func_wrapper_name));
+
+    // the trigger data is validated and the result converted by call_trigger
+    expanded.extend(quote!(
+        #[no_mangle]
+        pub extern "C" fn #func_wrapper_name (func_call_info: pg_extend::pg_sys::FunctionCallInfo) -> pg_extend::pg_sys::Datum {
+            unsafe { pg_extend::trigger::call_trigger(stringify!(#func_name), func_call_info, #func_name) }
+        }
+    ));
+
+    let create_sql_name =
+        syn::Ident::new(&format!("{}_pg_create_stmt", func_name), Span::call_site());
+
+    let sql_stmt = format!(
+        "CREATE or REPLACE FUNCTION {}() RETURNS trigger AS '{{library_path}}', '{}' LANGUAGE C;",
+        func_name, func_wrapper_name,
+    );
+
+    // declare a function that can be used to output a create statement for the trigger function
+    expanded.extend(quote!(
+        #[allow(unused)]
+        pub fn #create_sql_name(library_path: &str) -> String {
+            format!(
+                #sql_stmt,
+                library_path = library_path
+            )
+        }
+    ));
+
+    expanded
+}